@@ -0,0 +1,627 @@
+extern crate byteorder;
+extern crate flate2;
+extern crate walkdir;
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, CrcReader, CrcWriter};
+use walkdir::WalkDir;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// An archive looks like this:
+//   "RSARCHV1"
+//   the deflate compressed content of every file, one after another
+//   the index, describing every entry
+//   the position of the index (u64) and the number of entries (u32)
+//   "RSARCEND"
+// Because the index sits at the end, we can find any single
+// file by reading the trailer and the index, without
+// scanning through the compressed data
+const MAGIC: &[u8; 8] = b"RSARCHV1";
+const END_MAGIC: &[u8; 8] = b"RSARCEND";
+const TRAILER_LEN: u64 = 8 + 4 + 8;
+// Paths longer than this are most likely a corrupt index
+const MAX_PATH_LEN: u32 = 64 * 1024;
+
+const USAGE: &str = "Usage:
+    archive create <archive> <directory>
+    archive list <archive>
+    archive extract <archive> <destination> [path...]
+    archive verify <archive>";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["create", archive, directory] => create_archive(Path::new(directory), Path::new(archive))
+            .map(|count| println!("Archived {} entries", count)),
+        ["list", archive] => list_archive(Path::new(archive)),
+        ["extract", archive, destination, ref paths @ ..] => {
+            extract_archive(Path::new(archive), Path::new(destination), paths)
+        }
+        ["verify", archive] => open_archive(Path::new(archive))
+            .and_then(|mut reader| reader.verify())
+            .map(|()| println!("All entries are intact")),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EntryKind {
+    File,
+    Directory,
+}
+
+// Everything the index knows about a single entry
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    // Relative path, always separated by '/'
+    path: String,
+    kind: EntryKind,
+    // Unix permission bits
+    mode: u32,
+    // Seconds since the UNIX epoch
+    mtime: u64,
+    size: u64,
+    compressed_size: u64,
+    offset: u64,
+    // CRC32 of the uncompressed content
+    crc: u32,
+}
+
+impl Entry {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u32::<LE>(self.path.len() as u32)?;
+        writer.write_all(self.path.as_bytes())?;
+        writer.write_u8(match self.kind {
+            EntryKind::File => 0,
+            EntryKind::Directory => 1,
+        })?;
+        writer.write_u32::<LE>(self.mode)?;
+        writer.write_u64::<LE>(self.mtime)?;
+        writer.write_u64::<LE>(self.size)?;
+        writer.write_u64::<LE>(self.compressed_size)?;
+        writer.write_u64::<LE>(self.offset)?;
+        writer.write_u32::<LE>(self.crc)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Entry> {
+        let path_len = reader.read_u32::<LE>()?;
+        if path_len > MAX_PATH_LEN {
+            return Err(invalid_data(format!(
+                "Path length {} is too long",
+                path_len
+            )));
+        }
+        let mut path = vec![0; path_len as usize];
+        reader.read_exact(&mut path)?;
+        let path = String::from_utf8(path).map_err(|_| invalid_data("Path is not UTF-8"))?;
+        let kind = match reader.read_u8()? {
+            0 => EntryKind::File,
+            1 => EntryKind::Directory,
+            kind => return Err(invalid_data(format!("Unknown entry kind {}", kind))),
+        };
+        Ok(Entry {
+            path,
+            kind,
+            mode: reader.read_u32::<LE>()?,
+            mtime: reader.read_u64::<LE>()?,
+            size: reader.read_u64::<LE>()?,
+            compressed_size: reader.read_u64::<LE>()?,
+            offset: reader.read_u64::<LE>()?,
+            crc: reader.read_u32::<LE>()?,
+        })
+    }
+}
+
+// Writes an archive entry by entry
+struct ArchiveWriter<W: Write> {
+    writer: W,
+    position: u64,
+    entries: Vec<Entry>,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        Ok(ArchiveWriter {
+            writer,
+            position: MAGIC.len() as u64,
+            entries: Vec::new(),
+        })
+    }
+
+    // Compress the content of reader on its own,
+    // so it can later be extracted independently
+    fn add_file<R: Read>(
+        &mut self,
+        path: &str,
+        reader: R,
+        mode: u32,
+        mtime: u64,
+    ) -> io::Result<()> {
+        let offset = self.position;
+        let mut reader = CrcReader::new(reader);
+        let mut encoder = DeflateEncoder::new(&mut self.writer, Compression::default());
+        // The CRC only counts the length in 32 bits, which
+        // isn't enough for files of 4 GiB and more
+        let size = io::copy(&mut reader, &mut encoder)?;
+        encoder.try_finish()?;
+        let compressed_size = encoder.total_out();
+        encoder.finish()?;
+        self.position += compressed_size;
+
+        self.entries.push(Entry {
+            path: path.to_string(),
+            kind: EntryKind::File,
+            mode,
+            mtime,
+            size,
+            compressed_size,
+            offset,
+            crc: reader.crc().sum(),
+        });
+        Ok(())
+    }
+
+    fn add_directory(&mut self, path: &str, mode: u32, mtime: u64) {
+        self.entries.push(Entry {
+            path: path.to_string(),
+            kind: EntryKind::Directory,
+            mode,
+            mtime,
+            size: 0,
+            compressed_size: 0,
+            offset: self.position,
+            crc: 0,
+        });
+    }
+
+    // Write the index and the trailer
+    fn finish(mut self) -> io::Result<W> {
+        let index_offset = self.position;
+        for entry in &self.entries {
+            entry.write_to(&mut self.writer)?;
+        }
+        self.writer.write_u64::<LE>(index_offset)?;
+        self.writer.write_u32::<LE>(self.entries.len() as u32)?;
+        self.writer.write_all(END_MAGIC)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// Reads single entries from an archive by using its index
+struct ArchiveReader<R: Read + Seek> {
+    reader: R,
+    entries: Vec<Entry>,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not an archive"));
+        }
+
+        // Read the trailer to find the index
+        let len = reader.seek(SeekFrom::End(0))?;
+        if len < MAGIC.len() as u64 + TRAILER_LEN {
+            return Err(invalid_data("Archive is truncated"));
+        }
+        reader.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+        let index_offset = reader.read_u64::<LE>()?;
+        let count = reader.read_u32::<LE>()?;
+        reader.read_exact(&mut magic)?;
+        if &magic != END_MAGIC {
+            return Err(invalid_data("Archive is truncated"));
+        }
+        let index_end = len - TRAILER_LEN;
+        if index_offset < MAGIC.len() as u64 || index_offset > index_end {
+            return Err(invalid_data("Index offset is out of bounds"));
+        }
+
+        reader.seek(SeekFrom::Start(index_offset))?;
+        let mut index = BufReader::new((&mut reader).take(index_end - index_offset));
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let entry = Entry::read_from(&mut index)?;
+            let end = entry.offset.checked_add(entry.compressed_size);
+            if end.is_none_or(|end| end > index_offset) {
+                return Err(invalid_data(format!("{} is out of bounds", entry.path)));
+            }
+            entries.push(entry);
+        }
+        drop(index);
+        Ok(ArchiveReader { reader, entries })
+    }
+
+    fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    fn find(&self, path: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.path == path)
+    }
+
+    // Decompress a single entry, checking its size and checksum
+    fn read_entry<W: Write>(&mut self, entry: &Entry, writer: W) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        let compressed = (&mut self.reader).take(entry.compressed_size);
+        let mut decoder = DeflateDecoder::new(compressed);
+        let mut writer = CrcWriter::new(writer);
+        let size = io::copy(&mut decoder, &mut writer)?;
+        writer.flush()?;
+
+        if size != entry.size || writer.crc().sum() != entry.crc {
+            return Err(invalid_data(format!("{} is corrupt", entry.path)));
+        }
+        Ok(())
+    }
+
+    // Check every file without writing anything to disk
+    fn verify(&mut self) -> io::Result<()> {
+        let entries = self.entries.clone();
+        for entry in entries.iter().filter(|e| e.kind == EntryKind::File) {
+            self.read_entry(entry, io::sink())?;
+        }
+        Ok(())
+    }
+
+    fn extract(&mut self, entry: &Entry, destination: &Path) -> io::Result<()> {
+        let target = destination.join(safe_relative_path(&entry.path)?);
+        match entry.kind {
+            EntryKind::Directory => fs::create_dir_all(&target)?,
+            EntryKind::File => {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let mut writer = BufWriter::new(File::create(&target)?);
+                self.read_entry(entry, &mut writer)?;
+                writer.flush()?;
+                restore_metadata(&target, entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn extract_all(&mut self, destination: &Path) -> io::Result<()> {
+        let entries = self.entries.clone();
+        self.extract_entries(&entries, destination)
+    }
+
+    fn extract_entries(&mut self, entries: &[Entry], destination: &Path) -> io::Result<()> {
+        // Check every path before writing anything
+        for entry in entries {
+            safe_relative_path(&entry.path)?;
+        }
+        for entry in entries {
+            self.extract(entry, destination)?;
+        }
+        // Adding files changes the mtime of a directory,
+        // so directories are restored last, deepest first
+        let mut directories: Vec<_> = entries
+            .iter()
+            .filter(|e| e.kind == EntryKind::Directory)
+            .collect();
+        directories.sort_by(|a, b| b.path.cmp(&a.path));
+        for entry in directories {
+            restore_metadata(&destination.join(safe_relative_path(&entry.path)?), entry)?;
+        }
+        Ok(())
+    }
+}
+
+// Turn a path from the archive into one we can safely join
+// onto the destination. Anything that could escape the destination,
+// like "/etc/passwd" or "../../foo", is refused
+fn safe_relative_path(path: &str) -> io::Result<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(invalid_data(format!(
+                    "Refusing to extract unsafe path {}",
+                    path
+                )))
+            }
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        return Err(invalid_data(format!(
+            "Refusing to extract empty path '{}'",
+            path
+        )));
+    }
+    Ok(relative)
+}
+
+fn create_archive(source: &Path, output: &Path) -> io::Result<usize> {
+    let file = File::create(output)?;
+    let mut archive = ArchiveWriter::new(BufWriter::new(file))?;
+    let output = output.canonicalize()?;
+
+    for entry in WalkDir::new(source)
+        .min_depth(1)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+    {
+        let entry = entry.map_err(io::Error::from)?;
+        // Don't put the archive into itself
+        if entry.path().canonicalize().ok().as_ref() == Some(&output) {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(source)
+            .expect("WalkDir only yields paths inside the source");
+        let path = archive_path(relative)?;
+        let metadata = entry.metadata().map_err(io::Error::from)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        if metadata.is_dir() {
+            archive.add_directory(&path, mode_of(&metadata), mtime);
+        } else if metadata.is_file() {
+            let reader = BufReader::new(File::open(entry.path())?);
+            archive.add_file(&path, reader, mode_of(&metadata), mtime)?;
+        } else {
+            eprintln!("Skipping {}, as it is neither a file nor a directory", path);
+        }
+    }
+    let count = archive.entries.len();
+    archive.finish()?;
+    Ok(count)
+}
+
+fn list_archive(path: &Path) -> io::Result<()> {
+    let archive = open_archive(path)?;
+    for entry in archive.entries() {
+        match entry.kind {
+            EntryKind::Directory => println!("{:o} {:>10} {}/", entry.mode, "-", entry.path),
+            EntryKind::File => println!(
+                "{:o} {:>10} {} ({} bytes compressed)",
+                entry.mode, entry.size, entry.path, entry.compressed_size
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn extract_archive(path: &Path, destination: &Path, paths: &[&str]) -> io::Result<()> {
+    let mut archive = open_archive(path)?;
+    if paths.is_empty() {
+        return archive.extract_all(destination);
+    }
+    let entries = paths
+        .iter()
+        .map(|path| {
+            archive.find(path).cloned().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not in the archive", path),
+                )
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    archive.extract_entries(&entries, destination)
+}
+
+fn open_archive(path: &Path) -> io::Result<ArchiveReader<BufReader<File>>> {
+    ArchiveReader::new(BufReader::new(File::open(path)?))
+}
+
+// Store paths the same way on every platform
+fn archive_path(relative: &Path) -> io::Result<String> {
+    let parts: Option<Vec<&str>> = relative
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect();
+    parts
+        .map(|parts| parts.join("/"))
+        .ok_or_else(|| invalid_data(format!("{} is not valid UTF-8", relative.display())))
+}
+
+fn restore_metadata(path: &Path, entry: &Entry) -> io::Result<()> {
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(entry.mtime);
+    File::open(path)?.set_modified(mtime)?;
+    set_mode(path, entry.mode)
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    // Only the readonly flag is available, so pretend to be unix
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+// Archives can come from anywhere, so setuid,
+// setgid and sticky bits are never restored
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Creates <temp>/archive-<name>-<pid>/source with a few files
+    // and directories and returns the directory containing it
+    fn sample_tree(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("archive-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let dir = root.join("source");
+        fs::create_dir_all(dir.join("src/bin")).unwrap();
+        fs::create_dir_all(dir.join("empty")).unwrap();
+        fs::write(dir.join("Cargo.toml"), "[package]\nname = \"ferris\"\n").unwrap();
+        fs::write(dir.join("src/bin/main.rs"), "fn main() {}\n".repeat(1000)).unwrap();
+        fs::write(dir.join("src/empty.rs"), "").unwrap();
+        set_mode(&dir.join("Cargo.toml"), 0o600).unwrap();
+        set_mode(&dir.join("empty"), 0o700).unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        File::open(dir.join("src/empty.rs"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        root
+    }
+
+    #[test]
+    fn round_trips_a_directory() {
+        let root = sample_tree("round-trip");
+        let (source, archive) = (root.join("source"), root.join("tree.rsar"));
+        assert_eq!(6, create_archive(&source, &archive).unwrap());
+
+        let mut reader = open_archive(&archive).unwrap();
+        let paths: Vec<_> = reader.entries().iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            vec![
+                "Cargo.toml",
+                "empty",
+                "src",
+                "src/bin",
+                "src/bin/main.rs",
+                "src/empty.rs"
+            ],
+            paths
+        );
+        assert_eq!(13_000, reader.find("src/bin/main.rs").unwrap().size);
+        reader.verify().unwrap();
+
+        let destination = root.join("destination");
+        reader.extract_all(&destination).unwrap();
+        for file in &["Cargo.toml", "src/bin/main.rs", "src/empty.rs"] {
+            assert_eq!(
+                fs::read(source.join(file)).unwrap(),
+                fs::read(destination.join(file)).unwrap()
+            );
+        }
+        assert!(destination.join("empty").is_dir());
+
+        let metadata = fs::metadata(destination.join("Cargo.toml")).unwrap();
+        assert_eq!(0o600, mode_of(&metadata) & 0o777);
+        let mtime = fs::metadata(destination.join("src/empty.rs"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(1_500_000_000), mtime);
+    }
+
+    #[test]
+    fn extracts_single_entries() {
+        let root = sample_tree("single");
+        let archive = root.join("tree.rsar");
+        create_archive(&root.join("source"), &archive).unwrap();
+
+        let destination = root.join("destination");
+        extract_archive(&archive, &destination, &["src/bin/main.rs", "empty"]).unwrap();
+        assert!(destination.join("src/bin/main.rs").is_file());
+        assert!(!destination.join("Cargo.toml").exists());
+        // Directories get their metadata back, just like with extract_all
+        let metadata = fs::metadata(destination.join("empty")).unwrap();
+        assert_eq!(0o700, mode_of(&metadata) & 0o777);
+        assert!(extract_archive(&archive, &destination, &["missing.rs"]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn drops_special_permission_bits() {
+        let mut archive = ArchiveWriter::new(Vec::new()).unwrap();
+        archive
+            .add_file("setuid.sh", &b"#!/bin/sh"[..], 0o4755, 0)
+            .unwrap();
+        let bytes = archive.finish().unwrap();
+
+        let destination = env::temp_dir().join(format!("archive-setuid-{}", process::id()));
+        let mut reader = ArchiveReader::new(Cursor::new(bytes)).unwrap();
+        reader.extract_all(&destination).unwrap();
+        let metadata = fs::metadata(destination.join("setuid.sh")).unwrap();
+        assert_eq!(0o755, mode_of(&metadata));
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let mut archive = ArchiveWriter::new(Vec::new()).unwrap();
+        archive
+            .add_file("a.txt", &b"Hello World! Hello World!"[..], 0o644, 0)
+            .unwrap();
+        let mut bytes = archive.finish().unwrap();
+        ArchiveReader::new(Cursor::new(bytes.clone()))
+            .unwrap()
+            .verify()
+            .unwrap();
+
+        // Flip a bit in the compressed data
+        bytes[MAGIC.len() + 3] ^= 0x01;
+        let mut reader = ArchiveReader::new(Cursor::new(bytes.clone())).unwrap();
+        assert!(reader.verify().is_err());
+
+        // Cut off the trailer
+        let len = bytes.len();
+        assert!(ArchiveReader::new(Cursor::new(&bytes[..len - 1])).is_err());
+    }
+
+    #[test]
+    fn refuses_unsafe_paths() {
+        for path in &["../evil.txt", "/etc/evil.txt", "a/../../evil.txt", "", "."] {
+            let mut archive = ArchiveWriter::new(Vec::new()).unwrap();
+            archive
+                .add_file("harmless.txt", &b"ok"[..], 0o644, 0)
+                .unwrap();
+            archive.add_file(path, &b"evil"[..], 0o644, 0).unwrap();
+            let bytes = archive.finish().unwrap();
+
+            // The destination is never even created
+            let destination = env::temp_dir().join(format!("archive-unsafe-{}", process::id()));
+            let mut reader = ArchiveReader::new(Cursor::new(bytes)).unwrap();
+            assert!(reader.extract_all(&destination).is_err(), "{}", path);
+            // Nothing is written if a single path is unsafe
+            assert!(!destination.join("harmless.txt").exists());
+        }
+        assert_eq!(PathBuf::from("a/b"), safe_relative_path("./a/b").unwrap());
+    }
+}
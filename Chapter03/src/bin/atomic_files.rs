@@ -0,0 +1,355 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn main() {
+    let path = "./config.toml";
+    println!("Atomically writing '{}'", path);
+    write_atomic(path, b"[person]\nname = \"Ferris\"\n").expect("Failed to write file");
+    print_file(path);
+
+    // Readers will either see the old or the new content,
+    // never something in between
+    println!("Appending a line to '{}'", path);
+    append_or_replace(path, "age =", "age = 7").expect("Failed to update file");
+    print_file(path);
+
+    println!("Replacing a line in '{}'", path);
+    append_or_replace(path, "age =", "age = 8").expect("Failed to update file");
+    print_file(path);
+
+    // Anything that was not committed is thrown away
+    {
+        let mut file = AtomicFile::create(path).expect("Failed to create file");
+        file.write_all(b"This will never be visible")
+            .expect("Failed to write file");
+        println!("Dropping an uncommitted write");
+    }
+    print_file(path);
+
+    fs::remove_file(path).expect("Failed to remove file");
+}
+
+fn print_file(path: &str) {
+    let content = fs::read_to_string(path).expect("Failed to read file");
+    println!("The file '{}' now contains:\n{}", path, content);
+}
+
+// A file that only replaces its target when commit() is called.
+// Until then, all writes go into a temporary file next to it
+struct AtomicFile {
+    target: PathBuf,
+    temp_path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    // Until then, dropping removes the temporary file
+    committed: bool,
+}
+
+impl AtomicFile {
+    fn create<P: AsRef<Path>>(path: P) -> io::Result<AtomicFile> {
+        let target = resolve_symlinks(path.as_ref())?;
+
+        // A rename is only atomic within the same filesystem,
+        // so the temporary file has to be in the same directory
+        let temp_path = temp_path_for(&target)?;
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+
+        // Keep the permissions of the file we are replacing
+        if let Ok(metadata) = fs::metadata(&target) {
+            if let Err(e) = file.set_permissions(metadata.permissions()) {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e);
+            }
+        }
+
+        Ok(AtomicFile {
+            target,
+            temp_path,
+            writer: Some(BufWriter::new(file)),
+            committed: false,
+        })
+    }
+
+    // Make the written content visible under the target path
+    fn commit(mut self) -> io::Result<()> {
+        let writer = self
+            .writer
+            .take()
+            .expect("AtomicFile was already committed");
+        // into_inner() flushes the buffer and, unlike dropping it,
+        // tells us if that failed
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        // Make sure the content is on disk before it becomes visible,
+        // otherwise a crash could leave us with an empty file
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&self.temp_path, &self.target)?;
+        self.committed = true;
+        // The rename itself is only durable once
+        // the directory has been synced as well
+        sync_directory(parent_dir(&self.target))
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer
+            .as_mut()
+            .expect("AtomicFile was already committed")
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer
+            .as_mut()
+            .expect("AtomicFile was already committed")
+            .flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // If we were not committed, or committing failed
+        // halfway through, clean up after ourselves
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+// If path is a symlink, we have to replace the file it points to instead
+// of the link itself. Unlike fs::canonicalize, this also works for
+// links to files that don't exist yet
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    // Give up on loops after as many links as Linux follows
+    for _ in 0..40 {
+        match fs::symlink_metadata(&path) {
+            Ok(ref metadata) if metadata.file_type().is_symlink() => {
+                // Relative links are relative to the directory of the link
+                path = parent_dir(&path).join(fs::read_link(&path)?);
+            }
+            Ok(_) => return Ok(path),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(path),
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Too many levels of symbolic links in {}", path.display()),
+    ))
+}
+
+fn temp_path_for(target: &Path) -> io::Result<PathBuf> {
+    // Make the name unique per process and per call
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let name = target.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file name", target.display()),
+        )
+    })?;
+    let temp_name = format!(
+        ".{}.tmp.{}.{}",
+        name.to_string_lossy(),
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    );
+    Ok(target.with_file_name(temp_name))
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+#[cfg(unix)]
+fn sync_directory(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_dir: &Path) -> io::Result<()> {
+    // Directories cannot be opened like files on every platform
+    Ok(())
+}
+
+// Replace the content of a file in a crash-safe way
+fn write_atomic<P: AsRef<Path>>(path: P, content: &[u8]) -> io::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(content)?;
+    file.commit()
+}
+
+// Read a small file, change it and write it back atomically.
+// The closure receives None if the file doesn't exist yet
+fn update_atomic<P, F>(path: P, update: F) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(Option<String>) -> String,
+{
+    let old = match fs::read_to_string(path.as_ref()) {
+        Ok(content) => Some(content),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    write_atomic(path, update(old).as_bytes())
+}
+
+// Replace the first line starting with prefix or append the line,
+// which is handy for simple config files
+fn append_or_replace<P: AsRef<Path>>(path: P, prefix: &str, line: &str) -> io::Result<()> {
+    update_atomic(path, |old| {
+        let old = old.unwrap_or_default();
+        let mut replaced = false;
+        let mut content = String::with_capacity(old.len() + line.len() + 1);
+        for old_line in old.lines() {
+            if !replaced && old_line.starts_with(prefix) {
+                content.push_str(line);
+                replaced = true;
+            } else {
+                content.push_str(old_line);
+            }
+            content.push('\n');
+        }
+        if !replaced {
+            content.push_str(line);
+            content.push('\n');
+        }
+        content
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("atomic-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn files_in(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn creates_and_replaces_files() {
+        let dir = temp_dir("replace");
+        let path = dir.join("config.toml");
+        write_atomic(&path, b"first").unwrap();
+        assert_eq!("first", fs::read_to_string(&path).unwrap());
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!("second", fs::read_to_string(&path).unwrap());
+        // No temporary files are left behind
+        assert_eq!(vec!["config.toml"], files_in(&dir));
+    }
+
+    #[test]
+    fn keeps_old_content_until_commit() {
+        let dir = temp_dir("uncommitted");
+        let path = dir.join("config.toml");
+        write_atomic(&path, b"old").unwrap();
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"new").unwrap();
+        file.flush().unwrap();
+        assert_eq!("old", fs::read_to_string(&path).unwrap());
+        drop(file);
+
+        assert_eq!("old", fs::read_to_string(&path).unwrap());
+        assert_eq!(vec!["config.toml"], files_in(&dir));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn preserves_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = env::temp_dir().join(format!("atomic-secret-{}.toml", process::id()));
+        write_atomic(&path, b"old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replaces_symlink_targets() {
+        use std::os::unix::fs::symlink;
+        let dir = temp_dir("symlink");
+        let target = dir.join("real.toml");
+        let link = dir.join("link.toml");
+        write_atomic(&target, b"old").unwrap();
+        symlink(&target, &link).unwrap();
+
+        write_atomic(&link, b"new").unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!("new", fs::read_to_string(&target).unwrap());
+
+        // A link to a file that doesn't exist yet creates that file
+        let dangling = dir.join("dangling.toml");
+        symlink("created.toml", &dangling).unwrap();
+        write_atomic(&dangling, b"created").unwrap();
+        assert!(fs::symlink_metadata(&dangling)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            "created",
+            fs::read_to_string(dir.join("created.toml")).unwrap()
+        );
+    }
+
+    #[test]
+    fn cleans_up_when_commit_fails() {
+        let dir = temp_dir("failed-commit");
+        // A file can't be renamed onto a directory that isn't empty
+        let path = dir.join("config.toml");
+        fs::create_dir_all(path.join("in-the-way")).unwrap();
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"content").unwrap();
+        assert!(file.commit().is_err());
+        assert_eq!(vec!["config.toml"], files_in(&dir));
+    }
+
+    #[test]
+    fn appends_or_replaces_lines() {
+        let path = env::temp_dir().join(format!("atomic-append-{}.toml", process::id()));
+        let _ = fs::remove_file(&path);
+        append_or_replace(&path, "name =", "name = \"Ferris\"").unwrap();
+        append_or_replace(&path, "age =", "age = 7").unwrap();
+        append_or_replace(&path, "name =", "name = \"Corro\"").unwrap();
+        assert_eq!(
+            "name = \"Corro\"\nage = 7\n",
+            fs::read_to_string(&path).unwrap()
+        );
+    }
+
+    #[test]
+    fn fails_for_missing_directory() {
+        let path = env::temp_dir().join(format!("atomic-missing-{}/exist.toml", process::id()));
+        assert!(write_atomic(&path, b"content").is_err());
+    }
+}
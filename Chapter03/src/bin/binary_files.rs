@@ -1,7 +1,9 @@
 extern crate byteorder;
-use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt, BE, LE};
+use byteorder::{ByteOrder, WriteBytesExt, BE, LE};
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter, Read};
+use std::marker::PhantomData;
 use std::io::prelude::*;
 
 
@@ -71,28 +73,228 @@ fn read_protocol(path: &str) -> io::Result<Vec<u32>> {
 fn read_protocoll_payload<E, R>(reader: &mut R) -> io::Result<Vec<u32>>
 where
     E: ByteOrder,
-    R: ReadBytesExt,
+    R: Read,
 {
-    let mut payload = Vec::new();
-    const SIZE_OF_U32: usize = 4;
-    loop {
-        let mut raw_payload = [0; SIZE_OF_U32];
-        // Read the next 4 bytes
-        match reader.read(&mut raw_payload)? {
-            // Zero means we reached the end
-            0 => return Ok(payload),
-            // SIZE_OF_U32 means we read a complete number
-            SIZE_OF_U32 => {
-                let as_u32 = raw_payload.as_ref().read_u32::<E>()?;
-                payload.push(as_u32)
+    // A single read() is allowed to return fewer bytes than requested,
+    // so we let a decoder reassemble the numbers across reads
+    PayloadReader::<E, _>::new(reader).collect()
+}
+
+// An incremental decoder that accepts bytes in chunks of any size
+// and hands out complete u32 values as soon as they are available
+struct U32Decoder<E: ByteOrder> {
+    pending: [u8; 4],
+    pending_len: usize,
+    decoded: VecDeque<u32>,
+    endianness: PhantomData<E>,
+}
+
+impl<E: ByteOrder> U32Decoder<E> {
+    fn new() -> Self {
+        U32Decoder {
+            pending: [0; 4],
+            pending_len: 0,
+            decoded: VecDeque::new(),
+            endianness: PhantomData,
+        }
+    }
+
+    // Feed an arbitrary amount of bytes into the decoder
+    fn feed(&mut self, mut bytes: &[u8]) {
+        // First complete a number that was split by the last chunk
+        if self.pending_len > 0 {
+            let missing = (self.pending.len() - self.pending_len).min(bytes.len());
+            self.pending[self.pending_len..self.pending_len + missing]
+                .copy_from_slice(&bytes[..missing]);
+            self.pending_len += missing;
+            bytes = &bytes[missing..];
+            if self.pending_len < self.pending.len() {
+                return;
+            }
+            self.decoded.push_back(E::read_u32(&self.pending));
+            self.pending_len = 0;
+        }
+
+        // Then decode all numbers that are completely contained in the chunk
+        let mut chunks = bytes.chunks(self.pending.len());
+        for chunk in &mut chunks {
+            if chunk.len() == self.pending.len() {
+                self.decoded.push_back(E::read_u32(chunk));
+            } else {
+                // Remember the rest for the next call
+                self.pending[..chunk.len()].copy_from_slice(chunk);
+                self.pending_len = chunk.len();
+            }
+        }
+    }
+
+    // Take the next complete number, if there is one
+    fn next_value(&mut self) -> Option<u32> {
+        self.decoded.pop_front()
+    }
+
+    // Call this when there are no more bytes to come.
+    // It fails if the input stopped in the middle of a number
+    fn finish(&self) -> io::Result<()> {
+        if self.pending_len == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "Payload ended unexpectedly: the last value is missing {} of {} bytes",
+                    self.pending.len() - self.pending_len,
+                    self.pending.len()
+                ),
+            ))
+        }
+    }
+}
+
+// Iterates over the u32 values in a reader,
+// no matter how many bytes each single read() returns
+struct PayloadReader<E: ByteOrder, R: Read> {
+    reader: R,
+    decoder: U32Decoder<E>,
+    buffer: [u8; 512],
+    done: bool,
+}
+
+impl<E: ByteOrder, R: Read> PayloadReader<E, R> {
+    fn new(reader: R) -> Self {
+        PayloadReader {
+            reader,
+            decoder: U32Decoder::new(),
+            buffer: [0; 512],
+            done: false,
+        }
+    }
+}
+
+impl<E: ByteOrder, R: Read> Iterator for PayloadReader<E, R> {
+    type Item = io::Result<u32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(value) = self.decoder.next_value() {
+                return Some(Ok(value));
+            }
+            if self.done {
+                return None;
             }
-            // Anything else means the last element was not
-            // a valid u32
-            _ => {
-                return Err(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    "Payload ended unexpectedly",
-                ))
+            match self.reader.read(&mut self.buffer) {
+                // Zero means we reached the end.
+                // This is only clean if no number was cut off
+                Ok(0) => {
+                    self.done = true;
+                    return self.decoder.finish().err().map(Err);
+                }
+                Ok(len) => self.decoder.feed(&self.buffer[..len]),
+                // Interrupted reads can simply be retried
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A reader that returns its data in the given chunk sizes
+    // and everything that is left afterwards in one go,
+    // just like a pipe or a socket might
+    struct ChunkedReader<'a> {
+        data: &'a [u8],
+        chunk_sizes: Vec<usize>,
+    }
+
+    impl<'a> Read for ChunkedReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let wanted = if self.chunk_sizes.is_empty() {
+                self.data.len()
+            } else {
+                self.chunk_sizes.remove(0)
+            };
+            let len = wanted.min(buf.len()).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    fn payload() -> (Vec<u32>, Vec<u8>) {
+        let values = vec![0xDEAD, 0xBEEF, 0, u32::MAX, 0x0102_0304];
+        let mut bytes = Vec::new();
+        for value in &values {
+            bytes.write_u32::<LE>(*value).unwrap();
+        }
+        (values, bytes)
+    }
+
+    #[test]
+    fn reads_payload_split_at_every_boundary() {
+        let (values, bytes) = payload();
+        for split in 1..bytes.len() {
+            let mut reader = ChunkedReader {
+                data: &bytes,
+                chunk_sizes: vec![split],
+            };
+            let read = read_protocoll_payload::<LE, _>(&mut reader).unwrap();
+            assert_eq!(values, read, "split at byte {}", split);
+        }
+    }
+
+    #[test]
+    fn reads_payload_in_any_chunk_size() {
+        let (values, bytes) = payload();
+        for chunk_size in 1..bytes.len() + 1 {
+            let mut reader = ChunkedReader {
+                data: &bytes,
+                chunk_sizes: vec![chunk_size; bytes.len()],
+            };
+            let read = read_protocoll_payload::<LE, _>(&mut reader).unwrap();
+            assert_eq!(values, read, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn decodes_big_endian() {
+        let mut decoder = U32Decoder::<BE>::new();
+        decoder.feed(&[0x00, 0x00]);
+        assert_eq!(None, decoder.next_value());
+        decoder.feed(&[0xDE, 0xAD, 0x00]);
+        assert_eq!(Some(0xDEAD), decoder.next_value());
+        assert_eq!(None, decoder.next_value());
+        assert!(decoder.finish().is_err());
+    }
+
+    #[test]
+    fn accepts_empty_payload() {
+        let mut reader = ChunkedReader {
+            data: &[],
+            chunk_sizes: Vec::new(),
+        };
+        let read = read_protocoll_payload::<LE, _>(&mut reader).unwrap();
+        assert!(read.is_empty());
+    }
+
+    #[test]
+    fn rejects_truncated_trailing_value() {
+        let (_, bytes) = payload();
+        for cut in 1..4 {
+            let truncated = &bytes[..bytes.len() - cut];
+            for split in 1..truncated.len() {
+                let mut reader = ChunkedReader {
+                    data: truncated,
+                    chunk_sizes: vec![split],
+                };
+                let err = read_protocoll_payload::<LE, _>(&mut reader).unwrap_err();
+                assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
             }
         }
     }
@@ -1,89 +1,773 @@
 extern crate flate2;
 
-use std::io::{self, SeekFrom};
-use std::io::prelude::*;
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
 
-use flate2::{Compression, FlateReadExt};
-use flate2::write::ZlibEncoder;
-use flate2::read::ZlibDecoder;
+use flate2::bufread::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use flate2::write::{DeflateEncoder, ZlibEncoder};
+use flate2::{Compression, CrcReader, CrcWriter, GzBuilder};
 
-use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter, Read};
+// All data flows through buffers of this size,
+// so memory usage doesn't depend on the size of the file
+const BUFFER_SIZE: usize = 64 * 1024;
+
+const USAGE: &str = "Usage:
+    compression compress [--format gzip|zlib|deflate] [--level 0-9] [--verify] [--force] <input> [output]
+    compression decompress [--format gzip|zlib|deflate] [--force] <input> [output]
+Use - as input or output to read from stdin or write to stdout.
+Existing files are only overwritten with --force";
 
 fn main() {
-    let bytes = b"I have a dream that one day this nation will rise up, \
-        and live out the true meaning of its creed";
-    println!("Original: {:?}", bytes.as_ref());
-    // Conpress some bytes
-    let encoded = encode_bytes(bytes.as_ref()).expect("Failed to encode bytes");
-    println!("Encoded: {:?}", encoded);
-    // Decompress them again
-    let decoded = decode_bytes(&encoded).expect("Failed to decode bytes");
-    println!("Decoded: {:?}", decoded);
-
-    // Open file to compress
-    let original = File::open("ferris.png").expect("Failed to open file");
-    let mut original_reader = BufReader::new(original);
-
-    // Compress it
-    let data = encode_file(&mut original_reader).expect("Failed to encode file");
-
-    // Write compressed file to disk
-    let encoded = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open("ferris_encoded.zlib")
-        .expect("Failed to create encoded file");
-    let mut encoded_reader = BufReader::new(&encoded);
-    let mut encoded_writer = BufWriter::new(&encoded);
-    encoded_writer
-        .write_all(&data)
-        .expect("Failed to write encoded file");
-
-
-    // Jump back to the beginning of the compressed file
-    encoded_reader
-        .seek(SeekFrom::Start(0))
-        .expect("Failed to reset file");
-
-    // Decompress it
-    let data = decode_file(&mut encoded_reader).expect("Failed to decode file");
-
-    // Write the decompressed file to disk
-    let mut decoded = File::create("ferris_decoded.png").expect("Failed to create decoded file");
-    decoded
-        .write_all(&data)
-        .expect("Failed to write decoded file");
-}
-
-
-fn encode_bytes(bytes: &[u8]) -> io::Result<Vec<u8>> {
-    // You can choose your compression algorithm and it's efficiency
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::Default);
-    encoder.write_all(bytes)?;
-    encoder.finish()
-}
-
-fn decode_bytes(bytes: &[u8]) -> io::Result<Vec<u8>> {
-    let mut encoder = ZlibDecoder::new(bytes);
-    let mut buffer = Vec::new();
-    encoder.read_to_end(&mut buffer)?;
-    Ok(buffer)
-}
-
-
-fn encode_file(file: &mut Read) -> io::Result<Vec<u8>> {
-    // Files have a built-in encoder
-    let mut encoded = file.zlib_encode(Compression::Best);
-    let mut buffer = Vec::new();
-    encoded.read_to_end(&mut buffer)?;
-    Ok(buffer)
-}
-
-fn decode_file(file: &mut Read) -> io::Result<Vec<u8>> {
-    let mut buffer = Vec::new();
-    // Files have a built-in decoder
-    file.zlib_decode().read_to_end(&mut buffer)?;
-    Ok(buffer)
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = run(&command) {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Gzip,
+    Zlib,
+    Deflate,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match *self {
+            Format::Gzip => "gz",
+            Format::Zlib => "zz",
+            Format::Deflate => "deflate",
+        }
+    }
+
+    // Guess the format from the first two bytes of a compressed stream
+    fn detect(magic: &[u8]) -> Format {
+        match *magic {
+            [0x1F, 0x8B, ..] => Format::Gzip,
+            // A zlib header declares the deflate method in the lower
+            // nibble of its first byte, a window size of at most 32K
+            // and must be a multiple of 31 when read as a big endian u16
+            [cmf, flg, ..]
+                if cmf & 0x0F == 8
+                    && cmf >> 4 <= 7
+                    && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0 =>
+            {
+                Format::Zlib
+            }
+            // Raw deflate has no header at all
+            _ => Format::Deflate,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gzip" | "gz" => Ok(Format::Gzip),
+            "zlib" => Ok(Format::Zlib),
+            "deflate" => Ok(Format::Deflate),
+            _ => Err(format!("Unknown format '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Format::Gzip => "gzip",
+            Format::Zlib => "zlib",
+            Format::Deflate => "deflate",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The metadata a gzip header can carry
+#[derive(Debug, Clone, Default, PartialEq)]
+struct GzipInfo {
+    filename: Option<String>,
+    mtime: u32,
+}
+
+// What we know about a stream after (de)compressing it
+#[derive(Debug)]
+struct Summary {
+    format: Format,
+    // Size and CRC32 of the uncompressed data
+    uncompressed_len: u64,
+    crc: u32,
+    compressed_len: Option<u64>,
+    gzip_info: Option<GzipInfo>,
+}
+
+// Compress everything from reader into writer
+fn compress<R, W>(
+    reader: R,
+    writer: W,
+    format: Format,
+    level: Compression,
+    gzip_info: &GzipInfo,
+) -> io::Result<Summary>
+where
+    R: Read,
+    W: Write,
+{
+    // Remember checksum and length of the original data
+    // so that the result can be verified later on
+    let mut reader = CrcReader::new(reader);
+    let mut writer = CountingWriter::new(writer);
+    let uncompressed_len = match format {
+        Format::Gzip => {
+            let mut builder = GzBuilder::new().mtime(gzip_info.mtime);
+            if let Some(ref filename) = gzip_info.filename {
+                builder = builder.filename(filename.as_bytes());
+            }
+            let mut encoder = builder.write(&mut writer, level);
+            let len = io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+            len
+        }
+        Format::Zlib => {
+            let mut encoder = ZlibEncoder::new(&mut writer, level);
+            let len = io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+            len
+        }
+        Format::Deflate => {
+            let mut encoder = DeflateEncoder::new(&mut writer, level);
+            let len = io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+            len
+        }
+    };
+    writer.flush()?;
+
+    Ok(Summary {
+        format,
+        // The CRC only counts the length in 32 bits,
+        // so it is taken from io::copy instead
+        uncompressed_len,
+        crc: reader.crc().sum(),
+        compressed_len: Some(writer.count),
+        gzip_info: if format == Format::Gzip {
+            Some(gzip_info.clone())
+        } else {
+            None
+        },
+    })
+}
+
+// Decompress everything from reader into writer.
+// If no format is given, it is detected from the magic bytes
+fn decompress<R, W>(reader: R, writer: W, format: Option<Format>) -> io::Result<Summary>
+where
+    R: BufRead,
+    W: Write,
+{
+    // Read the first two bytes in a way that doesn't care
+    // about short reads and then put them back in front of the rest
+    let mut magic = [0; 2];
+    let mut reader = reader;
+    let magic_len = read_up_to(&mut reader, &mut magic)?;
+    let reader = Cursor::new(&magic[..magic_len]).chain(reader);
+    let format = format.unwrap_or_else(|| Format::detect(&magic[..magic_len]));
+
+    // Calculate the checksum of what we write
+    let mut writer = CrcWriter::new(writer);
+    let mut gzip_info = None;
+    let uncompressed_len = match format {
+        Format::Gzip => {
+            // A gzip file can consist of multiple members.
+            // The decoder verifies the CRC32 and the length stored at
+            // the end of every member and fails if they don't match
+            let mut decoder = MultiGzDecoder::new(reader);
+            let len = io::copy(&mut decoder, &mut writer)?;
+            gzip_info = decoder.header().map(|header| GzipInfo {
+                filename: header
+                    .filename()
+                    .map(|name| String::from_utf8_lossy(name).into_owned()),
+                mtime: header.mtime(),
+            });
+            len
+        }
+        Format::Zlib => io::copy(&mut ZlibDecoder::new(reader), &mut writer)?,
+        Format::Deflate => io::copy(&mut DeflateDecoder::new(reader), &mut writer)?,
+    };
+    writer.flush()?;
+
+    Ok(Summary {
+        format,
+        uncompressed_len,
+        crc: writer.crc().sum(),
+        compressed_len: None,
+        gzip_info,
+    })
+}
+
+// Decompress the result of compress() and check that
+// it matches the original data
+fn verify_round_trip<R: BufRead>(compressed: R, original: &Summary) -> io::Result<()> {
+    let decompressed = decompress(compressed, io::sink(), Some(original.format))?;
+    if decompressed.crc != original.crc
+        || decompressed.uncompressed_len != original.uncompressed_len
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Round trip check failed: expected CRC {:08x} and {} bytes, got CRC {:08x} and {} bytes",
+                original.crc,
+                original.uncompressed_len,
+                decompressed.crc,
+                decompressed.uncompressed_len
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// Like read_exact, but stops at the end of the stream
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+// Counts how many bytes went through it
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    fn new(inner: W) -> Self {
+        CountingWriter { inner, count: 0 }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Compress {
+        format: Format,
+        level: u32,
+        verify: bool,
+        force: bool,
+        input: String,
+        output: Option<String>,
+    },
+    Decompress {
+        format: Option<Format>,
+        force: bool,
+        input: String,
+        output: Option<String>,
+    },
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut args = args.iter();
+    let compressing = match args.next().map(String::as_str) {
+        Some("compress") => true,
+        Some("decompress") => false,
+        Some(other) => return Err(format!("Unknown command '{}'", other)),
+        None => return Err("Missing command".to_string()),
+    };
+
+    let mut format = None;
+    let mut level = Compression::default().level();
+    let mut verify = false;
+    let mut force = false;
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" | "-f" => {
+                let value = args.next().ok_or("Missing value for --format")?;
+                format = Some(value.parse()?);
+            }
+            "--level" | "-l" if compressing => {
+                let value = args.next().ok_or("Missing value for --level")?;
+                level = match value.parse() {
+                    Ok(level) if level <= 9 => level,
+                    _ => return Err(format!("Invalid level '{}', expected 0-9", value)),
+                };
+            }
+            "--verify" if compressing => verify = true,
+            "--force" => force = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'", arg)),
+            _ => paths.push(arg.clone()),
+        }
+    }
+
+    let mut paths = paths.into_iter();
+    let input = paths.next().ok_or("Missing input")?;
+    let output = paths.next();
+    if paths.next().is_some() {
+        return Err("Too many arguments".to_string());
+    }
+
+    if compressing {
+        Ok(Command::Compress {
+            format: format.unwrap_or(Format::Gzip),
+            level,
+            verify,
+            force,
+            input,
+            output,
+        })
+    } else {
+        Ok(Command::Decompress {
+            format,
+            force,
+            input,
+            output,
+        })
+    }
+}
+
+fn run(command: &Command) -> io::Result<()> {
+    match *command {
+        Command::Compress {
+            format,
+            level,
+            verify,
+            force,
+            ref input,
+            ref output,
+        } => {
+            let output = match *output {
+                Some(ref output) => output.clone(),
+                None if input == "-" => "-".to_string(),
+                None => format!("{}.{}", input, format.extension()),
+            };
+            if verify && output == "-" {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Cannot verify output written to stdout",
+                ));
+            }
+
+            if output != "-" {
+                check_target(input, Path::new(&output), force)?;
+            }
+
+            let gzip_info = gzip_info_for(input)?;
+            let reader = open_input(input)?;
+            let summary = write_output(&output, |writer| {
+                compress(reader, writer, format, Compression::new(level), &gzip_info)
+            })?;
+            eprintln!(
+                "Compressed {} bytes into {} bytes of {}",
+                summary.uncompressed_len,
+                summary.compressed_len.unwrap_or(0),
+                summary.format
+            );
+
+            if verify {
+                verify_round_trip(open_input(&output)?, &summary)?;
+                eprintln!("Verified that {} decompresses to the original", output);
+            }
+        }
+        Command::Decompress {
+            format,
+            force,
+            ref input,
+            ref output,
+        } => {
+            let reader = open_input(input)?;
+            let summary = match *output {
+                Some(ref output) if output != "-" => {
+                    check_target(input, Path::new(output), force)?;
+                    write_output(output, |writer| decompress(reader, writer, format))?
+                }
+                Some(_) => write_output("-", |writer| decompress(reader, writer, format))?,
+                None if input == "-" => {
+                    write_output("-", |writer| decompress(reader, writer, format))?
+                }
+                None => {
+                    // The original name is only known once the header has been read
+                    let temp = temp_path(Path::new(input));
+                    let summary = write_temp(&temp, |writer| decompress(reader, writer, format))?;
+                    let name = original_name(input, &summary);
+                    // The name comes from the file, so it could be anything
+                    if let Err(err) = check_target(input, &name, force) {
+                        let _ = fs::remove_file(&temp);
+                        return Err(err);
+                    }
+                    persist(&temp, &name)?;
+                    eprintln!("Wrote {}", name.display());
+                    summary
+                }
+            };
+            eprintln!(
+                "Decompressed {} bytes of {} (CRC32 {:08x})",
+                summary.uncompressed_len, summary.format, summary.crc
+            );
+        }
+    }
+    Ok(())
+}
+
+// Use the name stored in the gzip header, otherwise strip the extension
+fn original_name(input: &str, summary: &Summary) -> PathBuf {
+    let input = Path::new(input);
+    let stored_name = summary
+        .gzip_info
+        .as_ref()
+        .and_then(|info| info.filename.as_ref())
+        // Never trust a path from a file, only use its name
+        .and_then(|name| Path::new(name).file_name().map(PathBuf::from));
+    if let Some(name) = stored_name {
+        return input.with_file_name(name);
+    }
+    match input.extension().and_then(|e| e.to_str()) {
+        Some(extension) if extension == summary.format.extension() => input.with_extension(""),
+        _ => input.with_extension("out"),
+    }
+}
+
+fn gzip_info_for(input: &str) -> io::Result<GzipInfo> {
+    if input == "-" {
+        return Ok(GzipInfo::default());
+    }
+    let path = Path::new(input);
+    let mtime = path
+        .metadata()?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as u32)
+        .unwrap_or(0);
+    Ok(GzipInfo {
+        filename: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned()),
+        mtime,
+    })
+}
+
+fn open_input(path: &str) -> io::Result<Box<dyn BufRead>> {
+    if path == "-" {
+        Ok(Box::new(BufReader::with_capacity(BUFFER_SIZE, io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::with_capacity(
+            BUFFER_SIZE,
+            File::open(path)?,
+        )))
+    }
+}
+
+// Refuses to write over the input or, unless forced, any other existing file
+fn check_target(input: &str, target: &Path, force: bool) -> io::Result<()> {
+    let same_file = match (fs::canonicalize(input), fs::canonicalize(target)) {
+        (Ok(input), Ok(target)) => input == target,
+        _ => false,
+    };
+    if input != "-" && same_file {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is the input as well as the output", target.display()),
+        ));
+    }
+    if !force && target.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "{} already exists, use --force to overwrite it",
+                target.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// Files are written under a temporary name next to the target and only
+// renamed once they are complete, so that an error never leaves half
+// of a file behind or destroys the one that was there before
+fn write_output<F>(path: &str, write: F) -> io::Result<Summary>
+where
+    F: FnOnce(Box<dyn Write>) -> io::Result<Summary>,
+{
+    if path == "-" {
+        return write(Box::new(BufWriter::with_capacity(
+            BUFFER_SIZE,
+            io::stdout(),
+        )));
+    }
+    let temp = temp_path(Path::new(path));
+    let summary = write_temp(&temp, write)?;
+    persist(&temp, Path::new(path))?;
+    Ok(summary)
+}
+
+fn temp_path(near: &Path) -> PathBuf {
+    let name = near
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    near.with_file_name(format!(".{}.{}.tmp", name, process::id()))
+}
+
+fn write_temp<F>(temp: &Path, write: F) -> io::Result<Summary>
+where
+    F: FnOnce(Box<dyn Write>) -> io::Result<Summary>,
+{
+    let file = OpenOptions::new().write(true).create_new(true).open(temp)?;
+    let result = write(Box::new(BufWriter::with_capacity(BUFFER_SIZE, file)));
+    if result.is_err() {
+        let _ = fs::remove_file(temp);
+    }
+    result
+}
+
+fn persist(temp: &Path, target: &Path) -> io::Result<()> {
+    fs::rename(temp, target).inspect_err(|_| {
+        let _ = fs::remove_file(temp);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Some data that is larger than our buffers and
+    // partly compressible, partly not
+    fn sample_data() -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut state = 1u32;
+        for i in 0..200_000u32 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            data.push(if i % 3 == 0 {
+                (state >> 16) as u8
+            } else {
+                b'a' + (i % 7) as u8
+            });
+        }
+        data
+    }
+
+    fn compressed(data: &[u8], format: Format, level: u32) -> Vec<u8> {
+        let info = GzipInfo {
+            filename: Some("ferris.png".to_string()),
+            mtime: 1_518_000_000,
+        };
+        let mut output = Vec::new();
+        compress(data, &mut output, format, Compression::new(level), &info).unwrap();
+        output
+    }
+
+    #[test]
+    fn round_trips_all_formats_and_levels() {
+        let data = sample_data();
+        for &format in &[Format::Gzip, Format::Zlib, Format::Deflate] {
+            for &level in &[0, 1, 6, 9] {
+                let compressed = compressed(&data, format, level);
+                let mut decompressed = Vec::new();
+                let summary = decompress(&compressed[..], &mut decompressed, Some(format)).unwrap();
+                assert_eq!(data, decompressed, "{} at level {}", format, level);
+                assert_eq!(data.len() as u64, summary.uncompressed_len);
+            }
+        }
+    }
+
+    #[test]
+    fn detects_format_from_magic_bytes() {
+        let data = sample_data();
+        for &format in &[Format::Gzip, Format::Zlib, Format::Deflate] {
+            let compressed = compressed(&data, format, 6);
+            assert_eq!(format, Format::detect(&compressed));
+
+            let mut decompressed = Vec::new();
+            let summary = decompress(&compressed[..], &mut decompressed, None).unwrap();
+            assert_eq!(format, summary.format);
+            assert_eq!(data, decompressed);
+        }
+    }
+
+    #[test]
+    fn keeps_gzip_header() {
+        let compressed = compressed(b"Hello World!", Format::Gzip, 6);
+        let summary = decompress(&compressed[..], io::sink(), None).unwrap();
+        let info = summary.gzip_info.unwrap();
+        assert_eq!(Some("ferris.png".to_string()), info.filename);
+        assert_eq!(1_518_000_000, info.mtime);
+        assert_eq!(
+            PathBuf::from("dir/ferris.png"),
+            original_name(
+                "dir/x.gz",
+                &Summary {
+                    gzip_info: Some(info),
+                    ..summary
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_corrupted_gzip_trailer() {
+        let data = sample_data();
+        let compressed = compressed(&data, Format::Gzip, 6);
+        let len = compressed.len();
+
+        // The last 8 bytes are the CRC32 and the length
+        let mut wrong_crc = compressed.clone();
+        wrong_crc[len - 8] ^= 0xFF;
+        assert!(decompress(&wrong_crc[..], io::sink(), None).is_err());
+
+        let mut wrong_len = compressed.clone();
+        wrong_len[len - 1] ^= 0xFF;
+        assert!(decompress(&wrong_len[..], io::sink(), None).is_err());
+
+        assert!(decompress(&compressed[..len - 3], io::sink(), None).is_err());
+    }
+
+    #[test]
+    fn verifies_round_trip() {
+        let data = sample_data();
+        for &format in &[Format::Gzip, Format::Zlib, Format::Deflate] {
+            let mut output = Vec::new();
+            let summary = compress(
+                &data[..],
+                &mut output,
+                format,
+                Compression::best(),
+                &GzipInfo::default(),
+            )
+            .unwrap();
+            assert_eq!(Some(output.len() as u64), summary.compressed_len);
+            verify_round_trip(&output[..], &summary).unwrap();
+
+            // Compressing something else must not pass the check
+            let other = compressed(b"something else", format, 6);
+            assert!(verify_round_trip(&other[..], &summary).is_err());
+        }
+    }
+
+    #[test]
+    fn decompress_never_overwrites_or_leaves_partial_files() {
+        let dir = env::temp_dir().join(format!("compression-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("x.gz").to_string_lossy().into_owned();
+        let decompress_command = |output: Option<&str>| Command::Decompress {
+            format: None,
+            force: false,
+            input: input.clone(),
+            output: output.map(String::from),
+        };
+
+        // The header names ferris.png, which is already there
+        fs::write(&input, compressed(b"new", Format::Gzip, 6)).unwrap();
+        fs::write(dir.join("ferris.png"), "old").unwrap();
+        assert!(run(&decompress_command(None)).is_err());
+        assert_eq!("old", fs::read_to_string(dir.join("ferris.png")).unwrap());
+
+        let mut truncated = compressed(&sample_data(), Format::Gzip, 6);
+        truncated.truncate(1000);
+        fs::write(&input, truncated).unwrap();
+        let output = dir.join("out").to_string_lossy().into_owned();
+        assert!(run(&decompress_command(Some(&output))).is_err());
+        assert!(!Path::new(&output).exists());
+
+        // An existing file given as output is kept as well
+        fs::write(&output, "old").unwrap();
+        assert!(run(&decompress_command(Some(&output))).is_err());
+        assert_eq!("old", fs::read_to_string(&output).unwrap());
+        assert!(run(&decompress_command(Some(&input))).is_err());
+        assert_eq!(1000, fs::metadata(&input).unwrap().len());
+        assert_eq!(3, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compress_never_overwrites_without_force() {
+        let dir = env::temp_dir().join(format!("compression-compress-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("data").to_string_lossy().into_owned();
+        fs::write(&input, "data").unwrap();
+        let compress_command = |output: Option<&str>, force: bool| Command::Compress {
+            format: Format::Gzip,
+            level: 6,
+            verify: true,
+            force,
+            input: input.clone(),
+            output: output.map(String::from),
+        };
+
+        assert!(run(&compress_command(Some(&input), true)).is_err());
+        assert_eq!("data", fs::read_to_string(&input).unwrap());
+
+        let archive = format!("{}.gz", input);
+        fs::write(&archive, "old").unwrap();
+        assert!(run(&compress_command(None, false)).is_err());
+        assert_eq!("old", fs::read_to_string(&archive).unwrap());
+        run(&compress_command(None, true)).unwrap();
+        let mut decompressed = Vec::new();
+        decompress(&fs::read(&archive).unwrap()[..], &mut decompressed, None).unwrap();
+        assert_eq!(b"data", &decompressed[..]);
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parses_command_line() {
+        let args = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            Ok(Command::Compress {
+                format: Format::Zlib,
+                level: 9,
+                verify: true,
+                force: false,
+                input: "ferris.png".to_string(),
+                output: None,
+            }),
+            parse_args(&args(
+                "compress --format zlib --level 9 --verify ferris.png"
+            ))
+        );
+        assert_eq!(
+            Ok(Command::Decompress {
+                format: None,
+                force: true,
+                input: "-".to_string(),
+                output: Some("out.png".to_string()),
+            }),
+            parse_args(&args("decompress --force - out.png"))
+        );
+        assert!(parse_args(&args("compress --level 10 a")).is_err());
+        assert!(parse_args(&args("decompress --level 1 a")).is_err());
+        assert!(parse_args(&args("compress")).is_err());
+    }
 }
@@ -0,0 +1,328 @@
+extern crate walkdir;
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::process;
+use walkdir::WalkDir;
+
+fn main() {
+    // Behaves like a mix of "du" and "du | sort -h | tail"
+    let mut mode = SizeMode::Apparent;
+    let mut follow_links = false;
+    let mut top = 10;
+    let mut root = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--allocated" => mode = SizeMode::Allocated,
+            "--follow-links" => follow_links = true,
+            "--top" => {
+                top = args
+                    .next()
+                    .and_then(|count| count.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            _ if root.is_none() && !arg.starts_with('-') => root = Some(arg),
+            _ => usage(),
+        }
+    }
+    let root = root.unwrap_or_else(|| ".".to_string());
+
+    let report = DiskUsage::scan(&root, mode, follow_links);
+    for error in &report.errors {
+        eprintln!("Warning: {}", error);
+    }
+    println!(
+        "Total {:?} size of '{}': {}",
+        mode,
+        root,
+        format_size(report.total)
+    );
+
+    println!("\nLargest directories:");
+    for (path, size) in report.largest_directories(top) {
+        println!("{:>12}  {}", format_size(size), path.display());
+    }
+    println!("\nLargest files:");
+    for (path, size) in report.largest_files(top) {
+        println!("{:>12}  {}", format_size(size), path.display());
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: disk_usage [--allocated] [--follow-links] [--top <count>] [path]");
+    process::exit(2);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SizeMode {
+    // The number of bytes a program would read from the file
+    Apparent,
+    // The space the file actually takes up on disk,
+    // which differs for sparse files and small files
+    Allocated,
+}
+
+impl SizeMode {
+    fn size_of(self, metadata: &Metadata) -> u64 {
+        match self {
+            SizeMode::Apparent => metadata.len(),
+            SizeMode::Allocated => allocated_size(metadata),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn allocated_size(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    // The block count is always in units of 512 bytes,
+    // no matter what the block size of the filesystem is
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_size(metadata: &Metadata) -> u64 {
+    metadata.len()
+}
+
+// Identifies a file independently of the path we found it under
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[derive(Debug, Default)]
+struct DiskUsage {
+    total: u64,
+    // The cumulative size of every directory, including itself
+    directories: HashMap<PathBuf, u64>,
+    files: Vec<(PathBuf, u64)>,
+    // Entries we couldn't look at don't stop the scan
+    errors: Vec<walkdir::Error>,
+}
+
+impl DiskUsage {
+    fn scan<P: AsRef<Path>>(root: P, mode: SizeMode, follow_links: bool) -> DiskUsage {
+        let mut usage = DiskUsage::default();
+        // Hard links and symlinks can lead us to the same file more than once
+        let mut seen = HashSet::new();
+
+        // Without following links, a symlink is just a small file.
+        // When following them, WalkDir reports loops as errors
+        // instead of walking in circles
+        for entry in WalkDir::new(root).follow_links(follow_links) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    usage.errors.push(e);
+                    continue;
+                }
+            };
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    usage.errors.push(e);
+                    continue;
+                }
+            };
+            if let Some(id) = file_id(&metadata) {
+                if !seen.insert(id) {
+                    continue;
+                }
+            }
+
+            let size = mode.size_of(&metadata);
+            usage.total += size;
+            let is_dir = metadata.is_dir();
+            if !is_dir {
+                usage.files.push((entry.path().to_path_buf(), size));
+            }
+            // Add the size to every directory between the entry and the root
+            let skip = if is_dir { 0 } else { 1 };
+            for dir in entry.path().ancestors().take(entry.depth() + 1).skip(skip) {
+                *usage.directories.entry(dir.to_path_buf()).or_insert(0) += size;
+            }
+        }
+        usage
+    }
+
+    fn largest_directories(&self, count: usize) -> Vec<(&Path, u64)> {
+        largest(
+            self.directories
+                .iter()
+                .map(|(path, &size)| (path.as_path(), size)),
+            count,
+        )
+    }
+
+    fn largest_files(&self, count: usize) -> Vec<(&Path, u64)> {
+        largest(
+            self.files
+                .iter()
+                .map(|&(ref path, size)| (path.as_path(), size)),
+            count,
+        )
+    }
+}
+
+fn largest<'a, I>(entries: I, count: usize) -> Vec<(&'a Path, u64)>
+where
+    I: Iterator<Item = (&'a Path, u64)>,
+{
+    let mut entries: Vec<_> = entries.collect();
+    // Sort by path as well to get the same output on every run
+    entries.sort_by_key(|&(path, size)| (Reverse(size), path));
+    entries.truncate(count);
+    entries
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // root/a (100 bytes), root/sub/b (200), root/sub/deeper/c (300)
+    fn create_tree(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("disk-usage-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub/deeper")).unwrap();
+        fs::write(root.join("a"), vec![0; 100]).unwrap();
+        fs::write(root.join("sub/b"), vec![0; 200]).unwrap();
+        fs::write(root.join("sub/deeper/c"), vec![0; 300]).unwrap();
+        root
+    }
+
+    fn size_of(usage: &DiskUsage, dir: &Path) -> Option<u64> {
+        usage.directories.get(dir).cloned()
+    }
+
+    fn dir_size(path: &Path) -> u64 {
+        fs::symlink_metadata(path).unwrap().len()
+    }
+
+    #[test]
+    fn sums_up_directories() {
+        let root = create_tree("sums");
+        let usage = DiskUsage::scan(&root, SizeMode::Apparent, false);
+        assert!(usage.errors.is_empty());
+
+        let deeper = root.join("sub/deeper");
+        let sub = root.join("sub");
+        assert_eq!(Some(300 + dir_size(&deeper)), size_of(&usage, &deeper));
+        assert_eq!(
+            Some(500 + dir_size(&deeper) + dir_size(&sub)),
+            size_of(&usage, &sub)
+        );
+        let total = 600 + dir_size(&deeper) + dir_size(&sub) + dir_size(&root);
+        assert_eq!(Some(total), size_of(&usage, &root));
+        assert_eq!(total, usage.total);
+    }
+
+    #[test]
+    fn lists_largest_entries() {
+        let root = create_tree("largest");
+        let usage = DiskUsage::scan(&root, SizeMode::Apparent, false);
+        let files: Vec<_> = usage.largest_files(2);
+        assert_eq!(
+            vec![
+                (root.join("sub/deeper/c").as_path(), 300),
+                (root.join("sub/b").as_path(), 200),
+            ],
+            files
+        );
+        let dirs: Vec<_> = usage
+            .largest_directories(10)
+            .into_iter()
+            .map(|(path, _)| path.to_path_buf())
+            .collect();
+        assert_eq!(
+            vec![root.clone(), root.join("sub"), root.join("sub/deeper")],
+            dirs
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn counts_allocated_blocks() {
+        use std::fs::OpenOptions;
+        let root = env::temp_dir().join(format!("disk-usage-allocated-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir(&root).unwrap();
+        // A sparse file claims to be large but takes up next to no space
+        let sparse = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(root.join("sparse"))
+            .unwrap();
+        sparse.set_len(100 * 1024 * 1024).unwrap();
+
+        let apparent = DiskUsage::scan(&root, SizeMode::Apparent, false);
+        let allocated = DiskUsage::scan(&root, SizeMode::Allocated, false);
+        assert_eq!(100 * 1024 * 1024, apparent.largest_files(1)[0].1);
+        assert!(allocated.largest_files(1)[0].1 < 1024 * 1024);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn counts_hard_links_once() {
+        let root = create_tree("hard-links");
+        fs::hard_link(root.join("sub/deeper/c"), root.join("link")).unwrap();
+        let usage = DiskUsage::scan(&root, SizeMode::Apparent, false);
+        assert_eq!(3, usage.files.len());
+        let file_total: u64 = usage.files.iter().map(|&(_, size)| size).sum();
+        assert_eq!(600, file_total);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn survives_symlink_loops() {
+        use std::os::unix::fs::symlink;
+        let root = create_tree("loops");
+        symlink(&root, root.join("sub/deeper/loop")).unwrap();
+        symlink(root.join("a"), root.join("sub/alias")).unwrap();
+
+        // Without following links, the symlinks are just tiny files
+        let usage = DiskUsage::scan(&root, SizeMode::Apparent, false);
+        assert!(usage.errors.is_empty());
+        assert_eq!(5, usage.files.len());
+
+        // Following them finds the loop, but doesn't count anything twice
+        let usage = DiskUsage::scan(&root, SizeMode::Apparent, true);
+        assert_eq!(1, usage.errors.len());
+        assert!(usage.errors[0].loop_ancestor().is_some());
+        let file_total: u64 = usage.files.iter().map(|&(_, size)| size).sum();
+        assert_eq!(600, file_total);
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!("0 B", format_size(0));
+        assert_eq!("1023 B", format_size(1023));
+        assert_eq!("1.0 KiB", format_size(1024));
+        assert_eq!("1.5 MiB", format_size(1024 * 1024 * 3 / 2));
+    }
+}
@@ -0,0 +1,287 @@
+extern crate sha2;
+extern crate walkdir;
+
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{File, Metadata};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use walkdir::WalkDir;
+
+// How much of every file is hashed before hashing all of it
+const PARTIAL_SIZE: u64 = 4 * 1024;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (root, follow_links) = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => (".", false),
+        ["--follow-links"] => (".", true),
+        ["--follow-links", root] => (root, true),
+        [root] => (root, false),
+        _ => {
+            eprintln!("Usage: duplicates [--follow-links] [path]");
+            process::exit(2);
+        }
+    };
+    let report = find_duplicates(root, follow_links);
+    for (path, error) in &report.errors {
+        eprintln!("Warning: {}: {}", path.display(), error);
+    }
+    if report.groups.is_empty() {
+        println!("No duplicates found in '{}'", root);
+    }
+    for group in &report.groups {
+        println!(
+            "{} files of {} bytes each, {} bytes wasted:",
+            group.paths.len(),
+            group.size,
+            group.wasted()
+        );
+        for path in &group.paths {
+            println!("  {}", path.display());
+        }
+    }
+}
+
+// Files with identical content
+#[derive(Debug, PartialEq)]
+struct DuplicateGroup {
+    size: u64,
+    paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    // The space we could free by keeping only one copy
+    fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+#[derive(Debug, Default)]
+struct DuplicateReport {
+    groups: Vec<DuplicateGroup>,
+    // Files we couldn't look at are skipped
+    errors: Vec<(PathBuf, io::Error)>,
+}
+
+fn find_duplicates<P: AsRef<Path>>(root: P, follow_links: bool) -> DuplicateReport {
+    let mut report = DuplicateReport::default();
+
+    // Reading files is expensive, so we narrow down the candidates
+    // with increasingly expensive checks. First, only files
+    // of the same size can have the same content
+    let by_size = group_files_by_size(root.as_ref(), follow_links, &mut report.errors);
+
+    // Next, compare the beginning of the files, which
+    // already tells most files of the same size apart
+    let mut by_partial_hash = HashMap::new();
+    for (size, paths) in candidates(by_size) {
+        for path in paths {
+            match hash_file(&path, Some(PARTIAL_SIZE)) {
+                Ok(hash) => by_partial_hash
+                    .entry((size, hash))
+                    .or_insert_with(Vec::new)
+                    .push(path),
+                Err(e) => report.errors.push((path, e)),
+            }
+        }
+    }
+
+    // Finally, compare the whole content
+    let mut by_full_hash = HashMap::new();
+    for ((size, partial_hash), paths) in candidates(by_partial_hash) {
+        for path in paths {
+            // Small files were already hashed completely
+            let hash = if size <= PARTIAL_SIZE {
+                Ok(partial_hash)
+            } else {
+                hash_file(&path, None)
+            };
+            match hash {
+                Ok(hash) => by_full_hash
+                    .entry((size, hash))
+                    .or_insert_with(Vec::new)
+                    .push(path),
+                Err(e) => report.errors.push((path, e)),
+            }
+        }
+    }
+
+    report.groups = candidates(by_full_hash)
+        .map(|((size, _), mut paths)| {
+            paths.sort();
+            DuplicateGroup { size, paths }
+        })
+        .collect();
+    // Show the most wasteful groups first
+    report
+        .groups
+        .sort_by(|a, b| (Reverse(a.wasted()), &a.paths).cmp(&(Reverse(b.wasted()), &b.paths)));
+    report
+}
+
+fn group_files_by_size(
+    root: &Path,
+    follow_links: bool,
+    errors: &mut Vec<(PathBuf, io::Error)>,
+) -> HashMap<u64, Vec<PathBuf>> {
+    let mut by_size = HashMap::new();
+    // Hard links share their content, so they are not duplicates
+    // that could be cleaned up. Only look at each file once
+    let mut seen = HashSet::new();
+    // WalkDir reports symlink loops as errors instead of hanging
+    for entry in WalkDir::new(root).follow_links(follow_links) {
+        let (path, metadata) = match entry.and_then(|entry| {
+            let metadata = entry.metadata()?;
+            Ok((entry.into_path(), metadata))
+        }) {
+            Ok(found) => found,
+            Err(e) => {
+                let path = e.path().unwrap_or(root).to_path_buf();
+                errors.push((path, e.into()));
+                continue;
+            }
+        };
+        // Empty files are trivially identical, but not worth reporting
+        if !metadata.is_file() || metadata.len() == 0 {
+            continue;
+        }
+        if let Some(id) = file_id(&metadata) {
+            if !seen.insert(id) {
+                continue;
+            }
+        }
+        by_size
+            .entry(metadata.len())
+            .or_insert_with(Vec::new)
+            .push(path);
+    }
+    by_size
+}
+
+// Only groups with more than one file can contain duplicates
+fn candidates<K>(groups: HashMap<K, Vec<PathBuf>>) -> impl Iterator<Item = (K, Vec<PathBuf>)> {
+    groups.into_iter().filter(|(_, paths)| paths.len() > 1)
+}
+
+// Hash the first `limit` bytes of a file, or all of it
+fn hash_file(path: &Path, limit: Option<u64>) -> io::Result<[u8; 32]> {
+    let file = File::open(path)?;
+    let mut reader: Box<dyn Read> = match limit {
+        Some(limit) => Box::new(file.take(limit)),
+        None => Box::new(file),
+    };
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..len]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+// Identifies a file independently of the path we found it under
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // A path for the test to create its files in, without leftovers of earlier runs
+    fn temp_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("duplicates-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn groups_identical_files() {
+        let root = temp_path("identical");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a"), "same content").unwrap();
+        fs::write(root.join("sub/b"), "same content").unwrap();
+        fs::write(root.join("sub/c"), "same content").unwrap();
+        // Same size, different content
+        fs::write(root.join("d"), "more content").unwrap();
+        fs::write(root.join("unique"), "unique").unwrap();
+        fs::write(root.join("empty1"), "").unwrap();
+        fs::write(root.join("empty2"), "").unwrap();
+
+        let report = find_duplicates(&root, false);
+        assert!(report.errors.is_empty());
+        assert_eq!(
+            vec![DuplicateGroup {
+                size: 12,
+                paths: vec![root.join("a"), root.join("sub/b"), root.join("sub/c")],
+            }],
+            report.groups
+        );
+        assert_eq!(24, report.groups[0].wasted());
+    }
+
+    #[test]
+    fn tells_apart_files_differing_after_the_partial_hash() {
+        let root = temp_path("partial");
+        fs::create_dir(&root).unwrap();
+        let mut content = vec![7; 3 * PARTIAL_SIZE as usize];
+        fs::write(root.join("a"), &content).unwrap();
+        fs::write(root.join("b"), &content).unwrap();
+        *content.last_mut().unwrap() = 8;
+        fs::write(root.join("c"), &content).unwrap();
+
+        let report = find_duplicates(&root, false);
+        assert_eq!(1, report.groups.len());
+        assert_eq!(vec![root.join("a"), root.join("b")], report.groups[0].paths);
+    }
+
+    #[test]
+    fn sorts_groups_by_wasted_space() {
+        let root = temp_path("sorted");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("small1"), "small").unwrap();
+        fs::write(root.join("small2"), "small").unwrap();
+        fs::write(root.join("large1"), "larger content").unwrap();
+        fs::write(root.join("large2"), "larger content").unwrap();
+
+        let report = find_duplicates(&root, false);
+        let sizes: Vec<_> = report.groups.iter().map(|group| group.size).collect();
+        assert_eq!(vec![14, 5], sizes);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ignores_hard_links_and_symlink_loops() {
+        use std::os::unix::fs::symlink;
+        let root = temp_path("links");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a"), "content").unwrap();
+        fs::hard_link(root.join("a"), root.join("hard")).unwrap();
+        symlink(&root, root.join("sub/loop")).unwrap();
+
+        let report = find_duplicates(&root, false);
+        assert!(report.groups.is_empty());
+
+        let report = find_duplicates(&root, true);
+        assert!(report.groups.is_empty());
+        assert_eq!(1, report.errors.len());
+        assert_eq!(root.join("sub/loop"), report.errors[0].0);
+    }
+}
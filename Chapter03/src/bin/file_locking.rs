@@ -0,0 +1,580 @@
+extern crate libc;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::{error, fmt, process};
+
+// Note: flock is only available on Unix-like systems,
+// everywhere else locking fails with ErrorKind::Unsupported
+
+fn main() {
+    let path = "./locked.log";
+    // Every process appending through append_locked() waits for
+    // the others, so lines from different writers never interleave
+    append_locked(path, "First line").expect("Failed to append to file");
+    append_locked(path, "Second line").expect("Failed to append to file");
+
+    // Locks belong to an open file, so a second open
+    // conflicts even inside the same process
+    let file = File::open(path).expect("Failed to open file");
+    let shared = FileLock::lock(file, LockKind::Shared).expect("Failed to lock file");
+    println!("Holding a {:?} lock on '{}'", shared.kind(), path);
+
+    let other = File::open(path).expect("Failed to open file");
+    match FileLock::try_lock(other, LockKind::Exclusive).expect("Failed to lock file") {
+        Some(_) => println!("Got an exclusive lock, this should not happen"),
+        None => println!("Could not get an exclusive lock while a shared one is held"),
+    }
+    drop(shared);
+
+    let other = File::open(path).expect("Failed to open file");
+    if let Some(lock) = FileLock::try_lock(other, LockKind::Exclusive).expect("Failed to lock file")
+    {
+        println!(
+            "Got a {:?} lock after the shared one was dropped",
+            lock.kind()
+        );
+        // Unlocking early hands us back the file
+        let file = lock.unlock().expect("Failed to unlock file");
+        println!(
+            "Unlocked, the file is {} bytes long",
+            file.metadata().unwrap().len()
+        );
+    }
+    fs::remove_file(path).expect("Failed to remove file");
+
+    // A lock file makes sure only one instance of a program runs at a time
+    let pid_path = "./locked.pid";
+    let pid_lock = PidLock::acquire(pid_path).expect("Failed to acquire lock file");
+    if let Some(pid) = pid_lock.stale_pid() {
+        println!("Took over a stale lock left behind by process {}", pid);
+    }
+    println!(
+        "Process {} holds '{}'",
+        process::id(),
+        pid_lock.path().display()
+    );
+    match PidLock::acquire(pid_path) {
+        Err(PidLockError::Held(Some(pid))) => println!("The lock file is held by {}", pid),
+        other => println!("Unexpected result: {:?}", other),
+    }
+    // On filesystems without working flock, the PID in the file decides
+    match PidLock::acquire_checking_pid(pid_path) {
+        Err(PidLockError::Held(Some(pid))) => println!("Process {} is still alive", pid),
+        other => println!("Unexpected result: {:?}", other),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockKind {
+    // Any number of processes can hold a shared lock at the same time
+    Shared,
+    // An exclusive lock keeps out every other lock
+    Exclusive,
+}
+
+// An advisory lock on a whole file that is released when dropped.
+// We use flock instead of fcntl because fcntl locks belong to the process
+// and are dropped as soon as *any* handle to the file gets closed,
+// which doesn't play well with guards like this one
+#[derive(Debug)]
+struct FileLock {
+    file: Option<File>,
+    kind: LockKind,
+}
+
+impl FileLock {
+    // Wait until the lock is ours
+    fn lock(file: File, kind: LockKind) -> io::Result<FileLock> {
+        lock_file(&file, kind, true)?;
+        Ok(FileLock {
+            file: Some(file),
+            kind,
+        })
+    }
+
+    // Return None instead of waiting if somebody else
+    // holds a conflicting lock
+    fn try_lock(file: File, kind: LockKind) -> io::Result<Option<FileLock>> {
+        match lock_file(&file, kind, false) {
+            Ok(()) => Ok(Some(FileLock {
+                file: Some(file),
+                kind,
+            })),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn kind(&self) -> LockKind {
+        self.kind
+    }
+
+    // Release the lock early while keeping the file open
+    fn unlock(mut self) -> io::Result<File> {
+        let file = self.file.take().expect("FileLock was already unlocked");
+        unlock_file(&file)?;
+        Ok(file)
+    }
+}
+
+impl Deref for FileLock {
+    type Target = File;
+    fn deref(&self) -> &File {
+        self.file.as_ref().expect("FileLock was already unlocked")
+    }
+}
+
+impl DerefMut for FileLock {
+    fn deref_mut(&mut self) -> &mut File {
+        self.file.as_mut().expect("FileLock was already unlocked")
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Closing the file would release the lock as well,
+        // but being explicit doesn't hurt
+        if let Some(file) = self.file.take() {
+            let _ = unlock_file(&file);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn lock_file(file: &File, kind: LockKind, wait: bool) -> io::Result<()> {
+    let operation = match kind {
+        LockKind::Shared => libc::LOCK_SH,
+        LockKind::Exclusive => libc::LOCK_EX,
+    };
+    if wait {
+        flock(file, operation)
+    } else {
+        flock(file, operation | libc::LOCK_NB)
+    }
+}
+
+#[cfg(unix)]
+fn unlock_file(file: &File) -> io::Result<()> {
+    flock(file, libc::LOCK_UN)
+}
+
+#[cfg(unix)]
+fn flock(file: &File, operation: libc::c_int) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    loop {
+        let result = unsafe { libc::flock(file.as_raw_fd(), operation) };
+        if result == 0 {
+            return Ok(());
+        }
+        // A signal can interrupt us while we are waiting for the lock
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_file(_file: &File, _kind: LockKind, _wait: bool) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "File locking is only implemented for Unix-like systems",
+    ))
+}
+
+#[cfg(not(unix))]
+fn unlock_file(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+// Append a line to a file that other processes might be writing to as well
+fn append_locked<P: AsRef<Path>>(path: P, line: &str) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut lock = FileLock::lock(file, LockKind::Exclusive)?;
+    // Write the line in one go, there is no buffer that could
+    // be flushed after the lock has been released
+    lock.write_all(format!("{}\n", line).as_bytes())?;
+    lock.sync_data()
+}
+
+// A lock file containing the PID of the process holding it
+#[derive(Debug)]
+struct PidLock {
+    path: PathBuf,
+    // Only kept around so the lock lives as long as we do
+    _lock: FileLock,
+    stale_pid: Option<u32>,
+}
+
+#[derive(Debug)]
+enum PidLockError {
+    // Another living process holds the lock. The PID is missing
+    // if the holder hasn't gotten around to write it yet
+    Held(Option<u32>),
+    Io(io::Error),
+}
+
+impl PidLock {
+    fn acquire<P: AsRef<Path>>(path: P) -> Result<PidLock, PidLockError> {
+        PidLock::acquire_with(path.as_ref(), false)
+    }
+
+    // For filesystems where flock doesn't work, like some network filesystems.
+    // A PID that belongs to a living process then counts as holding the lock,
+    // even though the process might just have been given a reused PID
+    fn acquire_checking_pid<P: AsRef<Path>>(path: P) -> Result<PidLock, PidLockError> {
+        PidLock::acquire_with(path.as_ref(), true)
+    }
+
+    fn acquire_with(path: &Path, check_pid: bool) -> Result<PidLock, PidLockError> {
+        loop {
+            // Don't truncate yet, the content belongs to the holder
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+            let mut lock = match FileLock::try_lock(file, LockKind::Exclusive)? {
+                Some(lock) => lock,
+                None => return Err(PidLockError::Held(read_pid_from_path(path)?)),
+            };
+
+            // The previous holder removes the file before unlocking it.
+            // If that happened right after we opened it, we now hold
+            // a lock on a file nobody else can see, so try again
+            if !is_same_file(&lock, path)? {
+                continue;
+            }
+
+            // The lock is free, but the file still contains a PID.
+            // That is a leftover of a process that didn't clean up after itself.
+            // Any process with that PID now is somebody else, unless
+            // flock can't be trusted on this filesystem
+            let stale_pid = match read_pid(&mut lock)? {
+                Some(pid) if check_pid && pid != process::id() && process_exists(pid) => {
+                    return Err(PidLockError::Held(Some(pid)))
+                }
+                previous => previous,
+            };
+
+            lock.set_len(0)?;
+            lock.seek(SeekFrom::Start(0))?;
+            lock.write_all(format!("{}\n", process::id()).as_bytes())?;
+            lock.sync_data()?;
+            return Ok(PidLock {
+                path: path.to_path_buf(),
+                _lock: lock,
+                stale_pid,
+            });
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // The PID of a dead process that held the lock before us
+    fn stale_pid(&self) -> Option<u32> {
+        self.stale_pid
+    }
+}
+
+impl Drop for PidLock {
+    fn drop(&mut self) {
+        // Remove the file while we still hold the lock,
+        // the FileLock is released right after this
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn read_pid(file: &mut File) -> io::Result<Option<u32>> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_string(&mut content)?;
+    // 0 and anything above i32::MAX can't be a process, kill() would
+    // take them for our own process group or every process there is
+    Ok(content
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&pid| pid > 0 && pid <= i32::MAX as u32))
+}
+
+fn read_pid_from_path(path: &Path) -> io::Result<Option<u32>> {
+    match File::open(path) {
+        Ok(mut file) => read_pid(&mut file),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let opened = file.metadata()?;
+    match fs::metadata(path) {
+        Ok(current) => Ok(opened.dev() == current.dev() && opened.ino() == current.ino()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, path: &Path) -> io::Result<bool> {
+    // Without inode numbers, the best we can do is to check the path
+    Ok(path.exists())
+}
+
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool {
+    // Signal 0 doesn't send anything, it only checks
+    // whether the process could be signalled
+    if unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 {
+        return true;
+    }
+    // EPERM means it exists but belongs to somebody else
+    io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_exists(_pid: u32) -> bool {
+    // Better to leave a stale lock alone than to take over a living one
+    true
+}
+
+impl From<io::Error> for PidLockError {
+    fn from(e: io::Error) -> Self {
+        PidLockError::Io(e)
+    }
+}
+
+impl fmt::Display for PidLockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PidLockError::Held(Some(pid)) => write!(f, "Lock is held by process {}", pid),
+            PidLockError::Held(None) => write!(f, "Lock is held by another process"),
+            PidLockError::Io(ref e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl error::Error for PidLockError {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process::{Child, Command, Stdio};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const ACTION_VAR: &str = "FILE_LOCKING_CHILD_ACTION";
+    const PATH_VAR: &str = "FILE_LOCKING_CHILD_PATH";
+    const ACQUIRED: i32 = 0;
+    const BLOCKED: i32 = 1;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("locking-{}-{}", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn touch(path: &Path) -> File {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .unwrap()
+    }
+
+    // Run child_process() below in a new process of this test binary
+    fn spawn_child(action: &str, path: &Path) -> Child {
+        Command::new(env::current_exe().unwrap())
+            .args(["tests::child_process", "--exact", "--ignored"])
+            .env(ACTION_VAR, action)
+            .env(PATH_VAR, path)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap()
+    }
+
+    fn run_child(action: &str, path: &Path) -> i32 {
+        let status = spawn_child(action, path).wait().unwrap();
+        status.code().expect("Child process was killed")
+    }
+
+    fn try_lock_code(path: &Path, kind: LockKind) -> i32 {
+        match FileLock::try_lock(touch(path), kind).unwrap() {
+            Some(_) => ACQUIRED,
+            None => BLOCKED,
+        }
+    }
+
+    // This is not a real test, it is what the child processes run.
+    // The outcome is reported through the exit code
+    #[test]
+    #[ignore]
+    fn child_process() {
+        let action = match env::var(ACTION_VAR) {
+            Ok(action) => action,
+            Err(_) => return,
+        };
+        let path = PathBuf::from(env::var_os(PATH_VAR).unwrap());
+        let code = match action.as_str() {
+            "try-shared" => try_lock_code(&path, LockKind::Shared),
+            "try-exclusive" => try_lock_code(&path, LockKind::Exclusive),
+            "pid-lock" => match PidLock::acquire(&path) {
+                Ok(_) => ACQUIRED,
+                Err(PidLockError::Held(_)) => BLOCKED,
+                Err(e) => panic!("Failed to acquire lock file: {}", e),
+            },
+            "hold-exclusive" => {
+                let lock = FileLock::lock(touch(&path), LockKind::Exclusive).unwrap();
+                File::create(path.with_extension("ready")).unwrap();
+                thread::sleep(Duration::from_millis(300));
+                drop(lock);
+                ACQUIRED
+            }
+            _ => panic!("Unknown action {}", action),
+        };
+        process::exit(code);
+    }
+
+    #[test]
+    fn exclusive_lock_excludes_other_processes() {
+        let path = temp_path("exclusive");
+        let lock = FileLock::lock(touch(&path), LockKind::Exclusive).unwrap();
+        assert_eq!(BLOCKED, run_child("try-exclusive", &path));
+        assert_eq!(BLOCKED, run_child("try-shared", &path));
+
+        drop(lock);
+        assert_eq!(ACQUIRED, run_child("try-exclusive", &path));
+    }
+
+    #[test]
+    fn shared_locks_are_compatible() {
+        let path = temp_path("shared");
+        let lock = FileLock::lock(touch(&path), LockKind::Shared).unwrap();
+        assert_eq!(ACQUIRED, run_child("try-shared", &path));
+        assert_eq!(BLOCKED, run_child("try-exclusive", &path));
+
+        // Unlocking early keeps the file usable
+        let mut file = lock.unlock().unwrap();
+        assert_eq!(ACQUIRED, run_child("try-exclusive", &path));
+        file.write_all(b"still open").unwrap();
+    }
+
+    #[test]
+    fn blocking_lock_waits_for_release() {
+        let path = temp_path("blocking");
+        let mut child = spawn_child("hold-exclusive", &path);
+
+        let ready = path.with_extension("ready");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !ready.exists() {
+            assert!(Instant::now() < deadline, "Child never took the lock");
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let start = Instant::now();
+        assert!(FileLock::try_lock(touch(&path), LockKind::Shared)
+            .unwrap()
+            .is_none());
+        let lock = FileLock::lock(touch(&path), LockKind::Shared).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(LockKind::Shared, lock.kind());
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn appends_whole_lines() {
+        let path = temp_path("append");
+        for i in 0..3 {
+            append_locked(&path, &format!("line {}", i)).unwrap();
+        }
+        assert_eq!(
+            "line 0\nline 1\nline 2\n",
+            fs::read_to_string(&path).unwrap()
+        );
+    }
+
+    #[test]
+    fn pid_lock_records_holder() {
+        let path = temp_path("pid");
+        let lock = PidLock::acquire(&path).unwrap();
+        assert_eq!(None, lock.stale_pid());
+        assert_eq!(
+            format!("{}\n", process::id()),
+            fs::read_to_string(&path).unwrap()
+        );
+
+        assert_eq!(BLOCKED, run_child("pid-lock", &path));
+        match PidLock::acquire(&path) {
+            Err(PidLockError::Held(Some(pid))) => assert_eq!(process::id(), pid),
+            other => panic!("Expected the lock to be held, got {:?}", other),
+        }
+
+        // The lock file is removed again when the lock is dropped
+        drop(lock);
+        assert!(!path.exists());
+        assert_eq!(ACQUIRED, run_child("pid-lock", &path));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn pid_lock_replaces_stale_locks() {
+        let path = temp_path("stale");
+        // Get the PID of a process that has already exited
+        let mut child = Command::new(env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+        fs::write(&path, format!("{}\n", dead_pid)).unwrap();
+
+        let lock = PidLock::acquire(&path).unwrap();
+        assert_eq!(Some(dead_pid), lock.stale_pid());
+        assert_eq!(
+            format!("{}\n", process::id()),
+            fs::read_to_string(&path).unwrap()
+        );
+    }
+
+    #[test]
+    fn pid_lock_trusts_flock_over_reused_pids() {
+        let path = temp_path("reused");
+        // The init process is always alive, but it doesn't hold the flock
+        fs::write(&path, "1\n").unwrap();
+        let lock = PidLock::acquire(&path).unwrap();
+        assert_eq!(Some(1), lock.stale_pid());
+        drop(lock);
+
+        // ...unless flock can't be relied on
+        fs::write(&path, "1\n").unwrap();
+        match PidLock::acquire_checking_pid(&path) {
+            Err(PidLockError::Held(Some(1))) => {}
+            other => panic!("Expected the lock to be held, got {:?}", other),
+        }
+        assert_eq!("1\n", fs::read_to_string(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pid_lock_ignores_invalid_pids() {
+        let path = temp_path("invalid");
+        for content in &["0\n", "2147483648\n", "4294967295\n", "-1\n"] {
+            fs::write(&path, content).unwrap();
+            let lock = PidLock::acquire_checking_pid(&path).unwrap();
+            assert_eq!(None, lock.stale_pid());
+        }
+        assert!(!path.exists());
+    }
+}
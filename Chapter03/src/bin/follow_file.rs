@@ -0,0 +1,452 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    // Behaves like "tail -f", by default starting with the last 10 lines
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (start, path) = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["--from-start", path] => (StartAt::Beginning, path),
+        ["--from-end", path] => (StartAt::End, path),
+        ["-n", count, path] => (
+            StartAt::LastLines(count.parse().expect("Failed to parse line count")),
+            path,
+        ),
+        [path] => (StartAt::LastLines(10), path),
+        _ => {
+            eprintln!("Usage: follow_file [--from-start | --from-end | -n <count>] <path>");
+            process::exit(2);
+        }
+    };
+    println!("Following '{}', press Ctrl+C to stop", path);
+    let follower =
+        Follower::new(path, start, Duration::from_millis(250)).expect("Failed to open file");
+    for line in follower {
+        println!("{}", line.expect("Failed to read line"));
+    }
+}
+
+// Where to start reading when the follower is created
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StartAt {
+    Beginning,
+    End,
+    LastLines(usize),
+}
+
+// Identifies a file independent of its name,
+// so we notice when a log file was rotated
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileId {
+    device: u64,
+    inode: u64,
+}
+
+impl FileId {
+    #[cfg(unix)]
+    fn of(metadata: &fs::Metadata) -> Option<FileId> {
+        use std::os::unix::fs::MetadataExt;
+        Some(FileId {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn of(_metadata: &fs::Metadata) -> Option<FileId> {
+        None
+    }
+}
+
+// How many of the last read bytes we remember to detect truncation
+const FINGERPRINT_LEN: usize = 64;
+
+// Yields new lines of a file as they are appended.
+// It only polls, so it works on any filesystem without inotify
+struct Follower {
+    path: PathBuf,
+    interval: Duration,
+    reader: Option<BufReader<File>>,
+    file_id: Option<FileId>,
+    // How many bytes of the current file we have read
+    position: u64,
+    // The last bytes we read before position
+    fingerprint: Vec<u8>,
+    // The start of a line that has no newline yet
+    partial: Vec<u8>,
+    lines: VecDeque<String>,
+}
+
+impl Follower {
+    fn new<P: AsRef<Path>>(path: P, start: StartAt, interval: Duration) -> io::Result<Follower> {
+        let mut follower = Follower {
+            path: path.as_ref().to_path_buf(),
+            interval,
+            reader: None,
+            file_id: None,
+            position: 0,
+            fingerprint: Vec::new(),
+            partial: Vec::new(),
+            lines: VecDeque::new(),
+        };
+        // The file doesn't have to exist yet
+        if let Some(mut file) = follower.try_open()? {
+            follower.position = match start {
+                StartAt::Beginning => 0,
+                StartAt::End => file.seek(SeekFrom::End(0))?,
+                StartAt::LastLines(count) => start_of_last_lines(&mut file, count)?,
+            };
+            follower.fingerprint = read_fingerprint(&mut file, follower.position)?;
+            file.seek(SeekFrom::Start(follower.position))?;
+            follower.reader = Some(BufReader::new(file));
+        }
+        Ok(follower)
+    }
+
+    fn try_open(&mut self) -> io::Result<Option<File>> {
+        match File::open(&self.path) {
+            Ok(file) => {
+                self.file_id = FileId::of(&file.metadata()?);
+                Ok(Some(file))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Check the file once and queue all complete lines
+    // that were written since the last call
+    fn poll(&mut self) -> io::Result<()> {
+        if self.reader.is_none() {
+            match self.try_open()? {
+                Some(file) => self.start_reading(file),
+                None => return Ok(()),
+            }
+        }
+
+        // If the file was truncated, we start over at the beginning
+        if self.was_truncated()? {
+            let file = self.reader.take().expect("A file is open").into_inner();
+            self.start_reading(file);
+            self.current_file()?.seek(SeekFrom::Start(0))?;
+        }
+        self.read_available()?;
+
+        // A different file under our path means it was rotated.
+        // Everything that was still written to the old file has
+        // been read above, so we can safely switch over. An unfinished
+        // last line won't be finished anymore, so it's handed out as it is
+        if self.was_rotated() {
+            if !self.partial.is_empty() {
+                let line = String::from_utf8_lossy(&self.partial).into_owned();
+                self.lines.push_back(line);
+            }
+            self.reader = None;
+            if let Some(file) = self.try_open()? {
+                self.start_reading(file);
+                self.read_available()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn current_file(&mut self) -> io::Result<&mut File> {
+        self.reader
+            .as_mut()
+            .map(BufReader::get_mut)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No file is open"))
+    }
+
+    // Read a file from its current position on, forgetting everything
+    // about the previous one, including buffered data
+    fn start_reading(&mut self, file: File) {
+        self.reader = Some(BufReader::new(file));
+        self.position = 0;
+        self.fingerprint.clear();
+        self.partial.clear();
+    }
+
+    fn was_truncated(&mut self) -> io::Result<bool> {
+        let position = self.position;
+        let file = self.current_file()?;
+        // A shorter file was definitely truncated
+        if file.metadata()?.len() < position {
+            return Ok(true);
+        }
+        // But it could also have been truncated and refilled since the
+        // last poll, which we notice by the bytes before our position
+        // having changed. The reader's buffer is always empty here,
+        // as we read everything up to the end on every poll
+        match read_fingerprint(file, position) {
+            Ok(current) => Ok(current != self.fingerprint),
+            // It was truncated right after we checked its length
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn was_rotated(&self) -> bool {
+        match fs::metadata(&self.path) {
+            Ok(metadata) => FileId::of(&metadata) != self.file_id,
+            // While the new file has not been created yet,
+            // we keep reading the old one
+            Err(_) => false,
+        }
+    }
+
+    fn read_available(&mut self) -> io::Result<()> {
+        let reader = match self.reader {
+            Some(ref mut reader) => reader,
+            None => return Ok(()),
+        };
+        loop {
+            let read = reader.read_until(b'\n', &mut self.partial)?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.position += read as u64;
+            let new_bytes = &self.partial[self.partial.len() - read..];
+            self.fingerprint.extend_from_slice(new_bytes);
+            let excess = self.fingerprint.len().saturating_sub(FINGERPRINT_LEN);
+            self.fingerprint.drain(..excess);
+            // Incomplete lines stay in partial until the rest arrives
+            if self.partial.ends_with(b"\n") {
+                self.partial.pop();
+                if self.partial.ends_with(b"\r") {
+                    self.partial.pop();
+                }
+                let line = String::from_utf8_lossy(&self.partial).into_owned();
+                self.lines.push_back(line);
+                self.partial.clear();
+            }
+        }
+    }
+
+    // Returns the next line if one is available right now
+    fn try_next(&mut self) -> io::Result<Option<String>> {
+        if self.lines.is_empty() {
+            self.poll()?;
+        }
+        Ok(self.lines.pop_front())
+    }
+}
+
+impl Iterator for Follower {
+    type Item = io::Result<String>;
+
+    // Blocks until a new line arrives
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.try_next() {
+                Ok(Some(line)) => return Some(Ok(line)),
+                Ok(None) => thread::sleep(self.interval),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+// Read the bytes right before position and leave
+// the file at position afterwards
+fn read_fingerprint(file: &mut File, position: u64) -> io::Result<Vec<u8>> {
+    let start = position.saturating_sub(FINGERPRINT_LEN as u64);
+    let mut fingerprint = vec![0; (position - start) as usize];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut fingerprint)?;
+    Ok(fingerprint)
+}
+
+// Find the offset of the last count lines by
+// reading the file backwards in blocks
+fn start_of_last_lines(file: &mut File, count: usize) -> io::Result<u64> {
+    const BLOCK_SIZE: u64 = 4096;
+    let len = file.seek(SeekFrom::End(0))?;
+    if count == 0 {
+        return Ok(len);
+    }
+    let mut end = len;
+    let mut newlines = 0;
+    let mut block = vec![0; BLOCK_SIZE as usize];
+    while end > 0 {
+        let start = end.saturating_sub(BLOCK_SIZE);
+        let block = &mut block[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(block)?;
+        for (i, &byte) in block.iter().enumerate().rev() {
+            let offset = start + i as u64;
+            // The newline that ends the last line doesn't count
+            if byte == b'\n' && offset != len - 1 {
+                newlines += 1;
+                if newlines == count {
+                    return Ok(offset + 1);
+                }
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::process;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    const INTERVAL: Duration = Duration::from_millis(5);
+
+    // A log file path that doesn't exist yet
+    fn temp_file(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("follow-{}-{}.log", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn append(path: &Path, content: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    // Collect lines until we have the expected amount or time runs out
+    fn collect(follower: &mut Follower, count: usize) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut lines = Vec::new();
+        while lines.len() < count && Instant::now() < deadline {
+            match follower.try_next().unwrap() {
+                Some(line) => lines.push(line),
+                None => thread::sleep(INTERVAL),
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn starts_at_the_requested_position() {
+        let path = temp_file("start");
+        append(&path, "one\ntwo\nthree\n");
+
+        let mut follower = Follower::new(&path, StartAt::Beginning, INTERVAL).unwrap();
+        assert_eq!(vec!["one", "two", "three"], collect(&mut follower, 3));
+
+        let mut follower = Follower::new(&path, StartAt::LastLines(2), INTERVAL).unwrap();
+        assert_eq!(vec!["two", "three"], collect(&mut follower, 2));
+
+        let mut follower = Follower::new(&path, StartAt::LastLines(10), INTERVAL).unwrap();
+        assert_eq!(vec!["one", "two", "three"], collect(&mut follower, 3));
+
+        let mut follower = Follower::new(&path, StartAt::End, INTERVAL).unwrap();
+        assert_eq!(None, follower.try_next().unwrap());
+        append(&path, "four\n");
+        assert_eq!(vec!["four"], collect(&mut follower, 1));
+    }
+
+    #[test]
+    fn finds_last_lines_across_blocks() {
+        let path = temp_file("blocks");
+        let lines: Vec<String> = (0..2000).map(|i| format!("line number {}", i)).collect();
+        append(&path, &(lines.join("\n") + "\n"));
+
+        let mut follower = Follower::new(&path, StartAt::LastLines(500), INTERVAL).unwrap();
+        assert_eq!(&lines[1500..], &collect(&mut follower, 500)[..]);
+    }
+
+    #[test]
+    fn waits_for_complete_lines() {
+        let path = temp_file("partial");
+        let mut follower = Follower::new(&path, StartAt::Beginning, INTERVAL).unwrap();
+        append(&path, "Hello ");
+        assert_eq!(None, follower.try_next().unwrap());
+        append(&path, "World!\r\nBye");
+        assert_eq!(vec!["Hello World!"], collect(&mut follower, 1));
+    }
+
+    #[test]
+    fn follows_appends_truncation_and_rotation() {
+        let path = temp_file("writer");
+        let (step_done, wait_for_step) = mpsc::channel();
+        let (next_step, wait_for_reader) = mpsc::channel::<()>();
+
+        // The writer does one step at a time and waits for
+        // the reader to catch up before doing the next
+        let writer_path = path.clone();
+        let writer = thread::spawn(move || {
+            append(&writer_path, "first\n");
+            append(&writer_path, "second\n");
+            step_done.send(()).unwrap();
+            wait_for_reader.recv().unwrap();
+
+            // Truncate and write more than before, so that only
+            // the changed content tells us about the truncation
+            File::create(&writer_path).unwrap();
+            append(&writer_path, "after truncation\n");
+            step_done.send(()).unwrap();
+            wait_for_reader.recv().unwrap();
+
+            // Rotate the log like logrotate does
+            let rotated = writer_path.with_extension("log.1");
+            append(&writer_path, "last line before rotation\n");
+            fs::rename(&writer_path, &rotated).unwrap();
+            append(&rotated, "written to the old file\n");
+            append(&writer_path, "first line after rotation\n");
+            step_done.send(()).unwrap();
+        });
+
+        let mut follower = Follower::new(&path, StartAt::Beginning, INTERVAL).unwrap();
+        wait_for_step.recv().unwrap();
+        assert_eq!(vec!["first", "second"], collect(&mut follower, 2));
+        next_step.send(()).unwrap();
+
+        wait_for_step.recv().unwrap();
+        assert_eq!(vec!["after truncation"], collect(&mut follower, 1));
+        next_step.send(()).unwrap();
+
+        wait_for_step.recv().unwrap();
+        assert_eq!(
+            vec![
+                "last line before rotation",
+                "written to the old file",
+                "first line after rotation",
+            ],
+            collect(&mut follower, 3)
+        );
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn keeps_unfinished_lines_of_rotated_files() {
+        let path = temp_file("unfinished");
+        let mut follower = Follower::new(&path, StartAt::Beginning, INTERVAL).unwrap();
+        append(&path, "complete\ncut off by a crash");
+        assert_eq!(vec!["complete"], collect(&mut follower, 1));
+
+        let rotated = path.with_extension("log.1");
+        fs::rename(&path, &rotated).unwrap();
+        append(&path, "restarted\n");
+        assert_eq!(
+            vec!["cut off by a crash", "restarted"],
+            collect(&mut follower, 2)
+        );
+        fs::remove_file(&rotated).unwrap();
+    }
+
+    #[test]
+    fn waits_for_the_file_to_appear() {
+        let path = temp_file("missing");
+        let mut follower = Follower::new(&path, StartAt::End, INTERVAL).unwrap();
+        assert_eq!(None, follower.try_next().unwrap());
+        append(&path, "finally\n");
+        assert_eq!(vec!["finally"], collect(&mut follower, 1));
+    }
+}
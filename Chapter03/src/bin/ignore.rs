@@ -0,0 +1,613 @@
+extern crate walkdir;
+
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use walkdir::{DirEntry, WalkDir};
+
+fn main() {
+    let root = env::args().nth(1).unwrap_or_else(|| ".".to_string());
+    println!(
+        "All files in {} that are not ignored by a .gitignore:",
+        root
+    );
+    for entry in walk_not_ignored(&root, ".gitignore").filter_map(Result::ok) {
+        println!("{}", entry.path().display());
+    }
+
+    // A matcher can also be used without touching the filesystem
+    let matcher = Matcher::new(&["target/", "*.rs", "!src/bin/*.rs", "/ferris*"])
+        .expect("Failed to compile patterns");
+    for &(path, is_dir) in &[
+        ("target", true),
+        ("src/lib.rs", false),
+        ("src/bin/glob.rs", false),
+        ("ferris.png", false),
+        ("assets/ferris.png", false),
+    ] {
+        println!("Is {} ignored? {}", path, matcher.is_ignored(path, is_dir));
+    }
+}
+
+// The outcome of matching a path against a list of patterns
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Match {
+    // No pattern matched
+    None,
+    // The last matching pattern excludes the path
+    Ignore,
+    // The last matching pattern was negated with "!"
+    Whitelist,
+}
+
+#[derive(Debug, PartialEq)]
+struct PatternError {
+    pattern: String,
+    // Line number in the ignore file, starting at 1
+    line: usize,
+    reason: &'static str,
+}
+
+impl error::Error for PatternError {}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid pattern '{}' on line {}: {}",
+            self.pattern, self.line, self.reason
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    // "?" matches any single character except "/"
+    AnyChar,
+    // "*" matches anything except "/"
+    Star,
+    // A leading "**/" matches zero or more directories
+    AnyDirectories,
+    // A trailing "/**" matches everything inside a directory
+    AnyPath,
+    // "[a-z]" or "[!0-9]"
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+// A single line of an ignore file
+#[derive(Debug)]
+struct Rule {
+    tokens: Vec<Token>,
+    negated: bool,
+    directory_only: bool,
+}
+
+impl Rule {
+    // Returns None for blank lines and comments
+    fn parse(line: &str, line_number: usize) -> Result<Option<Rule>, PatternError> {
+        let error = |reason| PatternError {
+            pattern: line.to_string(),
+            line: line_number,
+            reason,
+        };
+
+        let mut pattern = trim_trailing_spaces(line);
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return Ok(None);
+        }
+        let negated = pattern.starts_with('!');
+        if negated {
+            pattern = &pattern[1..];
+        }
+        // A trailing slash only matches directories
+        let directory_only = pattern.ends_with('/') && !pattern.ends_with("\\/");
+        if directory_only {
+            pattern = &pattern[..pattern.len() - 1];
+        }
+        // A slash at the beginning or in the middle anchors the pattern
+        // to the directory of the ignore file. Otherwise it matches
+        // a name at any depth, as if it started with "**/"
+        let anchored = pattern.contains('/');
+        pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            return Err(error("Pattern is empty"));
+        }
+
+        let mut tokens = if anchored {
+            Vec::new()
+        } else {
+            vec![Token::AnyDirectories]
+        };
+        tokens.extend(tokenize(pattern).map_err(error)?);
+        Ok(Some(Rule {
+            tokens,
+            negated,
+            directory_only,
+        }))
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        let path: Vec<char> = path.chars().collect();
+        let mut memo = vec![None; (self.tokens.len() + 1) * (path.len() + 1)];
+        matches_from(&self.tokens, &path, 0, 0, &mut memo)
+    }
+}
+
+// Unescaped trailing spaces are ignored
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = line.len();
+    while line[..end].ends_with(' ') && !line[..end].ends_with("\\ ") {
+        end -= 1;
+    }
+    &line[..end]
+}
+
+fn tokenize(pattern: &str) -> Result<Vec<Token>, &'static str> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let at_segment_start = i == 0 || chars[i - 1] == '/';
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') && at_segment_start => {
+                // "**" is only special as a whole path segment
+                match chars.get(i + 2) {
+                    Some(&'/') => {
+                        tokens.push(Token::AnyDirectories);
+                        i += 3;
+                    }
+                    None if i > 0 => {
+                        tokens.push(Token::AnyPath);
+                        i += 2;
+                    }
+                    // A pattern of just "**" matches everything
+                    None => {
+                        tokens.push(Token::AnyDirectories);
+                        tokens.push(Token::Star);
+                        i += 2;
+                    }
+                    Some(_) => {
+                        tokens.push(Token::Star);
+                        i += 2;
+                    }
+                }
+            }
+            '*' => {
+                // Several stars in a row behave like a single one
+                if tokens.last() != Some(&Token::Star) {
+                    tokens.push(Token::Star);
+                }
+                i += 1;
+            }
+            '?' => {
+                tokens.push(Token::AnyChar);
+                i += 1;
+            }
+            '[' => {
+                let (token, len) = parse_class(&chars[i..])?;
+                tokens.push(token);
+                i += len;
+            }
+            '\\' => {
+                let escaped = chars.get(i + 1).ok_or("Pattern ends with a backslash")?;
+                tokens.push(Token::Literal(*escaped));
+                i += 2;
+            }
+            c => {
+                tokens.push(Token::Literal(c));
+                i += 1;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// Parse a character class starting at "[" and return
+// it together with the number of characters it spans
+fn parse_class(chars: &[char]) -> Result<(Token, usize), &'static str> {
+    let mut i = 1;
+    let negated = match chars.get(i) {
+        Some(&'!') | Some(&'^') => {
+            i += 1;
+            true
+        }
+        _ => false,
+    };
+    let mut ranges = Vec::new();
+    // A "]" right at the start is part of the class
+    let mut first = true;
+    loop {
+        let c = match chars.get(i) {
+            None => return Err("Unclosed character class"),
+            Some(&']') if !first => break,
+            Some(&'\\') => {
+                i += 1;
+                *chars.get(i).ok_or("Unclosed character class")?
+            }
+            Some(&c) => c,
+        };
+        first = false;
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&end| end != ']') {
+            let end = chars[i + 2];
+            if end < c {
+                return Err("Character range is out of order");
+            }
+            ranges.push((c, end));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    Ok((Token::Class { negated, ranges }, i + 1))
+}
+
+// Match tokens[t..] against path[p..], remembering
+// results so that patterns with many stars stay fast
+fn matches_from(
+    tokens: &[Token],
+    path: &[char],
+    t: usize,
+    p: usize,
+    memo: &mut Vec<Option<bool>>,
+) -> bool {
+    let key = t * (path.len() + 1) + p;
+    if let Some(result) = memo[key] {
+        return result;
+    }
+    let result = match tokens.get(t) {
+        None => p == path.len(),
+        Some(&Token::Literal(c)) => {
+            path.get(p) == Some(&c) && matches_from(tokens, path, t + 1, p + 1, memo)
+        }
+        Some(&Token::AnyChar) => {
+            path.get(p).is_some_and(|&c| c != '/') && matches_from(tokens, path, t + 1, p + 1, memo)
+        }
+        Some(&Token::Class {
+            negated,
+            ref ranges,
+        }) => {
+            path.get(p).is_some_and(|&c| {
+                c != '/' && ranges.iter().any(|&(start, end)| start <= c && c <= end) != negated
+            }) && matches_from(tokens, path, t + 1, p + 1, memo)
+        }
+        Some(&Token::Star) => {
+            // Either match nothing or eat one more character
+            matches_from(tokens, path, t + 1, p, memo)
+                || (path.get(p).is_some_and(|&c| c != '/')
+                    && matches_from(tokens, path, t, p + 1, memo))
+        }
+        Some(&Token::AnyDirectories) => {
+            // Skip zero or more complete directories
+            matches_from(tokens, path, t + 1, p, memo)
+                || (p..path.len())
+                    .filter(|&i| path[i] == '/')
+                    .any(|i| matches_from(tokens, path, t + 1, i + 1, memo))
+        }
+        Some(&Token::AnyPath) => p < path.len(),
+    };
+    memo[key] = Some(result);
+    result
+}
+
+// A compiled list of patterns, like the content of one .gitignore
+#[derive(Debug, Default)]
+struct Matcher {
+    rules: Vec<Rule>,
+}
+
+impl Matcher {
+    fn new(patterns: &[&str]) -> Result<Matcher, PatternError> {
+        let mut matcher = Matcher::default();
+        for (i, pattern) in patterns.iter().enumerate() {
+            matcher.add(pattern, i + 1)?;
+        }
+        Ok(matcher)
+    }
+
+    fn from_reader<R: BufRead>(reader: R) -> io::Result<Matcher> {
+        let mut matcher = Matcher::default();
+        for (i, line) in reader.lines().enumerate() {
+            matcher
+                .add(&line?, i + 1)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        }
+        Ok(matcher)
+    }
+
+    fn add(&mut self, pattern: &str, line: usize) -> Result<(), PatternError> {
+        if let Some(rule) = Rule::parse(pattern, line)? {
+            self.rules.push(rule);
+        }
+        Ok(())
+    }
+
+    // Match a path relative to the directory of the patterns,
+    // with components separated by "/".
+    // Like git, everything inside an ignored directory is ignored
+    // and can't be whitelisted again, so the parents are checked first
+    fn matched(&self, path: &str, is_dir: bool) -> Match {
+        let path = path.trim_matches('/');
+        let mut parents = path.match_indices('/').map(|(i, _)| &path[..i]);
+        if parents.any(|parent| self.matched_path(parent, true) == Match::Ignore) {
+            return Match::Ignore;
+        }
+        self.matched_path(path, is_dir)
+    }
+
+    // The last matching pattern wins
+    fn matched_path(&self, path: &str, is_dir: bool) -> Match {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .map(|rule| {
+                if rule.negated {
+                    Match::Whitelist
+                } else {
+                    Match::Ignore
+                }
+            })
+            .unwrap_or(Match::None)
+    }
+
+    fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        self.matched(path, is_dir) == Match::Ignore
+    }
+}
+
+// The ignore files that apply to the current position of a walk
+#[derive(Default)]
+struct IgnoreStack {
+    // The depth of the directory each matcher belongs to,
+    // its path relative to the root and its patterns
+    matchers: Vec<(usize, String, Matcher)>,
+}
+
+impl IgnoreStack {
+    // Ignore files deeper down take precedence over the ones above them
+    fn matched(&self, relative_path: &str, is_dir: bool) -> Match {
+        for (_, base, matcher) in self.matchers.iter().rev() {
+            let path = if base.is_empty() {
+                relative_path
+            } else {
+                &relative_path[base.len() + 1..]
+            };
+            match matcher.matched(path, is_dir) {
+                Match::None => continue,
+                result => return result,
+            }
+        }
+        Match::None
+    }
+
+    // Forget everything that belongs to directories we left
+    fn leave_to_depth(&mut self, depth: usize) {
+        while self.matchers.last().is_some_and(|m| m.0 >= depth) {
+            self.matchers.pop();
+        }
+    }
+}
+
+// Walk through root like WalkDir, but skip everything
+// that is ignored by an ignore file on the way down
+fn walk_not_ignored<P: AsRef<Path>>(
+    root: P,
+    ignore_file_name: &str,
+) -> impl Iterator<Item = walkdir::Result<DirEntry>> {
+    let root = root.as_ref().to_path_buf();
+    let ignore_file_name = ignore_file_name.to_string();
+    let mut stack = IgnoreStack::default();
+    WalkDir::new(&root)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(move |entry| {
+            // WalkDir goes depth first, so every matcher
+            // at this depth or deeper belongs to a sibling
+            stack.leave_to_depth(entry.depth());
+            let is_dir = entry.file_type().is_dir();
+            if entry.depth() > 0 {
+                let relative = relative_path(&root, entry.path());
+                if stack.matched(&relative, is_dir) == Match::Ignore {
+                    // This also skips everything inside ignored directories
+                    return false;
+                }
+            }
+            if is_dir {
+                let ignore_file = entry.path().join(&ignore_file_name);
+                if ignore_file.is_file() {
+                    match File::open(&ignore_file)
+                        .and_then(|f| Matcher::from_reader(BufReader::new(f)))
+                    {
+                        Ok(matcher) => {
+                            let base = relative_path(&root, entry.path());
+                            stack.matchers.push((entry.depth(), base, matcher));
+                        }
+                        Err(err) => eprintln!("Skipping {}: {}", ignore_file.display(), err),
+                    }
+                }
+            }
+            true
+        })
+}
+
+// Convert a path below root into the "/" separated form the patterns use
+fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn ignored(patterns: &[&str], path: &str, is_dir: bool) -> bool {
+        Matcher::new(patterns).unwrap().is_ignored(path, is_dir)
+    }
+
+    #[test]
+    fn matches_names_at_any_depth() {
+        assert!(ignored(&["*.log"], "debug.log", false));
+        assert!(ignored(&["*.log"], "logs/debug.log", false));
+        assert!(ignored(&["debug.log"], "a/b/debug.log", false));
+        assert!(!ignored(&["*.log"], "debug.log.txt", false));
+        assert!(!ignored(&["*.log"], "logs", true));
+    }
+
+    #[test]
+    fn anchors_patterns_with_slashes() {
+        assert!(ignored(&["/debug.log"], "debug.log", false));
+        assert!(!ignored(&["/debug.log"], "logs/debug.log", false));
+        assert!(ignored(&["logs/debug.log"], "logs/debug.log", false));
+        assert!(!ignored(&["logs/debug.log"], "build/logs/debug.log", false));
+        // "*" never matches a slash
+        assert!(!ignored(&["logs/*.log"], "logs/old/debug.log", false));
+    }
+
+    #[test]
+    fn respects_directory_only_patterns() {
+        assert!(ignored(&["build/"], "build", true));
+        assert!(ignored(&["build/"], "src/build", true));
+        assert!(!ignored(&["build/"], "build", false));
+    }
+
+    #[test]
+    fn supports_double_stars() {
+        assert!(ignored(&["**/logs"], "logs", true));
+        assert!(ignored(&["**/logs"], "a/b/logs", true));
+        assert!(ignored(&["logs/**"], "logs/debug.log", false));
+        assert!(ignored(&["logs/**"], "logs/a/b/debug.log", false));
+        assert!(!ignored(&["logs/**"], "logs", true));
+        assert!(ignored(&["a/**/b"], "a/b", false));
+        assert!(ignored(&["a/**/b"], "a/x/y/b", false));
+        assert!(!ignored(&["a/**/b"], "a/xb", false));
+        assert!(ignored(&["**"], "anything/at/all", false));
+    }
+
+    #[test]
+    fn last_match_wins() {
+        let patterns = ["*.log", "!important.log", "trace*.log"];
+        assert!(ignored(&patterns, "debug.log", false));
+        assert!(!ignored(&patterns, "important.log", false));
+        assert!(ignored(&patterns, "trace.log", false));
+        assert!(ignored(&["!a.txt", "*.txt"], "a.txt", false));
+        assert_eq!(
+            Match::Whitelist,
+            Matcher::new(&patterns)
+                .unwrap()
+                .matched("important.log", false)
+        );
+        assert_eq!(
+            Match::None,
+            Matcher::new(&patterns).unwrap().matched("main.rs", false)
+        );
+    }
+
+    #[test]
+    fn ignores_everything_inside_ignored_directories() {
+        assert!(ignored(&["target/"], "target/debug/app", false));
+        assert!(ignored(&["target/"], "crate/target/debug", true));
+        assert!(!ignored(&["target/"], "src/target.rs", false));
+        // A file can't be whitelisted again once its directory is ignored
+        assert!(ignored(
+            &["logs/", "!logs/keep.log"],
+            "logs/keep.log",
+            false
+        ));
+        assert!(!ignored(
+            &["logs/*", "!logs/keep.log"],
+            "logs/keep.log",
+            false
+        ));
+    }
+
+    #[test]
+    fn supports_classes_and_escapes() {
+        assert!(ignored(&["file[0-9].txt"], "file7.txt", false));
+        assert!(!ignored(&["file[!0-9].txt"], "file7.txt", false));
+        assert!(ignored(&["file[!0-9].txt"], "fileA.txt", false));
+        assert!(ignored(&["what?.md"], "whatX.md", false));
+        assert!(ignored(&["\\#notes"], "#notes", false));
+        assert!(ignored(&["\\!bang"], "!bang", false));
+        assert!(ignored(&["trailing\\ "], "trailing ", false));
+        assert!(ignored(&["trailing   "], "trailing", false));
+    }
+
+    #[test]
+    fn skips_comments_and_reports_errors() {
+        let matcher = Matcher::from_reader(&b"# a comment\n\n*.tmp\n"[..]).unwrap();
+        assert_eq!(1, matcher.rules.len());
+        assert_eq!(
+            Err(PatternError {
+                pattern: "[abc".to_string(),
+                line: 2,
+                reason: "Unclosed character class",
+            }),
+            Matcher::new(&["ok", "[abc"]).map(|_| ())
+        );
+        assert!(Matcher::new(&["/"]).is_err());
+    }
+
+    #[test]
+    fn reads_nested_ignore_files_while_walking() {
+        let root: PathBuf = env::temp_dir().join(format!("ignore-walk-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in &["src/generated", "target/debug", "docs/drafts"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let files = [
+            (".gitignore", "target/\n*.bak\n"),
+            ("main.rs", ""),
+            ("main.rs.bak", ""),
+            ("target/debug/app", ""),
+            ("src/.gitignore", "generated/\n!keep.bak\n"),
+            ("src/lib.rs", ""),
+            ("src/keep.bak", ""),
+            ("src/generated/code.rs", ""),
+            ("docs/.gitignore", "/drafts\n"),
+            ("docs/guide.md", ""),
+            ("docs/drafts/wip.md", ""),
+        ];
+        for &(path, content) in &files {
+            fs::write(root.join(path), content).unwrap();
+        }
+
+        let found: Vec<String> = walk_not_ignored(&root, ".gitignore")
+            .filter_map(Result::ok)
+            .map(|entry| relative_path(&root, entry.path()))
+            .collect();
+        assert_eq!(
+            vec![
+                "",
+                ".gitignore",
+                "docs",
+                "docs/.gitignore",
+                "docs/guide.md",
+                "main.rs",
+                "src",
+                "src/.gitignore",
+                "src/keep.bak",
+                "src/lib.rs",
+            ],
+            found
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,612 @@
+extern crate walkdir;
+
+use std::env;
+use std::fs::{self, FileType};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{error, fmt, io, vec};
+use walkdir::WalkDir;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["--bench"] => bench(100_000),
+        ["--bench", count] => bench(count.parse().expect("Failed to parse file count")),
+        [] => print_tree("."),
+        [root] => print_tree(root),
+        _ => {
+            eprintln!("Usage: parallel_walk [--bench [file count]] [path]");
+            std::process::exit(2);
+        }
+    }
+}
+
+fn print_tree(root: &str) {
+    println!(
+        "All non-hidden paths in '{}', in the order they were found:",
+        root
+    );
+    let walk = ParallelWalk::new(root).filter_entry(|entry| !is_hidden(entry));
+    for entry in walk {
+        match entry {
+            Ok(entry) => println!("{}", entry.path().display()),
+            // An error doesn't stop the walk, we just miss out on some entries
+            Err(e) => eprintln!("Warning: {}", e),
+        }
+    }
+
+    println!("The same paths, sorted and indented:");
+    ParallelWalk::new(root)
+        .filter_entry(|entry| !is_hidden(entry))
+        .sorted(true)
+        .into_iter()
+        .filter_map(Result::ok)
+        .for_each(|entry| {
+            let name = entry.file_name().unwrap_or(entry.path().as_os_str());
+            let slash = if entry.file_type().is_dir() { "/" } else { "" };
+            let indent = "  ".repeat(entry.depth());
+            println!("{}{}{}", indent, name.to_string_lossy(), slash)
+        });
+}
+
+fn is_hidden(entry: &Entry) -> bool {
+    entry
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.starts_with('.'))
+        .unwrap_or(false) // Return false if the filename is invalid UTF8
+}
+
+// A file or directory found by ParallelWalk
+#[derive(Debug, Clone)]
+struct Entry {
+    path: PathBuf,
+    file_type: FileType,
+    depth: usize,
+}
+
+impl Entry {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn file_name(&self) -> Option<&std::ffi::OsStr> {
+        self.path.file_name()
+    }
+
+    // Symlinks are reported as such and never followed,
+    // which means we cannot end up in a loop
+    fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    // How many directories below the root the entry is
+    fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+// A directory or entry that couldn't be read
+#[derive(Debug)]
+struct WalkError {
+    path: PathBuf,
+    error: io::Error,
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+impl error::Error for WalkError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+type WalkResult = Result<Entry, WalkError>;
+type Filter = Arc<dyn Fn(&Entry) -> bool + Send + Sync>;
+
+// Walks a directory tree like WalkDir, but reads
+// the directories on several threads at once
+struct ParallelWalk {
+    root: PathBuf,
+    threads: usize,
+    sorted: bool,
+    filter: Option<Filter>,
+}
+
+impl ParallelWalk {
+    fn new<P: AsRef<Path>>(root: P) -> Self {
+        let threads = thread::available_parallelism()
+            .map(|threads| threads.get())
+            .unwrap_or(4);
+        ParallelWalk {
+            root: root.as_ref().to_path_buf(),
+            threads,
+            sorted: false,
+            filter: None,
+        }
+    }
+
+    fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // The threads finish their work in no particular order.
+    // Sorting means waiting for the whole walk to finish
+    // before the first entry can be handed out
+    fn sorted(mut self, sorted: bool) -> Self {
+        self.sorted = sorted;
+        self
+    }
+
+    // Like WalkDir's filter_entry, entries for which the filter
+    // returns false are skipped and their directories are not entered
+    fn filter_entry<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&Entry) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    fn start(self, sender: Sender<Batch>) {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: Vec::new(),
+                active: 0,
+                cancelled: false,
+            }),
+            changed: Condvar::new(),
+            filter: self.filter,
+        });
+
+        // The root is reported like any other entry
+        match fs::symlink_metadata(&self.root) {
+            Ok(metadata) => {
+                let root = Entry {
+                    path: self.root.clone(),
+                    file_type: metadata.file_type(),
+                    depth: 0,
+                };
+                match shared.accepts(&root) {
+                    Ok(true) => {
+                        if root.file_type.is_dir() {
+                            shared.lock().queue.push((root.path.clone(), 0));
+                        }
+                        let _ = sender.send(vec![Ok(root)]);
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        let _ = sender.send(vec![Err(e)]);
+                    }
+                }
+            }
+            Err(error) => {
+                let _ = sender.send(vec![Err(WalkError {
+                    path: self.root,
+                    error,
+                })]);
+                return;
+            }
+        }
+
+        // The walk is over once every worker is done
+        // and dropped its sender
+        for _ in 0..self.threads {
+            let shared = shared.clone();
+            let sender = sender.clone();
+            thread::spawn(move || shared.work(&sender));
+        }
+    }
+}
+
+// Sending every entry on its own would make the channel
+// the bottleneck, so they are sent per directory
+type Batch = Vec<WalkResult>;
+
+impl IntoIterator for ParallelWalk {
+    type Item = WalkResult;
+    type IntoIter = Iter;
+
+    fn into_iter(self) -> Iter {
+        let (sender, receiver) = mpsc::channel();
+        if self.sorted {
+            // Collect everything and sort it by path, which results in
+            // the same order as WalkDir sorted by file name
+            thread::spawn(move || {
+                let (unsorted_sender, unsorted) = mpsc::channel();
+                self.start(unsorted_sender);
+                let mut results: Batch = unsorted.into_iter().flatten().collect();
+                results.sort_by(|a, b| result_path(a).cmp(result_path(b)));
+                let _ = sender.send(results);
+            });
+        } else {
+            self.start(sender);
+        }
+        Iter {
+            batches: receiver.into_iter(),
+            current: Vec::new().into_iter(),
+        }
+    }
+}
+
+struct Iter {
+    batches: mpsc::IntoIter<Batch>,
+    current: vec::IntoIter<WalkResult>,
+}
+
+impl Iterator for Iter {
+    type Item = WalkResult;
+
+    fn next(&mut self) -> Option<WalkResult> {
+        loop {
+            if let Some(result) = self.current.next() {
+                return Some(result);
+            }
+            // Blocks until the next batch arrives or all threads are done
+            self.current = self.batches.next()?.into_iter();
+        }
+    }
+}
+
+fn result_path(result: &WalkResult) -> &Path {
+    match *result {
+        Ok(ref entry) => &entry.path,
+        Err(ref e) => &e.path,
+    }
+}
+
+// Everything the worker threads have in common
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    filter: Option<Filter>,
+}
+
+struct State {
+    // Directories waiting to be read, together with their depth
+    queue: Vec<(PathBuf, usize)>,
+    // How many directories are being read right now.
+    // As long as that's not zero, more work might show up
+    active: usize,
+    // Nobody is listening anymore
+    cancelled: bool,
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("A walker thread panicked")
+    }
+
+    // A panicking filter is reported as an error for the entry. Otherwise
+    // its thread would be gone without ever finishing the directory,
+    // and the other threads would wait for it forever
+    fn accepts(&self, entry: &Entry) -> Result<bool, WalkError> {
+        let filter = match self.filter {
+            Some(ref filter) => filter,
+            None => return Ok(true),
+        };
+        panic::catch_unwind(AssertUnwindSafe(|| filter(entry))).map_err(|_| WalkError {
+            path: entry.path.clone(),
+            error: io::Error::other("The filter panicked"),
+        })
+    }
+
+    fn work(&self, sender: &Sender<Batch>) {
+        while let Some((dir, depth)) = self.next_directory() {
+            let mut subdirectories = Vec::new();
+            let batch = self.read_directory(dir, depth, &mut subdirectories);
+            {
+                // Hand out the new work before sending,
+                // so the other threads don't have to wait
+                let mut state = self.lock();
+                state.active -= 1;
+                state.queue.extend(subdirectories);
+                self.changed.notify_all();
+            }
+            if !batch.is_empty() && sender.send(batch).is_err() {
+                self.lock().cancelled = true;
+                self.changed.notify_all();
+            }
+        }
+    }
+
+    fn next_directory(&self) -> Option<(PathBuf, usize)> {
+        let mut state = self.lock();
+        loop {
+            if state.cancelled {
+                return None;
+            }
+            if let Some(dir) = state.queue.pop() {
+                state.active += 1;
+                return Some(dir);
+            }
+            // Nothing is queued and nobody could queue anything anymore
+            if state.active == 0 {
+                self.changed.notify_all();
+                return None;
+            }
+            state = self.changed.wait(state).expect("A walker thread panicked");
+        }
+    }
+
+    // Collect every entry of a directory and remember the subdirectories
+    fn read_directory(
+        &self,
+        dir: PathBuf,
+        depth: usize,
+        subdirectories: &mut Vec<(PathBuf, usize)>,
+    ) -> Batch {
+        let read_dir = match fs::read_dir(&dir) {
+            Ok(read_dir) => read_dir,
+            Err(error) => return vec![Err(WalkError { path: dir, error })],
+        };
+        let mut batch = Vec::new();
+        for entry in read_dir {
+            let entry = entry.and_then(|entry| {
+                Ok(Entry {
+                    path: entry.path(),
+                    file_type: entry.file_type()?,
+                    depth: depth + 1,
+                })
+            });
+            match entry {
+                Ok(entry) => match self.accepts(&entry) {
+                    Ok(true) => {
+                        if entry.file_type.is_dir() {
+                            subdirectories.push((entry.path.clone(), entry.depth));
+                        }
+                        batch.push(Ok(entry));
+                    }
+                    Ok(false) => {}
+                    Err(e) => batch.push(Err(e)),
+                },
+                Err(error) => batch.push(Err(WalkError {
+                    path: dir.clone(),
+                    error,
+                })),
+            }
+        }
+        batch
+    }
+}
+
+// Compare the parallel walk with WalkDir on a large generated tree.
+// How much the threads help depends on the number of cores and the disk:
+// with a cold cache, most of the time is spent waiting for reads
+fn bench(file_count: usize) {
+    let root = env::temp_dir().join(format!("parallel-walk-bench-{}", file_count));
+    if !root.join("complete").exists() {
+        println!("Generating {} files in '{}'", file_count, root.display());
+        generate_tree(&root, file_count).expect("Failed to generate tree");
+    }
+
+    let sequential = measure(|| {
+        WalkDir::new(&root)
+            .into_iter()
+            .filter_map(Result::ok)
+            .count()
+    });
+    println!("WalkDir:                {:?}", sequential);
+    for &threads in &[1, 2, 4, 8, 16] {
+        let parallel = measure(|| {
+            ParallelWalk::new(&root)
+                .threads(threads)
+                .into_iter()
+                .filter_map(Result::ok)
+                .count()
+        });
+        println!("ParallelWalk, {:>2} threads: {:?}", threads, parallel);
+    }
+    let sorted = measure(|| {
+        ParallelWalk::new(&root)
+            .sorted(true)
+            .into_iter()
+            .filter_map(Result::ok)
+            .count()
+    });
+    println!("ParallelWalk, sorted:   {:?}", sorted);
+}
+
+// The fastest of a few runs, so that the first one
+// doesn't suffer from a cold cache
+fn measure<F: FnMut() -> usize>(mut walk: F) -> Duration {
+    let mut count = None;
+    (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            let found = walk();
+            let elapsed = start.elapsed();
+            assert_eq!(*count.get_or_insert(found), found, "Walks disagree");
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+// 100 files per directory, in a tree that is two directories deep
+fn generate_tree(root: &Path, file_count: usize) -> io::Result<()> {
+    const FILES_PER_DIR: usize = 100;
+    const DIRS_PER_DIR: usize = 10;
+    let _ = fs::remove_dir_all(root);
+    for dir_index in 0..file_count.div_ceil(FILES_PER_DIR) {
+        let dir = root
+            .join(format!("dir{}", dir_index / DIRS_PER_DIR))
+            .join(format!("dir{}", dir_index % DIRS_PER_DIR));
+        fs::create_dir_all(&dir)?;
+        let first = dir_index * FILES_PER_DIR;
+        for file_index in first..file_count.min(first + FILES_PER_DIR) {
+            fs::write(dir.join(format!("file{}", file_index)), b"")?;
+        }
+    }
+    fs::write(root.join("complete"), b"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("parallel-walk-{}-{}", name, process::id()))
+    }
+
+    fn create_tree(name: &str) -> PathBuf {
+        let root = temp_path(name);
+        let _ = fs::remove_dir_all(&root);
+        for dir in &["a/b/c", "a/d", "a.b", "e", ".hidden/f"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in &["x", "a/y", "a/b/c/z", "a/d/w", "a.b/v", ".hidden/f/u"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+        root
+    }
+
+    fn sorted_walkdir(root: &Path) -> Vec<PathBuf> {
+        WalkDir::new(root)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter()
+            .map(|entry| entry.unwrap().into_path())
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_same_entries_as_walkdir() {
+        let root = create_tree("same");
+        for &threads in &[1, 2, 8] {
+            let mut paths: Vec<_> = ParallelWalk::new(&root)
+                .threads(threads)
+                .into_iter()
+                .map(|entry| entry.unwrap().path)
+                .collect();
+            paths.sort();
+            assert_eq!(sorted_walkdir(&root), paths);
+        }
+    }
+
+    #[test]
+    fn sorts_like_walkdir() {
+        let root = create_tree("sorted");
+        let paths: Vec<_> = ParallelWalk::new(&root)
+            .sorted(true)
+            .into_iter()
+            .map(|entry| entry.unwrap().path)
+            .collect();
+        assert_eq!(sorted_walkdir(&root), paths);
+    }
+
+    #[test]
+    fn reports_depth_and_file_types() {
+        let root = create_tree("depth");
+        for entry in ParallelWalk::new(&root) {
+            let entry = entry.unwrap();
+            let relative = entry.path().strip_prefix(&root).unwrap();
+            assert_eq!(relative.components().count(), entry.depth());
+            assert_eq!(entry.path().is_dir(), entry.file_type().is_dir());
+        }
+    }
+
+    #[test]
+    fn prunes_filtered_directories() {
+        let root = create_tree("filtered");
+        let paths: Vec<_> = ParallelWalk::new(&root)
+            .filter_entry(|entry| !is_hidden(entry))
+            .sorted(true)
+            .into_iter()
+            .map(|entry| entry.unwrap().path)
+            .collect();
+        let expected: Vec<_> = WalkDir::new(&root)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter()
+            .filter_entry(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .map(|entry| entry.unwrap().into_path())
+            .collect();
+        assert_eq!(expected, paths);
+        assert!(!paths.iter().any(|path| path.ends_with("u")));
+    }
+
+    #[test]
+    fn reports_missing_root() {
+        let root = temp_path("missing");
+        let results: Vec<_> = ParallelWalk::new(&root).into_iter().collect();
+        assert_eq!(1, results.len());
+        let error = results[0].as_ref().unwrap_err();
+        assert_eq!(root, error.path);
+        assert_eq!(io::ErrorKind::NotFound, error.error.kind());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_walking_after_errors() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = create_tree("errors");
+        let locked = root.join("a/b");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        let can_still_read = fs::read_dir(&locked).is_ok();
+
+        let results: Vec<_> = ParallelWalk::new(&root).sorted(true).into_iter().collect();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        // Running as root means we can read anything anyway
+        if can_still_read {
+            return;
+        }
+        let errors: Vec<_> = results.iter().filter_map(|r| r.as_ref().err()).collect();
+        assert_eq!(1, errors.len());
+        assert_eq!(locked, errors[0].path);
+        // Everything else was still found
+        assert!(results
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .any(|entry| entry.path().ends_with("a/d/w")));
+    }
+
+    #[test]
+    fn reports_panicking_filters() {
+        let root = create_tree("panic");
+        let results: Vec<_> = ParallelWalk::new(&root)
+            .threads(2)
+            .filter_entry(|entry| {
+                assert!(!entry.path().ends_with("a/b"), "Can't handle a/b");
+                true
+            })
+            .sorted(true)
+            .into_iter()
+            .collect();
+        let errors: Vec<_> = results.iter().filter_map(|r| r.as_ref().err()).collect();
+        assert_eq!(1, errors.len());
+        assert_eq!(root.join("a/b"), errors[0].path);
+        // The directory is skipped, everything else is still found
+        let paths: Vec<_> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+        assert!(paths.iter().any(|entry| entry.path().ends_with("a/d/w")));
+        assert!(!paths.iter().any(|entry| entry.path().ends_with("a/b/c")));
+    }
+
+    #[test]
+    fn stops_when_the_receiver_is_dropped() {
+        let root = create_tree("dropped");
+        let first: Vec<_> = ParallelWalk::new(&root).into_iter().take(3).collect();
+        assert_eq!(3, first.len());
+    }
+
+    #[test]
+    fn generates_benchmark_trees() {
+        // Replaces whatever an earlier run left behind
+        let root = temp_path("generate");
+        generate_tree(&root, 1234).unwrap();
+        let files = WalkDir::new(&root)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("file"))
+            .count();
+        assert_eq!(1234, files);
+    }
+}
@@ -61,6 +61,9 @@ fn write_file(path: &str, content: &str) -> io::Result<()> {
     // to read in an efficient way
     let mut buf_writer = BufWriter::new(file);
     buf_writer.write_all(content.as_bytes())?;
+    // Dropping a BufWriter flushes it, but silently ignores
+    // any error, so we flush it ourselves
+    buf_writer.flush()?;
     Ok(())
 }
 
@@ -69,6 +72,7 @@ fn append_file(path: &str, content: &str) -> io::Result<()> {
     let file = OpenOptions::new().append(true).open(path)?;
     let mut buf_writer = BufWriter::new(file);
     buf_writer.write_all(content.as_bytes())?;
+    buf_writer.flush()?;
     Ok(())
 }
 
@@ -0,0 +1,479 @@
+extern crate sha2;
+extern crate walkdir;
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use walkdir::WalkDir;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (with_hashes, root) = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => (false, "."),
+        ["--hash"] => (true, "."),
+        ["--hash", root] => (true, root),
+        [root] => (false, root),
+        _ => {
+            eprintln!("Usage: watch_directory [--hash] [path]");
+            process::exit(2);
+        }
+    };
+    println!("Watching '{}', press Ctrl+C to stop", root);
+    let mut watcher = Watcher::new(root, Duration::from_millis(500))
+        .expect("Failed to take initial snapshot")
+        .debounce(Duration::from_secs(1));
+    if with_hashes {
+        watcher = watcher.with_hashes().expect("Failed to hash files");
+    }
+    for batch in watcher {
+        for event in batch.expect("Failed to watch directory") {
+            println!("{:?}", event);
+        }
+        println!("---");
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Event {
+    Created(PathBuf),
+    Modified(PathBuf),
+    Deleted(PathBuf),
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+impl Event {
+    fn path(&self) -> &Path {
+        match *self {
+            Event::Created(ref path) | Event::Modified(ref path) | Event::Deleted(ref path) => path,
+            Event::Renamed { ref to, .. } => to,
+        }
+    }
+}
+
+// What we remember about a file between two polls
+#[derive(Debug, Clone, PartialEq)]
+struct FileState {
+    is_dir: bool,
+    size: u64,
+    modified: SystemTime,
+    id: Option<(u64, u64)>,
+    // The modification time has a coarse resolution on some filesystems,
+    // a hash catches changes that don't show up in size and time
+    hash: Option<[u8; 32]>,
+}
+
+type Snapshot = HashMap<PathBuf, FileState>;
+
+// Files can disappear while we are walking the tree, they show up
+// as deleted. Anything that can't be read for another reason, e.g.
+// because it's locked or being replaced, is taken from the previous
+// snapshot instead, so that it doesn't look deleted and then created again
+fn take_snapshot(root: &Path, with_hashes: bool, previous: &Snapshot) -> io::Result<Snapshot> {
+    // If the root itself is gone, there is nothing left to watch
+    fs::metadata(root)?;
+    let mut snapshot = HashMap::new();
+    for entry in WalkDir::new(root).min_depth(1) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let vanished = e.io_error().map(io::Error::kind) == Some(io::ErrorKind::NotFound);
+                if let (Some(path), false) = (e.path(), vanished) {
+                    carry_over(&mut snapshot, previous, path);
+                }
+                continue;
+            }
+        };
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(ref e) if e.io_error().map(io::Error::kind) == Some(io::ErrorKind::NotFound) => {
+                continue
+            }
+            Err(_) => {
+                carry_over(&mut snapshot, previous, entry.path());
+                continue;
+            }
+        };
+        let hash = if with_hashes && metadata.is_file() {
+            match hash_file(entry.path()) {
+                Ok(hash) => Some(hash),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                // Changes in size or time are still noticed
+                Err(_) => previous.get(entry.path()).and_then(|state| state.hash),
+            }
+        } else {
+            None
+        };
+        let state = FileState {
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            // Only missing on platforms that don't have it at all
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            id: file_id(&metadata),
+            hash,
+        };
+        snapshot.insert(entry.into_path(), state);
+    }
+    Ok(snapshot)
+}
+
+// Keep what we knew about a path and everything below it
+fn carry_over(snapshot: &mut Snapshot, previous: &Snapshot, path: &Path) {
+    for (old_path, state) in previous {
+        if old_path.starts_with(path) {
+            snapshot
+                .entry(old_path.clone())
+                .or_insert_with(|| state.clone());
+        }
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+// Identifies a file independently of its path, which lets us
+// recognize it after it has been renamed
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+// Describe how to get from one snapshot to the other
+fn diff(old: &Snapshot, new: &Snapshot) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut deleted: HashMap<_, _> = old
+        .iter()
+        .filter(|&(path, _)| !new.contains_key(path))
+        .map(|(path, state)| (path.as_path(), state))
+        .collect();
+    let mut created = Vec::new();
+
+    for (path, state) in new {
+        match old.get(path) {
+            // A directory's time changes with its content,
+            // which is already reported on its own
+            Some(old_state) if !state.is_dir && old_state != state => {
+                events.push(Event::Modified(path.clone()))
+            }
+            Some(_) => {}
+            None => created.push((path, state)),
+        }
+    }
+
+    // A file that vanished under one path and showed up under another
+    // with the same inode has been renamed. The filesystem is free
+    // to reuse the inode of a deleted file right away though,
+    // so the rest has to match as well. A file that was renamed and
+    // changed between two polls shows up as deleted and created
+    let deleted_ids: HashMap<_, _> = deleted
+        .iter()
+        .filter_map(|(&path, state)| state.id.map(|id| (id, path)))
+        .collect();
+    let mut renames = Vec::new();
+    for (path, state) in created {
+        let renamed_from = state
+            .id
+            .and_then(|id| deleted_ids.get(&id))
+            .filter(|&&from| is_same_file(&old[from], state));
+        match renamed_from {
+            Some(&from) => {
+                deleted.remove(from);
+                renames.push((from.to_path_buf(), path.clone()));
+            }
+            None => events.push(Event::Created(path.clone())),
+        }
+    }
+    events.extend(
+        deleted
+            .keys()
+            .map(|path| Event::Deleted(path.to_path_buf())),
+    );
+
+    // Renaming a directory moves everything in it as well,
+    // but there's no need to report every single file
+    let renamed_dirs: Vec<_> = renames
+        .iter()
+        .filter(|(_, to)| new[to].is_dir)
+        .cloned()
+        .collect();
+    renames.retain(|(from, to)| {
+        !renamed_dirs.iter().any(|(dir_from, dir_to)| {
+            from != dir_from
+                && match (from.strip_prefix(dir_from), to.strip_prefix(dir_to)) {
+                    (Ok(from_rest), Ok(to_rest)) => from_rest == to_rest,
+                    _ => false,
+                }
+        })
+    });
+    events.extend(
+        renames
+            .into_iter()
+            .map(|(from, to)| Event::Renamed { from, to }),
+    );
+
+    events.sort_by(|a, b| a.path().cmp(b.path()));
+    events
+}
+
+fn is_same_file(old: &FileState, new: &FileState) -> bool {
+    // A directory's time changes whenever its content does
+    old.is_dir == new.is_dir
+        && (new.is_dir || (old.size, old.modified, old.hash) == (new.size, new.modified, new.hash))
+}
+
+// Watches a directory tree by comparing snapshots of it
+struct Watcher {
+    root: PathBuf,
+    interval: Duration,
+    debounce: Duration,
+    with_hashes: bool,
+    snapshot: Snapshot,
+}
+
+impl Watcher {
+    fn new<P: AsRef<Path>>(root: P, interval: Duration) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let snapshot = take_snapshot(&root, false, &Snapshot::new())?;
+        Ok(Watcher {
+            root,
+            interval,
+            debounce: Duration::from_secs(0),
+            with_hashes: false,
+            snapshot,
+        })
+    }
+
+    // Wait until nothing has changed for this long
+    // before reporting a batch of events
+    fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    // Hash the content of every file on every poll.
+    // This is expensive, but catches every change
+    fn with_hashes(mut self) -> io::Result<Self> {
+        self.with_hashes = true;
+        self.snapshot = take_snapshot(&self.root, true, &self.snapshot)?;
+        Ok(self)
+    }
+
+    // Compare the tree with the last snapshot right away
+    fn poll(&mut self) -> io::Result<Vec<Event>> {
+        let snapshot = take_snapshot(&self.root, self.with_hashes, &self.snapshot)?;
+        let events = diff(&self.snapshot, &snapshot);
+        self.snapshot = snapshot;
+        Ok(events)
+    }
+
+    // Block until something changed and things have calmed down again
+    fn next_batch(&mut self) -> io::Result<Vec<Event>> {
+        // Without debouncing, every poll that finds changes is a batch
+        if self.debounce == Duration::from_secs(0) {
+            loop {
+                thread::sleep(self.interval);
+                let events = self.poll()?;
+                if !events.is_empty() {
+                    return Ok(events);
+                }
+            }
+        }
+
+        let mut latest: Option<(Snapshot, Instant)> = None;
+        loop {
+            thread::sleep(self.interval);
+            let previous = latest
+                .as_ref()
+                .map_or(&self.snapshot, |(snapshot, _)| snapshot);
+            let snapshot = take_snapshot(&self.root, self.with_hashes, previous)?;
+            let last_change = match latest {
+                Some((ref previous, changed_at)) if *previous == snapshot => changed_at,
+                Some(_) => Instant::now(),
+                None if snapshot == self.snapshot => continue,
+                None => Instant::now(),
+            };
+            if last_change.elapsed() < self.debounce {
+                latest = Some((snapshot, last_change));
+                continue;
+            }
+
+            // Comparing the first and the last snapshot of a burst combines
+            // its changes, e.g. a file that was created and deleted again
+            // doesn't show up at all
+            let events = diff(&self.snapshot, &snapshot);
+            self.snapshot = snapshot;
+            if !events.is_empty() {
+                return Ok(events);
+            }
+            latest = None;
+        }
+    }
+}
+
+impl Iterator for Watcher {
+    type Item = io::Result<Vec<Event>>;
+
+    // Never returns None, the watcher runs until it's dropped
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_batch())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every test watches a directory of its own
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("watch-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    fn watcher(root: &Path) -> Watcher {
+        Watcher::new(root, Duration::from_millis(10)).unwrap()
+    }
+
+    #[test]
+    fn reports_created_modified_and_deleted_files() {
+        let root = empty_dir("basic");
+        fs::write(root.join("changed"), "old").unwrap();
+        fs::write(root.join("removed"), "old").unwrap();
+        fs::write(root.join("untouched"), "old").unwrap();
+        let mut watcher = watcher(&root);
+        assert!(watcher.poll().unwrap().is_empty());
+
+        fs::write(root.join("changed"), "new content").unwrap();
+        fs::remove_file(root.join("removed")).unwrap();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub/created"), "new").unwrap();
+        assert_eq!(
+            vec![
+                Event::Modified(root.join("changed")),
+                Event::Deleted(root.join("removed")),
+                Event::Created(root.join("sub")),
+                Event::Created(root.join("sub/created")),
+            ],
+            watcher.poll().unwrap()
+        );
+        assert!(watcher.poll().unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detects_renames_by_inode() {
+        let root = empty_dir("rename");
+        fs::create_dir_all(root.join("dir/nested")).unwrap();
+        fs::write(root.join("file"), "content").unwrap();
+        fs::write(root.join("dir/nested/inner"), "content").unwrap();
+        let mut watcher = watcher(&root);
+
+        fs::rename(root.join("file"), root.join("moved")).unwrap();
+        fs::rename(root.join("dir"), root.join("renamed")).unwrap();
+        // The content of the directory moved along, but only
+        // the directory itself is reported
+        assert_eq!(
+            vec![
+                Event::Renamed {
+                    from: root.join("file"),
+                    to: root.join("moved"),
+                },
+                Event::Renamed {
+                    from: root.join("dir"),
+                    to: root.join("renamed"),
+                },
+            ],
+            watcher.poll().unwrap()
+        );
+    }
+
+    #[test]
+    fn hashes_catch_changes_with_the_same_size_and_time() {
+        let root = empty_dir("hashes");
+        let path = root.join("file");
+        fs::write(&path, "before").unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        let mut plain = watcher(&root);
+        let mut hashing = watcher(&root).with_hashes().unwrap();
+
+        fs::write(&path, "after!").unwrap();
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+
+        assert!(plain.poll().unwrap().is_empty());
+        assert_eq!(vec![Event::Modified(path)], hashing.poll().unwrap());
+    }
+
+    #[test]
+    fn debounces_bursts_of_changes() {
+        let root = empty_dir("debounce");
+        let mut watcher = watcher(&root).debounce(Duration::from_millis(200));
+
+        let writer_root = root.clone();
+        let writer = thread::spawn(move || {
+            for i in 0..5 {
+                fs::write(writer_root.join("file"), vec![b'x'; i + 1]).unwrap();
+                fs::write(writer_root.join("temporary"), "temporary").unwrap();
+                fs::remove_file(writer_root.join("temporary")).unwrap();
+                thread::sleep(Duration::from_millis(30));
+            }
+        });
+        let batch = watcher.next_batch().unwrap();
+        writer.join().unwrap();
+
+        // A file that was created and removed within the burst doesn't show up
+        assert_eq!(vec![Event::Created(root.join("file"))], batch);
+        assert_eq!(5, fs::metadata(root.join("file")).unwrap().len());
+        assert!(watcher.poll().unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_entries_are_not_deleted() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = empty_dir("unreadable");
+        fs::create_dir_all(root.join("locked/sub")).unwrap();
+        fs::write(root.join("locked/sub/file"), "content").unwrap();
+        fs::write(root.join("busy"), "content").unwrap();
+        let mut watcher = watcher(&root).with_hashes().unwrap();
+
+        let mode = |path: &str, mode| {
+            fs::set_permissions(root.join(path), fs::Permissions::from_mode(mode)).unwrap()
+        };
+        mode("locked", 0o000);
+        mode("busy", 0o000);
+        let can_still_read = fs::read_dir(root.join("locked")).is_ok();
+        let events = watcher.poll().unwrap();
+        mode("locked", 0o755);
+        mode("busy", 0o644);
+        // Running as root means we can read anything anyway
+        if can_still_read {
+            return;
+        }
+        assert!(events.is_empty(), "{:?}", events);
+        assert!(watcher.poll().unwrap().is_empty());
+    }
+
+    #[test]
+    fn fails_when_the_root_disappears() {
+        let root = empty_dir("vanishing");
+        let mut watcher = watcher(&root);
+        fs::remove_dir(&root).unwrap();
+        assert_eq!(io::ErrorKind::NotFound, watcher.poll().unwrap_err().kind());
+    }
+}
@@ -0,0 +1,806 @@
+extern crate csv;
+#[macro_use]
+extern crate serde_json;
+extern crate toml;
+
+use serde_json::{Map, Number, Value};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::{env, error, fmt, process};
+
+fn main() {
+    // Usage: convert [--from <format>] [--to <format>] <input> <output>
+    // The formats are guessed from the file extensions if not given,
+    // and "-" means stdin or stdout
+    let mut from = None;
+    let mut to = None;
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" | "--to" => {
+                let format = args
+                    .next()
+                    .unwrap_or_else(|| usage_error(&format!("Missing format after {}", arg)));
+                let format = format.parse().unwrap_or_else(|e: String| usage_error(&e));
+                if arg == "--from" {
+                    from = Some(format);
+                } else {
+                    to = Some(format);
+                }
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() != 2 {
+        usage_error("Expected an input and an output");
+    }
+    let from = from
+        .or_else(|| Format::from_path(&paths[0]))
+        .expect("Failed to guess the input format, please use --from");
+    let to = to
+        .or_else(|| Format::from_path(&paths[1]))
+        .expect("Failed to guess the output format, please use --to");
+
+    if let Err(e) = convert_paths(from, &paths[0], to, &paths[1]) {
+        eprintln!("Failed to convert {} to {}: {}", from, to, e);
+        process::exit(1);
+    }
+}
+
+// The output is written next to its final path and only moved there once
+// the conversion succeeded. That way a failed conversion never leaves half
+// a file behind, and converting a file into itself doesn't destroy it
+fn convert_paths(from: Format, input: &str, to: Format, output: &str) -> Result<()> {
+    let reader: Box<dyn Read> = if input == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(input)?)
+    };
+    if output == "-" {
+        return convert(from, reader, to, BufWriter::new(io::stdout()));
+    }
+    let output = Path::new(output);
+    let name = output
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "The output is not a file"))?;
+    let temp = output.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), process::id()));
+    let result = File::create(&temp)
+        .map_err(ConvertError::from)
+        .and_then(|file| convert(from, reader, to, BufWriter::new(file)))
+        .and_then(|_| Ok(fs::rename(&temp, output)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("Usage: convert [--from <format>] [--to <format>] <input> <output>");
+    process::exit(2);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    // A header and one row per record
+    Csv,
+    Json,
+    // One JSON value per line
+    Ndjson,
+    Toml,
+}
+
+impl Format {
+    fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "jsonl" => Some(Format::Ndjson),
+            extension => extension.parse().ok(),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "toml" => Ok(Format::Toml),
+            _ => Err(format!("Unknown format '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Format::Csv => "CSV",
+            Format::Json => "JSON",
+            Format::Ndjson => "NDJSON",
+            Format::Toml => "TOML",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+enum ConvertError {
+    Io(io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    TomlDeserialize(toml::de::Error),
+    TomlSerialize(toml::ser::Error),
+    // The value at `path` has no equivalent in the target format
+    Unrepresentable {
+        format: Format,
+        path: String,
+        reason: String,
+    },
+    // Two CSV columns that would end up in the same place, like "a" and "a.b"
+    ConflictingColumns(String, String),
+}
+
+type Result<T> = std::result::Result<T, ConvertError>;
+
+fn unrepresentable<T>(format: Format, path: &str, reason: &str) -> Result<T> {
+    Err(ConvertError::Unrepresentable {
+        format,
+        path: if path.is_empty() {
+            "the top level".to_string()
+        } else {
+            path.to_string()
+        },
+        reason: reason.to_string(),
+    })
+}
+
+fn convert<R: Read, W: Write>(from: Format, reader: R, to: Format, writer: W) -> Result<()> {
+    let value = read(from, reader)?;
+    write(to, &value, writer)
+}
+
+// Read any of the formats into a JSON value. Formats
+// that hold a list of records result in an array
+fn read<R: Read>(format: Format, reader: R) -> Result<Value> {
+    match format {
+        Format::Csv => read_csv(reader),
+        Format::Json => Ok(serde_json::from_reader(reader)?),
+        Format::Ndjson => {
+            let mut values = Vec::new();
+            for line in BufReader::new(reader).lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    values.push(serde_json::from_str(&line)?);
+                }
+            }
+            Ok(Value::Array(values))
+        }
+        Format::Toml => read_toml(reader),
+    }
+}
+
+fn write<W: Write>(format: Format, value: &Value, mut writer: W) -> Result<()> {
+    match format {
+        Format::Csv => write_csv(value, writer),
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut writer, value)?;
+            writer.write_all(b"\n")?;
+            Ok(writer.flush()?)
+        }
+        Format::Ndjson => {
+            let records = match *value {
+                Value::Array(ref values) => values.iter().collect(),
+                ref value => vec![value],
+            };
+            for record in records {
+                serde_json::to_writer(&mut writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+            Ok(writer.flush()?)
+        }
+        Format::Toml => {
+            let toml = toml::to_string(&to_toml_document(value)?)?;
+            writer.write_all(toml.as_bytes())?;
+            Ok(writer.flush()?)
+        }
+    }
+}
+
+fn read_csv<R: Read>(reader: R) -> Result<Value> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers()?.clone();
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let fields = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(header, field)| (header, parse_csv_field(field)));
+        records.push(unflatten(fields)?);
+    }
+    Ok(Value::Array(records))
+}
+
+// CSV doesn't know about types, so guess them.
+// Empty fields are what we write for nulls
+fn parse_csv_field(field: &str) -> Value {
+    if field.is_empty() {
+        return Value::Null;
+    }
+    if let Ok(number) = field.parse::<i64>() {
+        return json!(number);
+    }
+    if let Ok(number) = field.parse::<u64>() {
+        return json!(number);
+    }
+    // Larger integers would lose digits as a float
+    let digits = field.trim_start_matches(['-', '+']);
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Value::String(field.to_string());
+    }
+    if let Some(number) = field.parse::<f64>().ok().and_then(Number::from_f64) {
+        // Don't turn words like "inf" into numbers
+        if field.bytes().any(|b| b.is_ascii_digit()) {
+            return Value::Number(number);
+        }
+    }
+    match field {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::String(field.to_string()),
+    }
+}
+
+fn write_csv<W: Write>(value: &Value, writer: W) -> Result<()> {
+    let records = match *value {
+        Value::Array(ref records) => records.iter().collect(),
+        Value::Object(_) => vec![value],
+        _ => return unrepresentable(Format::Csv, "", "expected an array of objects"),
+    };
+
+    let mut rows = Vec::with_capacity(records.len());
+    for (index, record) in records.iter().enumerate() {
+        if !record.is_object() {
+            return unrepresentable(Format::Csv, &index.to_string(), "expected an object");
+        }
+        let mut row = Vec::new();
+        flatten(record, &index.to_string(), "", &mut row)?;
+        rows.push(row);
+    }
+
+    // Records can have different fields, the header contains all of them
+    let mut headers: Vec<&str> = Vec::new();
+    for row in &rows {
+        for (key, _) in row {
+            if headers.contains(&key.as_str()) {
+                continue;
+            }
+            if let Some(other) = headers.iter().find(|other| overlapping(other, key)) {
+                return unrepresentable(
+                    Format::Csv,
+                    key,
+                    &format!("the column would overlap with {}", other),
+                );
+            }
+            headers.push(key);
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(&headers)?;
+    for row in &rows {
+        let fields = headers.iter().map(|header| {
+            row.iter()
+                .find(|&(key, _)| key == header)
+                .map_or("", |(_, field)| field.as_str())
+        });
+        writer.write_record(fields)?;
+    }
+    Ok(writer.flush()?)
+}
+
+// Turn nested objects and arrays into dotted keys,
+// e.g. {"a": {"b": [1]}} becomes "a.b.0" = 1
+fn flatten(value: &Value, path: &str, key: &str, row: &mut Vec<(String, String)>) -> Result<()> {
+    let child_key = |child: &str| {
+        if key.is_empty() {
+            child.to_string()
+        } else {
+            format!("{}.{}", key, child)
+        }
+    };
+    let child_path = |child: &str| format!("{}.{}", path, child);
+    match *value {
+        Value::Object(ref map) if map.is_empty() && !key.is_empty() => {
+            unrepresentable(Format::Csv, path, "empty objects have no columns")
+        }
+        Value::Array(ref values) if values.is_empty() => {
+            unrepresentable(Format::Csv, path, "empty arrays have no columns")
+        }
+        Value::Object(ref map) => {
+            for (child, value) in map {
+                if child.contains('.') {
+                    return unrepresentable(
+                        Format::Csv,
+                        &child_path(child),
+                        "keys containing dots would be split when reading",
+                    );
+                }
+                flatten(value, &child_path(child), &child_key(child), row)?;
+            }
+            Ok(())
+        }
+        Value::Array(ref values) => {
+            for (index, value) in values.iter().enumerate() {
+                let index = index.to_string();
+                flatten(value, &child_path(&index), &child_key(&index), row)?;
+            }
+            Ok(())
+        }
+        // Strings that look like something else would come back with another type
+        Value::String(ref s) => match parse_csv_field(s) {
+            Value::String(_) => {
+                row.push((key.to_string(), s.clone()));
+                Ok(())
+            }
+            Value::Null => {
+                unrepresentable(Format::Csv, path, "empty strings would be read as null")
+            }
+            _ => unrepresentable(
+                Format::Csv,
+                path,
+                "the string would be read as a number or a boolean",
+            ),
+        },
+        Value::Null => {
+            row.push((key.to_string(), String::new()));
+            Ok(())
+        }
+        ref value => {
+            row.push((key.to_string(), value.to_string()));
+            Ok(())
+        }
+    }
+}
+
+// The opposite of flatten. Objects whose keys are
+// exactly 0, 1, 2, ... are turned back into arrays
+fn unflatten<'a, I>(fields: I) -> Result<Value>
+where
+    I: IntoIterator<Item = (&'a str, Value)>,
+{
+    let mut root = Map::new();
+    let mut keys: Vec<&str> = Vec::new();
+    for (key, value) in fields {
+        if let Some(other) = keys.iter().find(|other| overlapping(other, key)) {
+            return Err(ConvertError::ConflictingColumns(
+                other.to_string(),
+                key.to_string(),
+            ));
+        }
+        keys.push(key);
+        let mut parts = key.split('.').peekable();
+        let mut map = &mut root;
+        while let Some(part) = parts.next() {
+            if parts.peek().is_none() {
+                map.insert(part.to_string(), value);
+                break;
+            }
+            map = map
+                .entry(part.to_string())
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+                .expect("Keys were checked for overlaps");
+        }
+    }
+    Ok(restore_arrays(Value::Object(root)))
+}
+
+// Whether two columns are the same or one would contain the other
+fn overlapping(a: &str, b: &str) -> bool {
+    let contains = |parent: &str, child: &str| {
+        child.starts_with(parent) && child[parent.len()..].starts_with('.')
+    };
+    a == b || contains(a, b) || contains(b, a)
+}
+
+fn restore_arrays(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let is_array = (0..map.len()).all(|index| map.contains_key(&index.to_string()));
+            let mut map: Map<String, Value> = map
+                .into_iter()
+                .map(|(key, value)| (key, restore_arrays(value)))
+                .collect();
+            if is_array && !map.is_empty() {
+                let values = (0..map.len())
+                    .map(|index| map.remove(&index.to_string()).expect("Index was checked"))
+                    .collect();
+                Value::Array(values)
+            } else {
+                Value::Object(map)
+            }
+        }
+        value => value,
+    }
+}
+
+// TOML needs a table at the top, so a list of records is stored as
+// an array of tables called "records", which read_toml() unwraps again
+const TOML_RECORDS_KEY: &str = "records";
+
+fn read_toml<R: Read>(mut reader: R) -> Result<Value> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+    let value = from_toml(toml::from_str(&input)?, "")?;
+    match value {
+        Value::Object(mut map) => {
+            if map.len() == 1 && map.get(TOML_RECORDS_KEY).is_some_and(Value::is_array) {
+                Ok(map.remove(TOML_RECORDS_KEY).expect("Key was checked"))
+            } else {
+                Ok(Value::Object(map))
+            }
+        }
+        value => Ok(value),
+    }
+}
+
+fn from_toml(value: toml::Value, path: &str) -> Result<Value> {
+    let child_path = |child: &str| {
+        if path.is_empty() {
+            child.to_string()
+        } else {
+            format!("{}.{}", path, child)
+        }
+    };
+    Ok(match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => json!(i),
+        toml::Value::Float(f) => match Number::from_f64(f) {
+            Some(number) => Value::Number(number),
+            None => return unrepresentable(Format::Json, path, "JSON has no nan or inf"),
+        },
+        toml::Value::Boolean(b) => Value::Bool(b),
+        // JSON has no dates, so they become strings
+        toml::Value::Datetime(date) => Value::String(date.to_string()),
+        toml::Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(index, value)| from_toml(value, &child_path(&index.to_string())))
+                .collect::<Result<_>>()?,
+        ),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = from_toml(value, &child_path(&key))?;
+                    Ok((key, value))
+                })
+                .collect::<Result<_>>()?,
+        ),
+    })
+}
+
+fn to_toml_document(value: &Value) -> Result<toml::Value> {
+    match *value {
+        Value::Object(_) => to_toml(value, ""),
+        Value::Array(_) => {
+            let records = to_toml(value, "")?;
+            let mut table = toml::value::Table::new();
+            table.insert(TOML_RECORDS_KEY.to_string(), records);
+            Ok(toml::Value::Table(table))
+        }
+        _ => unrepresentable(Format::Toml, "", "expected an object or an array"),
+    }
+}
+
+fn to_toml(value: &Value, path: &str) -> Result<toml::Value> {
+    let child_path = |child: &str| {
+        if path.is_empty() {
+            child.to_string()
+        } else {
+            format!("{}.{}", path, child)
+        }
+    };
+    Ok(match *value {
+        Value::Null => return unrepresentable(Format::Toml, path, "TOML has no null"),
+        Value::Bool(b) => toml::Value::Boolean(b),
+        Value::Number(ref number) => match (number.as_i64(), number.as_f64()) {
+            (Some(i), _) => toml::Value::Integer(i),
+            (None, Some(_)) if number.is_u64() => {
+                return unrepresentable(Format::Toml, path, "integer is too large for TOML")
+            }
+            (None, Some(f)) => toml::Value::Float(f),
+            (None, None) => return unrepresentable(Format::Toml, path, "not a number"),
+        },
+        Value::String(ref s) => toml::Value::String(s.clone()),
+        Value::Array(ref values) => {
+            let values = values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| to_toml(value, &child_path(&index.to_string())))
+                .collect::<Result<Vec<_>>>()?;
+            // Arrays can only hold one type of value in this version of TOML
+            if let Some(first) = values.first() {
+                if values
+                    .iter()
+                    .any(|value| value.type_str() != first.type_str())
+                {
+                    return unrepresentable(Format::Toml, path, "array mixes different types");
+                }
+            }
+            toml::Value::Array(values)
+        }
+        Value::Object(ref map) => toml::Value::Table(
+            map.iter()
+                .map(|(key, value)| Ok((key.clone(), to_toml(value, &child_path(key))?)))
+                .collect::<Result<_>>()?,
+        ),
+    })
+}
+
+impl From<io::Error> for ConvertError {
+    fn from(e: io::Error) -> Self {
+        ConvertError::Io(e)
+    }
+}
+
+impl From<csv::Error> for ConvertError {
+    fn from(e: csv::Error) -> Self {
+        ConvertError::Csv(e)
+    }
+}
+
+impl From<serde_json::Error> for ConvertError {
+    fn from(e: serde_json::Error) -> Self {
+        ConvertError::Json(e)
+    }
+}
+
+impl From<toml::de::Error> for ConvertError {
+    fn from(e: toml::de::Error) -> Self {
+        ConvertError::TomlDeserialize(e)
+    }
+}
+
+impl From<toml::ser::Error> for ConvertError {
+    fn from(e: toml::ser::Error) -> Self {
+        ConvertError::TomlSerialize(e)
+    }
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConvertError::Io(ref e) => write!(f, "IO error: {}", e),
+            ConvertError::Csv(ref e) => write!(f, "CSV error: {}", e),
+            ConvertError::Json(ref e) => write!(f, "JSON error: {}", e),
+            ConvertError::TomlDeserialize(ref e) => write!(f, "Invalid TOML: {}", e),
+            ConvertError::TomlSerialize(ref e) => write!(f, "Failed to write TOML: {}", e),
+            ConvertError::Unrepresentable {
+                format,
+                ref path,
+                ref reason,
+            } => write!(f, "Cannot write {} as {}: {}", path, format, reason),
+            ConvertError::ConflictingColumns(ref a, ref b) => {
+                write!(f, "Columns {} and {} overlap", a, b)
+            }
+        }
+    }
+}
+
+impl error::Error for ConvertError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PET_OWNER: &str = include_str!("../../pet_owner.json");
+    const PREFERENCES: &str = include_str!("../../preferences.toml");
+
+    fn convert_str(from: Format, input: &str, to: Format) -> Result<String> {
+        let mut output = Vec::new();
+        convert(from, input.as_bytes(), to, &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    fn round_trip(value: &Value, format: Format) -> Value {
+        let mut output = Vec::new();
+        write(format, value, &mut output).unwrap();
+        read(format, &output[..]).unwrap()
+    }
+
+    fn unrepresentable_path(result: Result<String>) -> String {
+        match result {
+            Err(ConvertError::Unrepresentable { path, .. }) => path,
+            other => panic!("Expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn flattens_nested_values_into_csv_headers() {
+        let csv = convert_str(Format::Json, PET_OWNER, Format::Csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            Some(
+                "age,name,pets.0.age,pets.0.colour,pets.0.name,pets.0.species,\
+                 pets.1.age,pets.1.colour,pets.1.name,pets.1.species,\
+                 pets.2.age,pets.2.colour,pets.2.name,pets.2.species"
+            ),
+            lines.next()
+        );
+        assert_eq!(
+            Some("23,John,2,,Waldo,Dog,47,Green,Speedy,Turtle,,Orange,Meows,Cat"),
+            lines.next()
+        );
+    }
+
+    #[test]
+    fn round_trips_through_every_format() {
+        let owner: Value = serde_json::from_str(PET_OWNER).unwrap();
+        let owners = Value::Array(vec![owner.clone(), owner.clone()]);
+        assert_eq!(owners, round_trip(&owners, Format::Csv));
+        assert_eq!(owners, round_trip(&owners, Format::Json));
+        assert_eq!(owners, round_trip(&owners, Format::Ndjson));
+
+        let preferences = read(Format::Toml, PREFERENCES.as_bytes()).unwrap();
+        assert_eq!(json!("en-GB"), preferences["language"]["display"]);
+        assert_eq!(preferences, round_trip(&preferences, Format::Toml));
+        assert_eq!(preferences, round_trip(&preferences, Format::Json));
+        assert_eq!(Value::Array(vec![preferences.clone()]), {
+            round_trip(&preferences, Format::Csv)
+        });
+    }
+
+    #[test]
+    fn stores_record_lists_in_toml() {
+        let records =
+            json!([{"name": "Mercury", "radius": 0.38}, {"name": "Venus", "radius": 0.95}]);
+        let toml = convert_str(Format::Json, &records.to_string(), Format::Toml).unwrap();
+        assert!(toml.starts_with("[[records]]\n"));
+        assert_eq!(records, round_trip(&records, Format::Toml));
+    }
+
+    #[test]
+    fn converts_ndjson() {
+        let input = "{\"a\": 1}\n\n{\"b\": {\"c\": true}}\n";
+        let csv = convert_str(Format::Ndjson, input, Format::Csv).unwrap();
+        assert_eq!("a,b.c\n1,\n,true\n", csv);
+        let ndjson = convert_str(Format::Json, "[1, [2], {}]", Format::Ndjson).unwrap();
+        assert_eq!("1\n[2]\n{}\n", ndjson);
+    }
+
+    #[test]
+    fn guesses_csv_types() {
+        let value = read(Format::Csv, "a,b,c,d,e\n1,-2.5,true,inf,\n".as_bytes()).unwrap();
+        assert_eq!(
+            json!([{"a": 1, "b": -2.5, "c": true, "d": "inf", "e": null}]),
+            value
+        );
+    }
+
+    #[test]
+    fn keeps_large_integers() {
+        let value = read(
+            Format::Csv,
+            "a,b,c\n18446744073709551615,18446744073709551616,-9223372036854775809\n".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            json!([{
+                "a": 18446744073709551615u64,
+                "b": "18446744073709551616",
+                "c": "-9223372036854775809",
+            }]),
+            value
+        );
+    }
+
+    #[test]
+    fn replaces_output_files_only_on_success() {
+        let dir = env::temp_dir().join(format!("convert-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("data.json");
+        let path = path.to_str().unwrap();
+        fs::write(path, "{\"a\": null}").unwrap();
+
+        // Nulls can't be written as TOML
+        let output = dir.join("data.toml");
+        fs::write(&output, "old = true\n").unwrap();
+        assert!(convert_paths(Format::Json, path, Format::Toml, output.to_str().unwrap()).is_err());
+        assert_eq!("old = true\n", fs::read_to_string(&output).unwrap());
+
+        convert_paths(Format::Json, path, Format::Json, path).unwrap();
+        assert_eq!("{\n  \"a\": null\n}\n", fs::read_to_string(path).unwrap());
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_unrepresentable_values() {
+        assert_eq!(
+            "pets.0.colour",
+            unrepresentable_path(convert_str(Format::Json, PET_OWNER, Format::Toml))
+        );
+        assert_eq!(
+            "0.a",
+            unrepresentable_path(convert_str(
+                Format::Json,
+                r#"[{"a": [1, "two"]}]"#,
+                Format::Toml
+            ))
+        );
+        assert_eq!(
+            "the top level",
+            unrepresentable_path(convert_str(Format::Json, "42", Format::Toml))
+        );
+        assert_eq!(
+            "0.a.b",
+            unrepresentable_path(convert_str(
+                Format::Json,
+                r#"{"a": {"b": []}}"#,
+                Format::Csv
+            ))
+        );
+        assert_eq!(
+            "0.a.b",
+            unrepresentable_path(convert_str(Format::Json, r#"{"a.b": 1}"#, Format::Csv))
+        );
+        assert_eq!(
+            "1",
+            unrepresentable_path(convert_str(Format::Json, r#"[{}, 1]"#, Format::Csv))
+        );
+        // Strings that would be read back as something else
+        for input in &[r#"{"a": ""}"#, r#"{"a": "42"}"#, r#"{"a": "true"}"#] {
+            assert_eq!(
+                "0.a",
+                unrepresentable_path(convert_str(Format::Json, input, Format::Csv))
+            );
+        }
+        assert_eq!(
+            "a.b",
+            unrepresentable_path(convert_str(
+                Format::Json,
+                r#"[{"a": 1}, {"a": {"b": 2}}]"#,
+                Format::Csv
+            ))
+        );
+        assert_eq!(
+            "x",
+            unrepresentable_path(convert_str(Format::Toml, "x = nan", Format::Json))
+        );
+    }
+
+    #[test]
+    fn rejects_overlapping_csv_columns() {
+        for input in &["a,a.b\n1,2\n", "a.b,a\n1,2\n", "a,a\n1,2\n"] {
+            match convert_str(Format::Csv, input, Format::Json) {
+                Err(ConvertError::ConflictingColumns(..)) => {}
+                other => panic!("Expected overlapping columns, got {:?}", other),
+            }
+        }
+        // Only whole parts count
+        assert_eq!(
+            json!([{"a": 1, "ab": {"c": 2}}]),
+            read(Format::Csv, "a,ab.c\n1,2\n".as_bytes()).unwrap()
+        );
+    }
+
+    #[test]
+    fn guesses_formats_from_extensions() {
+        assert_eq!(Some(Format::Csv), Format::from_path("planets.CSV"));
+        assert_eq!(Some(Format::Ndjson), Format::from_path("log.jsonl"));
+        assert_eq!(
+            Some(Format::Toml),
+            Format::from_path("a/b/preferences.toml")
+        );
+        assert_eq!(None, Format::from_path("README"));
+    }
+}
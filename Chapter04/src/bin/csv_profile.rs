@@ -0,0 +1,675 @@
+extern crate csv;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (sample_size, path) = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => (1000, "solar_system_compared_to_earth.csv"),
+        [path] => (1000, path),
+        ["--sample", size, path] => (size.parse().expect("Failed to parse sample size"), path),
+        _ => {
+            eprintln!("Usage: csv_profile [--sample <rows>] [path]");
+            process::exit(2);
+        }
+    };
+    let file = File::open(path).expect("Failed to open csv file");
+    // The file is read as a stream, only the sample is kept in memory
+    let profile = Profiler::new()
+        .sample_size(sample_size)
+        .profile(file)
+        .expect("Failed to read csv");
+
+    println!("{} rows in '{}'", profile.rows, path);
+    for column in &profile.columns {
+        println!("-------");
+        println!(
+            "{}: {}{}",
+            column.name,
+            column.column_type,
+            if column.nullable { ", nullable" } else { "" }
+        );
+        println!("Nulls: {}", column.null_count);
+        if column.mismatches > 0 {
+            println!("Values not matching the type: {}", column.mismatches);
+        }
+        if let (Some(min), Some(max)) = (&column.min, &column.max) {
+            println!("Min: {}, max: {}", min, max);
+        }
+        if let Some(mean) = column.mean() {
+            println!("Mean: {}", mean);
+        }
+        let (distinct, is_estimate) = column.distinct();
+        println!(
+            "Distinct values: {}{}",
+            if is_estimate { "~" } else { "" },
+            distinct
+        );
+    }
+
+    println!("-------");
+    println!("{}", profile.to_rust_struct("Planet"));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnType {
+    Bool,
+    Integer,
+    Float,
+    // In the YYYY-MM-DD format
+    Date,
+    String,
+}
+
+impl ColumnType {
+    // Ordered from the most to the least specific
+    const ALL: [ColumnType; 5] = [
+        ColumnType::Bool,
+        ColumnType::Integer,
+        ColumnType::Float,
+        ColumnType::Date,
+        ColumnType::String,
+    ];
+
+    fn accepts(self, value: &str) -> bool {
+        match self {
+            // That's what serde accepts for a bool
+            ColumnType::Bool => value == "true" || value == "false",
+            ColumnType::Integer => value.parse::<i64>().is_ok(),
+            ColumnType::Float => {
+                value.parse::<f64>().is_ok() && value.bytes().any(|b| b.is_ascii_digit())
+            }
+            ColumnType::Date => is_date(value),
+            ColumnType::String => true,
+        }
+    }
+
+    fn parse(self, value: &str) -> Option<Value> {
+        match self {
+            ColumnType::Bool => Some(Value::Bool(value == "true")),
+            ColumnType::Integer => value.parse().ok().map(Value::Integer),
+            ColumnType::Float => value.parse().ok().map(Value::Float),
+            ColumnType::Date | ColumnType::String => Some(Value::Text(value.to_string())),
+        }
+    }
+
+    fn rust_type(self) -> &'static str {
+        match self {
+            ColumnType::Bool => "bool",
+            ColumnType::Integer => "i64",
+            ColumnType::Float => "f64",
+            ColumnType::Date | ColumnType::String => "String",
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            ColumnType::Bool => "bool",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Date => "date",
+            ColumnType::String => "string",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn is_date(value: &str) -> bool {
+    let parts: Vec<_> = value.split('-').collect();
+    if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+        return false;
+    }
+    let numbers: Vec<u32> = parts.iter().filter_map(|part| part.parse().ok()).collect();
+    match numbers[..] {
+        [year, month, day] => {
+            let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+            let days_in_month = match month {
+                2 if is_leap_year => 29,
+                2 => 28,
+                4 | 6 | 9 | 11 => 30,
+                1..=12 => 31,
+                _ => return false,
+            };
+            day >= 1 && day <= days_in_month
+        }
+        _ => false,
+    }
+}
+
+fn is_null(value: &str) -> bool {
+    value.trim().is_empty()
+}
+
+// A value of a column, used for its minimum and maximum
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+enum Value {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    // Dates in the YYYY-MM-DD format sort just like strings
+    Text(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Text(ref value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+// Counts distinct values exactly as long as there are few of them.
+// After that, it switches to a HyperLogLog estimate,
+// which needs the same little memory no matter how many values we see
+#[derive(Debug)]
+enum DistinctCounter {
+    Exact(HashSet<u64>),
+    Estimate(Vec<u8>),
+}
+
+const EXACT_DISTINCT_LIMIT: usize = 4096;
+// 2^12 registers give an error of about 1.6%
+const REGISTER_BITS: u32 = 12;
+
+impl DistinctCounter {
+    fn new() -> Self {
+        DistinctCounter::Exact(HashSet::new())
+    }
+
+    fn insert(&mut self, value: &str) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let switch = match *self {
+            DistinctCounter::Exact(ref mut hashes) => {
+                hashes.insert(hash);
+                hashes.len() > EXACT_DISTINCT_LIMIT
+            }
+            DistinctCounter::Estimate(ref mut registers) => {
+                add_to_registers(registers, hash);
+                false
+            }
+        };
+        if switch {
+            let mut registers = vec![0; 1 << REGISTER_BITS];
+            if let DistinctCounter::Exact(ref hashes) = *self {
+                for &hash in hashes {
+                    add_to_registers(&mut registers, hash);
+                }
+            }
+            *self = DistinctCounter::Estimate(registers);
+        }
+    }
+
+    // The number of distinct values and whether it's an estimate
+    fn count(&self) -> (u64, bool) {
+        match *self {
+            DistinctCounter::Exact(ref hashes) => (hashes.len() as u64, false),
+            DistinctCounter::Estimate(ref registers) => (estimate(registers), true),
+        }
+    }
+}
+
+fn add_to_registers(registers: &mut [u8], hash: u64) {
+    // The first bits pick the register, the rest is a
+    // random number whose leading zeros we count
+    let index = (hash >> (64 - REGISTER_BITS)) as usize;
+    let rest = hash << REGISTER_BITS;
+    let rank = (rest.leading_zeros() + 1).min(64 - REGISTER_BITS + 1) as u8;
+    registers[index] = registers[index].max(rank);
+}
+
+fn estimate(registers: &[u8]) -> u64 {
+    let m = registers.len() as f64;
+    let alpha = 0.7213 / (1.0 + 1.079 / m);
+    let sum: f64 = registers.iter().map(|&r| 2f64.powi(-i32::from(r))).sum();
+    let raw = alpha * m * m / sum;
+    let zeros = registers.iter().filter(|&&r| r == 0).count();
+    // The raw estimate is too high for small counts
+    if raw <= 2.5 * m && zeros > 0 {
+        (m * (m / zeros as f64).ln()).round() as u64
+    } else {
+        raw.round() as u64
+    }
+}
+
+#[derive(Debug)]
+struct ColumnProfile {
+    name: String,
+    column_type: ColumnType,
+    nullable: bool,
+    null_count: u64,
+    // Values after the sample that don't fit the inferred type
+    mismatches: u64,
+    min: Option<Value>,
+    max: Option<Value>,
+    sum: f64,
+    numbers: u64,
+    distinct: DistinctCounter,
+}
+
+impl ColumnProfile {
+    fn new(name: &str, column_type: ColumnType, nullable: bool) -> Self {
+        ColumnProfile {
+            name: name.to_string(),
+            column_type,
+            nullable,
+            null_count: 0,
+            mismatches: 0,
+            min: None,
+            max: None,
+            sum: 0.0,
+            numbers: 0,
+            distinct: DistinctCounter::new(),
+        }
+    }
+
+    fn add(&mut self, raw: &str) {
+        if is_null(raw) {
+            self.null_count += 1;
+            // Nulls are cheap to spot, so we don't rely on the sample for them
+            self.nullable = true;
+            return;
+        }
+        self.distinct.insert(raw);
+        let value = match self.column_type.parse(raw) {
+            Some(ref value) if self.column_type.accepts(raw) => value.clone(),
+            _ => {
+                self.mismatches += 1;
+                return;
+            }
+        };
+        match value {
+            Value::Integer(number) => self.add_number(number as f64),
+            Value::Float(number) => self.add_number(number),
+            _ => {}
+        }
+        if self.min.as_ref().is_none_or(|min| value < *min) {
+            self.min = Some(value.clone());
+        }
+        if self.max.as_ref().is_none_or(|max| value > *max) {
+            self.max = Some(value);
+        }
+    }
+
+    fn add_number(&mut self, number: f64) {
+        self.sum += number;
+        self.numbers += 1;
+    }
+
+    fn mean(&self) -> Option<f64> {
+        if self.numbers == 0 {
+            None
+        } else {
+            Some(self.sum / self.numbers as f64)
+        }
+    }
+
+    fn distinct(&self) -> (u64, bool) {
+        self.distinct.count()
+    }
+}
+
+#[derive(Debug)]
+struct Profile {
+    rows: u64,
+    columns: Vec<ColumnProfile>,
+}
+
+impl Profile {
+    // Generate a struct that the file can be deserialized into
+    fn to_rust_struct(&self, name: &str) -> String {
+        let mut code = String::from("#[derive(Debug, Serialize, Deserialize)]\n");
+        code += &format!("struct {} {{\n", name);
+        let mut fields = HashSet::new();
+        for column in &self.columns {
+            // Headers like "Name" and "name" end up as the same field
+            let mut field = field_name(&column.name);
+            let mut suffix = 1;
+            while !fields.insert(field.clone()) {
+                suffix += 1;
+                field = format!("{}_{}", field_name(&column.name), suffix);
+            }
+            if field != column.name {
+                code += &format!("    #[serde(rename = {:?})]\n", column.name);
+            }
+            // A type that doesn't fit every value would fail to deserialize
+            let rust_type = if column.mismatches > 0 {
+                code += &format!(
+                    "    // {} values are not of type {}\n",
+                    column.mismatches, column.column_type
+                );
+                "String"
+            } else {
+                column.column_type.rust_type()
+            };
+            if column.nullable {
+                code += &format!("    {}: Option<{}>,\n", field, rust_type);
+            } else {
+                code += &format!("    {}: {},\n", field, rust_type);
+            }
+        }
+        code.push('}');
+        code
+    }
+}
+
+// Turn a column header into a valid snake_case identifier.
+// A run of capitals is one word, so "userID" becomes user_id
+// and "HTTPServer" becomes http_server
+fn field_name(header: &str) -> String {
+    let mut name = String::with_capacity(header.len());
+    let chars: Vec<char> = header.trim().chars().collect();
+    for (index, &c) in chars.iter().enumerate() {
+        if c.is_ascii_alphanumeric() {
+            let after_lowercase = index > 0 && !chars[index - 1].is_ascii_uppercase();
+            let before_lowercase = chars.get(index + 1).is_some_and(char::is_ascii_lowercase);
+            let starts_word = c.is_ascii_uppercase() && (after_lowercase || before_lowercase);
+            if starts_word && !name.is_empty() && !name.ends_with('_') {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    while name.ends_with('_') {
+        name.pop();
+    }
+    let is_keyword = [
+        "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+        "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+        "where", "while",
+    ]
+    .contains(&name.as_str());
+    if name.is_empty() || is_keyword || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+struct Profiler {
+    sample_size: usize,
+}
+
+impl Profiler {
+    fn new() -> Self {
+        Profiler { sample_size: 1000 }
+    }
+
+    // How many rows are used to infer the column types
+    fn sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size.max(1);
+        self
+    }
+
+    fn profile<R: Read>(&self, reader: R) -> csv::Result<Profile> {
+        let mut reader = csv::Reader::from_reader(reader);
+        let headers = reader.headers()?.clone();
+
+        // Only the sample is kept in memory,
+        // everything after it is looked at row by row
+        let mut records = reader.into_records();
+        let mut sample = Vec::with_capacity(self.sample_size);
+        while sample.len() < self.sample_size {
+            match records.next() {
+                Some(record) => sample.push(record?),
+                None => break,
+            }
+        }
+
+        let mut columns: Vec<_> = headers
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let values = || {
+                    sample
+                        .iter()
+                        .filter_map(move |record| record.get(index))
+                        .filter(|value| !is_null(value))
+                };
+                // Without a single value to go by, a string is the safest guess
+                let column_type = if values().next().is_none() {
+                    ColumnType::String
+                } else {
+                    *ColumnType::ALL
+                        .iter()
+                        .find(|column_type| values().all(|value| column_type.accepts(value)))
+                        .expect("Every value is a string")
+                };
+                let nullable = values().count() < sample.len();
+                ColumnProfile::new(name, column_type, nullable)
+            })
+            .collect();
+
+        let mut rows = 0;
+        let mut add = |record: &csv::StringRecord| {
+            rows += 1;
+            for (column, value) in columns.iter_mut().zip(record.iter()) {
+                column.add(value);
+            }
+        };
+        for record in &sample {
+            add(record);
+        }
+        for record in records {
+            add(&record?);
+        }
+        Ok(Profile { rows, columns })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    const PEOPLE: &str = "\
+id,Name,active,born,height,Team Name
+1,Ferris,true,2015-05-15,1.5,crabs
+2,Corro,false,2016-02-29,2,
+3,Ferris,true,2000-01-01,0.5,crabs
+";
+
+    fn profile(input: &str) -> Profile {
+        Profiler::new().profile(input.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn infers_column_types() {
+        let profile = profile(PEOPLE);
+        let types: Vec<_> = profile
+            .columns
+            .iter()
+            .map(|column| (column.column_type, column.nullable))
+            .collect();
+        assert_eq!(
+            vec![
+                (ColumnType::Integer, false),
+                (ColumnType::String, false),
+                (ColumnType::Bool, false),
+                (ColumnType::Date, false),
+                (ColumnType::Float, false),
+                (ColumnType::String, true),
+            ],
+            types
+        );
+    }
+
+    #[test]
+    fn computes_statistics() {
+        let profile = profile(PEOPLE);
+        assert_eq!(3, profile.rows);
+        let id = &profile.columns[0];
+        assert_eq!(Some(Value::Integer(1)), id.min);
+        assert_eq!(Some(Value::Integer(3)), id.max);
+        assert_eq!(Some(2.0), id.mean());
+        assert_eq!((3, false), id.distinct());
+
+        let name = &profile.columns[1];
+        assert_eq!(Some(Value::Text("Corro".to_string())), name.min);
+        assert_eq!(None, name.mean());
+        assert_eq!((2, false), name.distinct());
+
+        let born = &profile.columns[3];
+        assert_eq!(Some(Value::Text("2000-01-01".to_string())), born.min);
+        assert_eq!(Some(Value::Text("2016-02-29".to_string())), born.max);
+
+        let team = &profile.columns[5];
+        assert_eq!(1, team.null_count);
+        assert_eq!((1, false), team.distinct());
+    }
+
+    #[test]
+    fn counts_values_that_do_not_fit_the_sample() {
+        let input = "count,name\n1,a\n2,b\nmany,c\n,d\n";
+        let profile = Profiler::new()
+            .sample_size(2)
+            .profile(input.as_bytes())
+            .unwrap();
+        let count = &profile.columns[0];
+        assert_eq!(ColumnType::Integer, count.column_type);
+        assert_eq!(1, count.mismatches);
+        assert_eq!(1, count.null_count);
+        assert!(count.nullable);
+        assert_eq!(Some(Value::Integer(2)), count.max);
+        assert_eq!(
+            "#[derive(Debug, Serialize, Deserialize)]\n\
+             struct Counts {\n    \
+             // 1 values are not of type integer\n    \
+             count: Option<String>,\n    \
+             name: String,\n}",
+            profile.to_rust_struct("Counts")
+        );
+    }
+
+    #[test]
+    fn generates_structs() {
+        let planets = "\
+name,radius,distance_from_sun,gravity
+Mercury,0.38,0.47,0.38
+Earth,1,1,1
+";
+        assert_eq!(
+            "#[derive(Debug, Serialize, Deserialize)]
+struct Planet {
+    name: String,
+    radius: f64,
+    distance_from_sun: f64,
+    gravity: f64,
+}",
+            profile(planets).to_rust_struct("Planet")
+        );
+
+        let generated = profile(PEOPLE).to_rust_struct("Person");
+        assert!(generated.contains("    #[serde(rename = \"Name\")]\n    name: String,\n"));
+        assert!(generated.contains("    born: String,\n"));
+        assert!(generated.contains("    #[serde(rename = \"Team Name\")]\n"));
+        assert!(generated.contains("    team_name: Option<String>,\n"));
+    }
+
+    #[test]
+    fn sanitizes_field_names() {
+        assert_eq!("distance_from_sun", field_name("distance_from_sun"));
+        assert_eq!("team_name", field_name(" Team Name "));
+        assert_eq!("_type", field_name("type"));
+        assert_eq!("_1st_place", field_name("1st place"));
+        assert_eq!("_", field_name("???"));
+        assert_eq!("id", field_name("ID"));
+        assert_eq!("user_id", field_name("userID"));
+        assert_eq!("http_server", field_name("HTTPServer"));
+        assert_eq!("planet_id_2", field_name("PlanetID 2"));
+    }
+
+    #[test]
+    fn generates_unique_fields() {
+        let input = "Name,name,name_2,Notes\nFerris,ferris,x,\nCorro,corro,y,\n";
+        let profile = profile(input);
+        assert_eq!(ColumnType::String, profile.columns[3].column_type);
+        assert_eq!(
+            "#[derive(Debug, Serialize, Deserialize)]\n\
+             struct Person {\n    \
+             #[serde(rename = \"Name\")]\n    \
+             name: String,\n    \
+             #[serde(rename = \"name\")]\n    \
+             name_2: String,\n    \
+             #[serde(rename = \"name_2\")]\n    \
+             name_2_2: String,\n    \
+             #[serde(rename = \"Notes\")]\n    \
+             notes: Option<String>,\n}",
+            profile.to_rust_struct("Person")
+        );
+    }
+
+    #[test]
+    fn recognizes_dates() {
+        assert!(is_date("2016-02-29"));
+        assert!(!is_date("2015-02-29"));
+        assert!(!is_date("2015-13-01"));
+        assert!(!is_date("15-01-01"));
+        assert!(!is_date("2015-1-1"));
+    }
+
+    // Produces a large csv file row by row without holding it in memory
+    struct GeneratedCsv {
+        next_row: u64,
+        rows: u64,
+        buffer: Vec<u8>,
+    }
+
+    impl Read for GeneratedCsv {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.buffer.is_empty() {
+                if self.next_row > self.rows {
+                    return Ok(0);
+                }
+                self.buffer = if self.next_row == 0 {
+                    b"id,parity\n".to_vec()
+                } else {
+                    format!("{},{}\n", self.next_row, self.next_row % 2).into_bytes()
+                };
+                self.next_row += 1;
+            }
+            let len = buf.len().min(self.buffer.len());
+            buf[..len].copy_from_slice(&self.buffer[..len]);
+            self.buffer.drain(..len);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn streams_large_inputs() {
+        let rows = 200_000;
+        let input = GeneratedCsv {
+            next_row: 0,
+            rows,
+            buffer: Vec::new(),
+        };
+        let profile = Profiler::new().profile(input).unwrap();
+        assert_eq!(rows, profile.rows);
+
+        let id = &profile.columns[0];
+        assert_eq!(Some(Value::Integer(rows as i64)), id.max);
+        let (distinct, is_estimate) = id.distinct();
+        assert!(is_estimate);
+        let error = (distinct as f64 - rows as f64).abs() / rows as f64;
+        assert!(error < 0.05, "Estimated {} distinct values", distinct);
+
+        assert_eq!((2, false), profile.columns[1].distinct());
+        assert_eq!(Some(0.5), profile.columns[1].mean());
+    }
+}
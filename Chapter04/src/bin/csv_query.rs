@@ -0,0 +1,1198 @@
+extern crate csv;
+extern crate serde;
+extern crate serde_json;
+
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+use std::{env, error, fmt, fs, process};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (json, path, query) = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => {
+            demo();
+            return;
+        }
+        ["--json", path] => (true, path, ""),
+        ["--json", path, query] => (true, path, query),
+        [path] => (false, path, ""),
+        [path, query] => (false, path, query),
+        _ => {
+            eprintln!("Usage: csv_query [--json] <file> [query]");
+            eprintln!("Example: csv_query planets.csv \"select name, gravity where radius > 1 sort by gravity desc limit 3\"");
+            eprintln!("Use - as file to read from stdin");
+            process::exit(2);
+        }
+    };
+
+    let query: Query = match query.parse() {
+        Ok(query) => query,
+        Err(e) => {
+            let e: SyntaxError = e;
+            eprintln!("{}", query);
+            eprintln!("{}^", " ".repeat(e.position));
+            eprintln!("Invalid query: {}", e);
+            process::exit(2);
+        }
+    };
+    let reader: Box<dyn Read> = match path {
+        "-" => Box::new(io::stdin()),
+        path => Box::new(File::open(path).expect("Failed to open CSV file")),
+    };
+    let table = query.run(reader).unwrap_or_else(|e| {
+        eprintln!("Query failed: {}", e);
+        process::exit(1);
+    });
+    let stdout = io::stdout();
+    let result = if json {
+        serde_json::to_writer_pretty(stdout.lock(), &table)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(stdout.lock()))
+    } else {
+        table.write_csv(stdout.lock()).map_err(io::Error::from)
+    };
+    result.expect("Failed to write result");
+}
+
+fn demo() {
+    let planets = fs::read("solar_system_compared_to_earth.csv").expect(
+        "Failed to read solar_system_compared_to_earth.csv, run serde_csv first to create it",
+    );
+
+    // Queries can be built in code...
+    let heavy = Query::new()
+        .select("name")
+        .select_as("gravity", "g")
+        .filter("gravity", Operator::Gt, Value::number(1.0))
+        .sort_by("g", true)
+        .limit(2);
+    println!("The two planets with the most gravity:");
+    let table = heavy.run(&planets[..]).expect("Failed to run query");
+    table.write_csv(io::stdout()).expect("Failed to write CSV");
+
+    let light = Query::new()
+        .filter("gravity", Operator::Lt, Value::number(1.0))
+        .group_by("gravity")
+        .select("gravity")
+        .aggregate(Function::Count, "*")
+        .aggregate(Function::Min, "name");
+    println!("\nPlanets with less gravity than earth:");
+    let table = light.run(&planets[..]).expect("Failed to run query");
+    table.write_csv(io::stdout()).expect("Failed to write CSV");
+
+    // ...or parsed from text
+    let query: Query = "select count(*) as planets, avg(radius), max(distance_from_sun) \
+                        where distance_from_sun > 1"
+        .parse()
+        .expect("Failed to parse query");
+    println!("\nOuter planets:");
+    let table = query.run(&planets[..]).expect("Failed to run query");
+    serde_json::to_writer_pretty(io::stdout(), &table).expect("Failed to write JSON");
+    println!();
+}
+
+// Fields are typed by their content: empty fields are null,
+// anything that looks like a number is one and the rest is text
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    // Numbers keep the text they were parsed from, as going through
+    // an f64 would turn 02134 into 2134 and 1.10 into 1.1
+    Number(f64, String),
+    Text(String),
+}
+
+impl Value {
+    fn parse(field: &str) -> Value {
+        if field.is_empty() {
+            return Value::Null;
+        }
+        match field.trim().parse::<f64>() {
+            // "NaN" and "inf" are better treated as words
+            Ok(number) if number.is_finite() => Value::Number(number, field.to_string()),
+            _ => Value::Text(field.to_string()),
+        }
+    }
+
+    // A number that didn't come from the input, like a sum
+    fn number(number: f64) -> Value {
+        Value::Number(number, number.to_string())
+    }
+
+    // Null sorts first, then numbers, then text
+    fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) => Ordering::Less,
+            (_, Value::Null) => Ordering::Greater,
+            (Value::Number(a, _), Value::Number(b, _)) => {
+                a.partial_cmp(b).unwrap_or(Ordering::Equal)
+            }
+            (Value::Number(..), Value::Text(_)) => Ordering::Less,
+            (Value::Text(_), Value::Number(..)) => Ordering::Greater,
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => Ok(()),
+            Value::Number(_, ref text) | Value::Text(ref text) => f.write_str(text),
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Value::Null => serializer.serialize_none(),
+            // Counts and other whole numbers shouldn't turn into 3.0
+            Value::Number(number, ref text)
+                if number.fract() == 0.0 && number.abs() < 1e15 && *text == number.to_string() =>
+            {
+                serializer.serialize_i64(number as i64)
+            }
+            Value::Number(number, ref text) if *text == number.to_string() => {
+                serializer.serialize_f64(number)
+            }
+            // Numbers that an f64 can't represent exactly stay as they are
+            Value::Number(_, ref text) | Value::Text(ref text) => serializer.serialize_str(text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl FromStr for Function {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "count" => Ok(Function::Count),
+            "sum" => Ok(Function::Sum),
+            "avg" => Ok(Function::Avg),
+            "min" => Ok(Function::Min),
+            "max" => Ok(Function::Max),
+            _ => Err(format!("Unknown function '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Function::Count => "count",
+            Function::Sum => "sum",
+            Function::Avg => "avg",
+            Function::Min => "min",
+            Function::Max => "max",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Column(String),
+    // A column of None means all rows, as in count(*)
+    Aggregate(Function, Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Selection {
+    expr: Expr,
+    name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Predicate {
+    column: String,
+    operator: Operator,
+    value: Value,
+}
+
+impl Predicate {
+    // Values of different types are never equal and can't be ordered,
+    // so "radius > 1" is false for a radius of "unknown"
+    fn matches(&self, value: &Value) -> bool {
+        if self.operator == Operator::Contains {
+            return value.to_string().contains(&self.value.to_string());
+        }
+        let same_type = matches!(
+            (value, &self.value),
+            (Value::Null, Value::Null)
+                | (Value::Number(..), Value::Number(..))
+                | (Value::Text(_), Value::Text(_))
+        );
+        if !same_type {
+            return self.operator == Operator::Ne;
+        }
+        let ordering = value.compare(&self.value);
+        match self.operator {
+            Operator::Eq => ordering == Ordering::Equal,
+            Operator::Ne => ordering != Ordering::Equal,
+            Operator::Lt => ordering == Ordering::Less,
+            Operator::Le => ordering != Ordering::Greater,
+            Operator::Gt => ordering == Ordering::Greater,
+            Operator::Ge => ordering != Ordering::Less,
+            Operator::Contains => unreachable!(),
+        }
+    }
+}
+
+// Runs like SQL: filter, then group or select, then sort and limit
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Query {
+    selections: Vec<Selection>,
+    filters: Vec<Predicate>,
+    group_by: Vec<String>,
+    // Output columns, true for descending
+    sort_by: Vec<(String, bool)>,
+    limit: Option<usize>,
+}
+
+impl Query {
+    fn new() -> Self {
+        Query::default()
+    }
+
+    fn select(self, column: &str) -> Self {
+        self.select_as(column, column)
+    }
+
+    fn select_as(mut self, column: &str, name: &str) -> Self {
+        self.selections.push(Selection {
+            expr: Expr::Column(column.to_string()),
+            name: name.to_string(),
+        });
+        self
+    }
+
+    // Use "*" as column to count rows
+    fn aggregate(self, function: Function, column: &str) -> Self {
+        let name = format!("{}({})", function, column);
+        self.aggregate_as(function, column, &name)
+    }
+
+    fn aggregate_as(mut self, function: Function, column: &str, name: &str) -> Self {
+        let column = if column == "*" {
+            None
+        } else {
+            Some(column.to_string())
+        };
+        self.selections.push(Selection {
+            expr: Expr::Aggregate(function, column),
+            name: name.to_string(),
+        });
+        self
+    }
+
+    // All filters have to match
+    fn filter(mut self, column: &str, operator: Operator, value: Value) -> Self {
+        self.filters.push(Predicate {
+            column: column.to_string(),
+            operator,
+            value,
+        });
+        self
+    }
+
+    fn group_by(mut self, column: &str) -> Self {
+        self.group_by.push(column.to_string());
+        self
+    }
+
+    fn sort_by(mut self, column: &str, descending: bool) -> Self {
+        self.sort_by.push((column.to_string(), descending));
+        self
+    }
+
+    fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn is_grouped(&self) -> bool {
+        !self.group_by.is_empty()
+            || self
+                .selections
+                .iter()
+                .any(|selection| matches!(selection.expr, Expr::Aggregate(..)))
+    }
+
+    // Reads the records one at a time. Only the selected rows,
+    // or one row per group, are kept in memory
+    fn run<R: Read>(&self, reader: R) -> Result<Table, QueryError> {
+        let mut reader = csv::Reader::from_reader(reader);
+        let headers = reader.headers()?.clone();
+        let index_of = |column: &str| {
+            headers
+                .iter()
+                .position(|header| header == column)
+                .ok_or_else(|| QueryError::UnknownColumn(column.to_string()))
+        };
+        let filters = self
+            .filters
+            .iter()
+            .map(|filter| Ok((index_of(&filter.column)?, filter)))
+            .collect::<Result<Vec<_>, QueryError>>()?;
+        let group_by = self
+            .group_by
+            .iter()
+            .map(|column| index_of(column))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let grouped = self.is_grouped();
+        let selections = if !self.selections.is_empty() {
+            self.selections.clone()
+        } else if grouped {
+            let mut selections = Query::new();
+            for column in &self.group_by {
+                selections = selections.select(column);
+            }
+            selections
+                .aggregate_as(Function::Count, "*", "count")
+                .selections
+        } else {
+            headers
+                .iter()
+                .fold(Query::new(), |selections, column| selections.select(column))
+                .selections
+        };
+        // Where to find the value of each selection: a column of the
+        // record, a position in the group key, or the column to aggregate
+        let sources = selections
+            .iter()
+            .map(|selection| match selection.expr {
+                Expr::Column(ref column) if grouped => self
+                    .group_by
+                    .iter()
+                    .position(|group| group == column)
+                    .ok_or_else(|| QueryError::NotGrouped(column.clone())),
+                Expr::Column(ref column) => index_of(column),
+                Expr::Aggregate(_, Some(ref column)) => index_of(column),
+                Expr::Aggregate(Function::Count, None) => Ok(0),
+                Expr::Aggregate(_, None) => Err(QueryError::UnknownColumn("*".to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut rows = Vec::new();
+        let mut groups: HashMap<Vec<String>, usize> = HashMap::new();
+        let mut accumulators: Vec<Vec<Accumulator>> = Vec::new();
+        let mut group_keys: Vec<Vec<String>> = Vec::new();
+        let mut record = csv::StringRecord::new();
+        while reader.read_record(&mut record)? {
+            let line = record.position().map_or(0, |position| position.line());
+            let matches = filters
+                .iter()
+                .all(|&(index, filter)| filter.matches(&Value::parse(&record[index])));
+            if !matches {
+                continue;
+            }
+            if !grouped {
+                rows.push(
+                    sources
+                        .iter()
+                        .map(|&index| Value::parse(&record[index]))
+                        .collect(),
+                );
+                // Without sorting, there's no need to read further than the limit
+                if self.sort_by.is_empty() && Some(rows.len()) == self.limit {
+                    break;
+                }
+                continue;
+            }
+            let key: Vec<String> = group_by
+                .iter()
+                .map(|&index| record[index].to_string())
+                .collect();
+            // Groups are output in the order they were first seen
+            let group = *groups.entry(key.clone()).or_insert_with(|| {
+                group_keys.push(key);
+                accumulators.push(
+                    selections
+                        .iter()
+                        .zip(&sources)
+                        .map(|(selection, &index)| Accumulator::new(&selection.expr, index))
+                        .collect(),
+                );
+                accumulators.len() - 1
+            });
+            for (accumulator, &index) in accumulators[group].iter_mut().zip(&sources) {
+                // The index of a group column points into the key, not into the record
+                if let Accumulator::Key(_) = *accumulator {
+                    continue;
+                }
+                accumulator.add(&record[index], line)?;
+            }
+        }
+        // Aggregating without grouping always returns one row, even for no input
+        if grouped && group_by.is_empty() && accumulators.is_empty() {
+            group_keys.push(Vec::new());
+            accumulators.push(
+                selections
+                    .iter()
+                    .zip(&sources)
+                    .map(|(selection, &index)| Accumulator::new(&selection.expr, index))
+                    .collect(),
+            );
+        }
+        if grouped {
+            rows = group_keys
+                .iter()
+                .zip(accumulators)
+                .map(|(key, accumulators)| {
+                    accumulators
+                        .into_iter()
+                        .map(|accumulator| accumulator.finish(key))
+                        .collect()
+                })
+                .collect();
+        }
+
+        let names: Vec<String> = selections
+            .into_iter()
+            .map(|selection| selection.name)
+            .collect();
+        let sort_by = self
+            .sort_by
+            .iter()
+            .map(|(column, descending)| {
+                names
+                    .iter()
+                    .position(|name| name == column)
+                    .map(|index| (index, *descending))
+                    .ok_or_else(|| QueryError::UnknownColumn(column.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // The sort is stable, so rows that compare equal keep their order
+        rows.sort_by(|a: &Vec<Value>, b: &Vec<Value>| {
+            sort_by
+                .iter()
+                .map(|&(index, descending)| {
+                    let ordering = a[index].compare(&b[index]);
+                    if descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|&ordering| ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+        if let Some(limit) = self.limit {
+            rows.truncate(limit);
+        }
+        Ok(Table {
+            headers: names,
+            rows,
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Accumulator {
+    // A column that is grouped by, at this position in the group key
+    Key(usize),
+    Count {
+        all_rows: bool,
+        count: usize,
+    },
+    Sum {
+        column: String,
+        average: bool,
+        sum: f64,
+        count: usize,
+    },
+    Extreme {
+        max: bool,
+        value: Value,
+    },
+}
+
+impl Accumulator {
+    fn new(expr: &Expr, index: usize) -> Self {
+        match *expr {
+            Expr::Column(_) => Accumulator::Key(index),
+            Expr::Aggregate(Function::Count, ref column) => Accumulator::Count {
+                all_rows: column.is_none(),
+                count: 0,
+            },
+            Expr::Aggregate(function @ Function::Sum, ref column)
+            | Expr::Aggregate(function @ Function::Avg, ref column) => Accumulator::Sum {
+                column: column.clone().unwrap_or_else(|| "*".to_string()),
+                average: function == Function::Avg,
+                sum: 0.0,
+                count: 0,
+            },
+            Expr::Aggregate(function, _) => Accumulator::Extreme {
+                max: function == Function::Max,
+                value: Value::Null,
+            },
+        }
+    }
+
+    // Nulls are ignored by everything but count(*)
+    fn add(&mut self, field: &str, line: u64) -> Result<(), QueryError> {
+        let value = Value::parse(field);
+        match *self {
+            Accumulator::Key(_) => {}
+            Accumulator::Count {
+                all_rows,
+                ref mut count,
+            } => {
+                if all_rows || value != Value::Null {
+                    *count += 1;
+                }
+            }
+            Accumulator::Sum {
+                ref column,
+                ref mut sum,
+                ref mut count,
+                ..
+            } => match value {
+                Value::Null => {}
+                Value::Number(number, _) => {
+                    *sum += number;
+                    *count += 1;
+                }
+                Value::Text(text) => {
+                    return Err(QueryError::NotNumeric {
+                        column: column.clone(),
+                        line,
+                        value: text,
+                    })
+                }
+            },
+            Accumulator::Extreme {
+                max,
+                value: ref mut extreme,
+            } => {
+                let better = if max {
+                    Ordering::Greater
+                } else {
+                    Ordering::Less
+                };
+                if value != Value::Null
+                    && (*extreme == Value::Null || value.compare(extreme) == better)
+                {
+                    *extreme = value;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self, key: &[String]) -> Value {
+        match self {
+            Accumulator::Key(index) => Value::parse(&key[index]),
+            Accumulator::Count { count, .. } => Value::number(count as f64),
+            // Like in SQL, the sum of nothing is null and not 0
+            Accumulator::Sum { count: 0, .. } => Value::Null,
+            Accumulator::Sum {
+                average: true,
+                sum,
+                count,
+                ..
+            } => Value::number(sum / count as f64),
+            Accumulator::Sum { sum, .. } => Value::number(sum),
+            Accumulator::Extreme { value, .. } => value,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl Table {
+    fn write_csv<W: Write>(&self, writer: W) -> csv::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(&self.headers)?;
+        for row in &self.rows {
+            writer.write_record(row.iter().map(Value::to_string))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// An array of objects whose keys keep the order of the columns
+impl Serialize for Table {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct Row<'a>(&'a [String], &'a [Value]);
+
+        impl<'a> Serialize for Row<'a> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut map = serializer.serialize_map(Some(self.0.len()))?;
+                for (header, value) in self.0.iter().zip(self.1) {
+                    map.serialize_entry(header, value)?;
+                }
+                map.end()
+            }
+        }
+
+        let mut seq = serializer.serialize_seq(Some(self.rows.len()))?;
+        for row in &self.rows {
+            seq.serialize_element(&Row(&self.headers, row))?;
+        }
+        seq.end()
+    }
+}
+
+#[derive(Debug)]
+enum QueryError {
+    Csv(csv::Error),
+    UnknownColumn(String),
+    // Grouped queries can only select the columns they are grouped by
+    NotGrouped(String),
+    NotNumeric {
+        column: String,
+        line: u64,
+        value: String,
+    },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryError::Csv(ref e) => write!(f, "Failed to read CSV: {}", e),
+            QueryError::UnknownColumn(ref column) => write!(f, "Unknown column '{}'", column),
+            QueryError::NotGrouped(ref column) => write!(
+                f,
+                "Column '{}' must be aggregated or appear in group by",
+                column
+            ),
+            QueryError::NotNumeric {
+                ref column,
+                line,
+                ref value,
+            } => write!(
+                f,
+                "Column '{}' has the non-numeric value '{}' on line {}",
+                column, value, line
+            ),
+        }
+    }
+}
+
+impl error::Error for QueryError {}
+
+impl From<csv::Error> for QueryError {
+    fn from(e: csv::Error) -> Self {
+        QueryError::Csv(e)
+    }
+}
+
+// The syntax is a small subset of SQL:
+//   [select <column> [as <name>], <function>(<column> | *) [as <name>], ...]
+//   [where <column> <operator> <value> [and ...]]
+//   [group by <column>, ...]
+//   [sort by <column> [asc | desc], ...]
+//   [limit <number>]
+// Column names that aren't plain words can be quoted with `backticks`
+impl FromStr for Query {
+    type Err = SyntaxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+            end: s.chars().count(),
+        };
+        parser.query()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct SyntaxError {
+    // Counted in characters, starting at 0
+    position: usize,
+    message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl error::Error for SyntaxError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    // A `quoted` column name
+    Quoted(String),
+    Text(String),
+    // Keeps its text, so "zip contains 021" doesn't look for "21"
+    Number(f64, String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &["!=", "<=", ">=", "=", "<", ">", ",", "(", ")", "*"];
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, SyntaxError> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let start = pos;
+        let c = chars[pos];
+        let rest: String = chars[pos..].iter().take(2).collect();
+        if c.is_whitespace() {
+            pos += 1;
+            continue;
+        }
+        let token = if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            pos += symbol.len();
+            Token::Symbol(symbol)
+        } else if c == '"' || c == '\'' || c == '`' {
+            let end = chars[pos + 1..]
+                .iter()
+                .position(|&other| other == c)
+                .ok_or_else(|| SyntaxError {
+                    position: start,
+                    message: format!("Missing closing {}", c),
+                })?;
+            let text: String = chars[pos + 1..pos + 1 + end].iter().collect();
+            pos += end + 2;
+            if c == '`' {
+                Token::Quoted(text)
+            } else {
+                Token::Text(text)
+            }
+        } else if c.is_ascii_digit() || c == '-' || c == '.' {
+            while pos < chars.len()
+                && (chars[pos].is_ascii_alphanumeric() || "+-.".contains(chars[pos]))
+            {
+                pos += 1;
+            }
+            let number: String = chars[start..pos].iter().collect();
+            match Value::parse(&number) {
+                Value::Number(number, text) => Token::Number(number, text),
+                _ => {
+                    return Err(SyntaxError {
+                        position: start,
+                        message: format!("Invalid number '{}'", number),
+                    })
+                }
+            }
+        } else if c.is_alphanumeric() || c == '_' {
+            while pos < chars.len() && (chars[pos].is_alphanumeric() || chars[pos] == '_') {
+                pos += 1;
+            }
+            Token::Word(chars[start..pos].iter().collect())
+        } else {
+            return Err(SyntaxError {
+                position: start,
+                message: format!("Unexpected character '{}'", c),
+            });
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    // Position of the end of the input, for errors there
+    end: usize,
+}
+
+impl Parser {
+    fn query(&mut self) -> Result<Query, SyntaxError> {
+        let mut query = Query::new();
+        if self.keyword("select") {
+            loop {
+                query.selections.push(self.selection()?);
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+        if self.keyword("where") {
+            loop {
+                query.filters.push(self.predicate()?);
+                if !self.keyword("and") {
+                    break;
+                }
+            }
+        }
+        if self.keyword("group") {
+            self.expect_keyword("by")?;
+            loop {
+                query.group_by.push(self.column()?);
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+        if self.keyword("sort") || self.keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let column = self.column()?;
+                let descending = self.keyword("desc");
+                if !descending {
+                    self.keyword("asc");
+                }
+                query.sort_by.push((column, descending));
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+        if self.keyword("limit") {
+            match self.next() {
+                Some(Token::Number(limit, _)) if limit >= 0.0 && limit.fract() == 0.0 => {
+                    query.limit = Some(limit as usize)
+                }
+                _ => return Err(self.error_at_previous("Expected a whole number after limit")),
+            }
+        }
+        if self.pos < self.tokens.len() {
+            return Err(self.error("Unexpected input"));
+        }
+        Ok(query)
+    }
+
+    fn selection(&mut self) -> Result<Selection, SyntaxError> {
+        let is_call =
+            self.tokens.get(self.pos + 1).map(|token| &token.1) == Some(&Token::Symbol("("));
+        let (expr, default_name) = if is_call {
+            let name = self.column()?;
+            let function: Function = name
+                .parse()
+                .map_err(|message: String| self.error_at_previous(&message))?;
+            self.symbol("(");
+            let column = if self.symbol("*") {
+                // Summing up whole rows makes no sense
+                if function != Function::Count {
+                    return Err(self.error_at_previous(&format!(
+                        "Only count can be used with *, {} needs a column",
+                        function
+                    )));
+                }
+                None
+            } else {
+                Some(self.column()?)
+            };
+            if !self.symbol(")") {
+                return Err(self.error("Expected ')'"));
+            }
+            let default_name = format!(
+                "{}({})",
+                function,
+                column.as_ref().map_or("*", String::as_str)
+            );
+            (Expr::Aggregate(function, column), default_name)
+        } else {
+            let column = self.column()?;
+            (Expr::Column(column.clone()), column)
+        };
+        let name = if self.keyword("as") {
+            self.column()?
+        } else {
+            default_name
+        };
+        Ok(Selection { expr, name })
+    }
+
+    fn predicate(&mut self) -> Result<Predicate, SyntaxError> {
+        let column = self.column()?;
+        let operator = match self.next() {
+            Some(Token::Symbol("=")) => Operator::Eq,
+            Some(Token::Symbol("!=")) => Operator::Ne,
+            Some(Token::Symbol("<")) => Operator::Lt,
+            Some(Token::Symbol("<=")) => Operator::Le,
+            Some(Token::Symbol(">")) => Operator::Gt,
+            Some(Token::Symbol(">=")) => Operator::Ge,
+            Some(Token::Word(ref word)) if word.eq_ignore_ascii_case("contains") => {
+                Operator::Contains
+            }
+            _ => return Err(self.error_at_previous("Expected a comparison")),
+        };
+        let value = match self.next() {
+            Some(Token::Number(number, text)) => Value::Number(number, text),
+            Some(Token::Text(text)) => Value::Text(text),
+            Some(Token::Word(ref word)) if word.eq_ignore_ascii_case("null") => Value::Null,
+            // Unquoted words are text too, as in name = Earth
+            Some(Token::Word(word)) => Value::Text(word),
+            _ => return Err(self.error_at_previous("Expected a value")),
+        };
+        Ok(Predicate {
+            column,
+            operator,
+            value,
+        })
+    }
+
+    fn column(&mut self) -> Result<String, SyntaxError> {
+        match self.next() {
+            Some(Token::Word(word)) | Some(Token::Quoted(word)) => Ok(word),
+            _ => Err(self.error_at_previous("Expected a column name")),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some((_, Token::Word(word))) if word.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), SyntaxError> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", keyword)))
+        }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some((_, Token::Symbol(other))) if *other == symbol => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|token| token.1.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, message: &str) -> SyntaxError {
+        SyntaxError {
+            position: self.tokens.get(self.pos).map_or(self.end, |token| token.0),
+            message: message.to_string(),
+        }
+    }
+
+    // For errors about the token that was just read
+    fn error_at_previous(&self, message: &str) -> SyntaxError {
+        SyntaxError {
+            position: self
+                .tokens
+                .get(self.pos.saturating_sub(1))
+                .map_or(self.end, |token| token.0),
+            message: message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SALES: &str = "region,product,units,price\n\
+                         north,apples,10,0.5\n\
+                         south,pears,4,0.75\n\
+                         north,pears,,0.8\n\
+                         east,apples,7,0.55\n\
+                         south,apples,12,0.45\n\
+                         north,apples,3,\n";
+
+    fn run(query: &str) -> Table {
+        query
+            .parse::<Query>()
+            .unwrap()
+            .run(SALES.as_bytes())
+            .unwrap()
+    }
+
+    fn csv(table: &Table) -> String {
+        let mut output = Vec::new();
+        table.write_csv(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn selects_filters_sorts_and_limits() {
+        let query = Query::new()
+            .select("product")
+            .select_as("units", "amount")
+            .filter("units", Operator::Ge, Value::number(4.0))
+            .filter("region", Operator::Ne, Value::Text("east".to_string()))
+            .sort_by("amount", true)
+            .limit(2);
+        let table = query.run(SALES.as_bytes()).unwrap();
+        assert_eq!("product,amount\napples,12\napples,10\n", csv(&table));
+        assert_eq!(
+            query,
+            "select product, units as amount where units >= 4 and region != 'east' \
+             sort by amount desc limit 2"
+                .parse()
+                .unwrap()
+        );
+
+        // Without a selection, all columns are returned
+        assert_eq!(
+            "region,product,units,price\nnorth,pears,,0.8\n",
+            csv(&run("where units = null"))
+        );
+        assert_eq!(
+            "product\npears\npears\n",
+            csv(&run("select product where product contains ea"))
+        );
+        // Ties keep their original order, so sorting by several columns works as expected
+        assert_eq!(
+            "region,price\neast,0.55\nnorth,\nnorth,0.5\nnorth,0.8\nsouth,0.45\nsouth,0.75\n",
+            csv(&run("SELECT region, price ORDER BY region, price"))
+        );
+    }
+
+    #[test]
+    fn groups_and_aggregates() {
+        let table = run(
+            "select region, count(*), count(units) as with_units, sum(units), \
+             avg(price), min(product), max(units) group by region sort by region",
+        );
+        assert_eq!(
+            "region,count(*),with_units,sum(units),avg(price),min(product),max(units)\n\
+             east,1,1,7,0.55,apples,7\n\
+             north,3,2,13,0.65,apples,10\n\
+             south,2,2,16,0.6,apples,12\n",
+            csv(&table)
+        );
+
+        // Grouping by several columns, without a selection
+        assert_eq!(
+            "product,region,count\napples,north,2\npears,south,1\npears,north,1\n\
+             apples,east,1\napples,south,1\n",
+            csv(&run("group by product, region"))
+        );
+        // Aggregating without grouping summarizes all rows
+        assert_eq!(
+            "rows,total\n6,36\n",
+            csv(&run("select count(*) as rows, sum(units) as total"))
+        );
+        // Nothing to aggregate still gives one row, with a null sum
+        assert_eq!(
+            "rows,sum(units)\n0,\n",
+            csv(&run(
+                "select count(*) as rows, sum(units) where region = west"
+            ))
+        );
+    }
+
+    #[test]
+    fn keeps_fields_as_they_are() {
+        let input = "zip,id,price\n02134,12345678901234567891,1.10\n02134,7, 2.5\n";
+        let run = |query: &str| {
+            query
+                .parse::<Query>()
+                .unwrap()
+                .run(input.as_bytes())
+                .unwrap()
+        };
+        assert_eq!(
+            "zip,id,price\n02134,12345678901234567891,1.10\n",
+            csv(&run("where price < 2"))
+        );
+        // Group keys and extremes are copied too, only computed values are formatted
+        assert_eq!(
+            "zip,max(price),sum(price)\n02134, 2.5,3.6\n",
+            csv(&run("select zip, max(price), sum(price) group by zip"))
+        );
+        assert_eq!(
+            "[{\"zip\":\"02134\",\"id\":\"12345678901234567891\",\"price\":\"1.10\"}]",
+            serde_json::to_string(&run("where zip contains 021 limit 1")).unwrap()
+        );
+    }
+
+    #[test]
+    fn writes_json() {
+        let table = run("select product, sum(units) as units, avg(price) as price \
+                         group by product sort by units desc");
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(
+            "[{\"product\":\"apples\",\"units\":32,\"price\":0.5},\
+             {\"product\":\"pears\",\"units\":4,\"price\":0.775}]",
+            json
+        );
+    }
+
+    #[test]
+    fn reports_query_errors() {
+        let run = |query: &str| query.parse::<Query>().unwrap().run(SALES.as_bytes());
+        match run("select colour") {
+            Err(QueryError::UnknownColumn(ref column)) if column == "colour" => {}
+            other => panic!("Expected an unknown column, got {:?}", other),
+        }
+        match run("select product, count(*) group by region") {
+            Err(QueryError::NotGrouped(ref column)) if column == "product" => {}
+            other => panic!("Expected an ungrouped column, got {:?}", other),
+        }
+        match run("select sum(product)") {
+            Err(QueryError::NotNumeric { line: 2, .. }) => {}
+            other => panic!("Expected a non-numeric value, got {:?}", other),
+        }
+        // Only count(*) works on whole rows
+        match Query::new()
+            .aggregate(Function::Avg, "*")
+            .run(SALES.as_bytes())
+        {
+            Err(QueryError::UnknownColumn(ref column)) if column == "*" => {}
+            other => panic!("Expected an unknown column, got {:?}", other),
+        }
+        match run("select units sort by price") {
+            Err(QueryError::UnknownColumn(ref column)) if column == "price" => {}
+            other => panic!("Expected an unknown column, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let position = |query: &str| query.parse::<Query>().unwrap_err().position;
+        assert_eq!(7, position("select median(units)"));
+        assert_eq!(24, position("select units where units"));
+        assert_eq!(13, position("where name = 'open"));
+        assert_eq!(19, position("select units limit -1"));
+        assert_eq!(13, position("select units units"));
+        assert_eq!(6, position("group of region"));
+        assert_eq!(11, position("select sum(*)"));
+        assert_eq!(21, position("select count(*), max(*)"));
+        assert_eq!(
+            "Unknown function 'median' at position 7",
+            "select median(units)"
+                .parse::<Query>()
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
@@ -0,0 +1,449 @@
+extern crate csv;
+extern crate regex;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::{error, fmt};
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Planet {
+    name: String,
+    radius: f32,
+    distance_from_sun: f32,
+    gravity: f32,
+}
+
+const INPUT: &str = "\
+name,radius,distance_from_sun,gravity
+Mercury,0.38,0.47,0.38
+Venus,zero point nine,0.73,0.9
+,1,1,1
+Mars,0.53,1.67
+Jupiter,11.21,5.46,2.53
+saturn,-9.45,10.12,many
+";
+
+fn main() {
+    let validator = Validator::new()
+        .rule("name", Rule::Required)
+        .rule(
+            "name",
+            Rule::matches("^[A-Z][a-z]+$").expect("Invalid regex"),
+        )
+        .rule(
+            "radius",
+            Rule::Range {
+                min: 0.0,
+                max: 100.0,
+            },
+        )
+        .rule(
+            "gravity",
+            Rule::Range {
+                min: 0.0,
+                max: 50.0,
+            },
+        );
+
+    let valid = File::create("valid_planets.csv").expect("Failed to create file");
+    let rejected = File::create("rejected_planets.csv").expect("Failed to create file");
+    let report = validator
+        .validate::<Planet, _, _, _>(INPUT.as_bytes(), valid, rejected)
+        .expect("Failed to validate csv");
+
+    println!(
+        "{} valid rows were written to valid_planets.csv",
+        report.valid_rows
+    );
+    println!(
+        "{} rejected rows were written to rejected_planets.csv:",
+        report.rejected_rows
+    );
+    for violation in &report.violations {
+        println!("{}", violation);
+    }
+}
+
+// A check that is run on the raw value of a column
+#[derive(Debug)]
+enum Rule {
+    Required,
+    // Inclusive on both ends
+    Range { min: f64, max: f64 },
+    Matches(Regex),
+}
+
+impl Rule {
+    fn matches(pattern: &str) -> Result<Rule, regex::Error> {
+        Ok(Rule::Matches(Regex::new(pattern)?))
+    }
+
+    fn check(&self, value: &str) -> Result<(), String> {
+        match *self {
+            Rule::Required if value.trim().is_empty() => Err("value is required".to_string()),
+            Rule::Required => Ok(()),
+            // Empty values are Required's business
+            _ if value.is_empty() => Ok(()),
+            Rule::Range { min, max } => match value.trim().parse::<f64>() {
+                Ok(number) if number >= min && number <= max => Ok(()),
+                Ok(_) => Err(format!("must be between {} and {}", min, max)),
+                Err(_) => Err("is not a number".to_string()),
+            },
+            Rule::Matches(ref regex) if regex.is_match(value) => Ok(()),
+            Rule::Matches(ref regex) => Err(format!("does not match {}", regex)),
+        }
+    }
+}
+
+// A single problem in the input
+#[derive(Debug, PartialEq)]
+struct Violation {
+    line: u64,
+    // None if the problem concerns the whole row
+    column: Option<String>,
+    value: Option<String>,
+    reason: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let Some(ref column) = self.column {
+            write!(f, ", column '{}'", column)?;
+        }
+        if let Some(ref value) = self.value {
+            write!(f, " ('{}')", value)?;
+        }
+        write!(f, ": {}", self.reason)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    valid_rows: usize,
+    rejected_rows: usize,
+    violations: Vec<Violation>,
+}
+
+#[derive(Debug)]
+enum ValidationError {
+    Csv(csv::Error),
+    // A rule was given for a column that is not in the header
+    UnknownColumn(String),
+}
+
+#[derive(Debug, Default)]
+struct Validator {
+    rules: Vec<(String, Rule)>,
+}
+
+impl Validator {
+    fn new() -> Self {
+        Validator::default()
+    }
+
+    fn rule(mut self, column: &str, rule: Rule) -> Self {
+        self.rules.push((column.to_string(), rule));
+        self
+    }
+
+    // Check every row of the input instead of stopping at the first error.
+    // Rows are copied as they were, the ones that pass to `valid` and
+    // the others to `rejected`, together with their line and what's wrong
+    fn validate<T, R, V, J>(
+        &self,
+        reader: R,
+        valid: V,
+        rejected: J,
+    ) -> Result<Report, ValidationError>
+    where
+        T: DeserializeOwned,
+        R: Read,
+        V: Write,
+        J: Write,
+    {
+        // Rows with the wrong number of fields are something we want to
+        // report, so don't let the reader turn them into errors
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+        let mut valid = csv::Writer::from_writer(valid);
+        let mut rejected = csv::WriterBuilder::new()
+            .flexible(true)
+            .from_writer(rejected);
+
+        let headers = reader.headers()?.clone();
+        let mut rules = Vec::with_capacity(self.rules.len());
+        for (column, rule) in &self.rules {
+            match headers.iter().position(|header| header == column) {
+                Some(index) => rules.push((index, rule)),
+                None => return Err(ValidationError::UnknownColumn(column.clone())),
+            }
+        }
+        valid.write_record(&headers)?;
+        let mut rejected_headers = headers.clone();
+        rejected_headers.push_field("line");
+        rejected_headers.push_field("errors");
+        rejected.write_record(&rejected_headers)?;
+
+        let mut report = Report::default();
+        let mut record = csv::StringRecord::new();
+        while reader.read_record(&mut record)? {
+            let line = record.position().map_or(0, |position| position.line());
+            let violations = check_record::<T>(&headers, &record, &rules, line);
+            if violations.is_empty() {
+                valid.write_record(&record)?;
+                report.valid_rows += 1;
+                continue;
+            }
+
+            let reasons: Vec<_> = violations
+                .iter()
+                .map(|violation| match violation.column {
+                    Some(ref column) => format!("{}: {}", column, violation.reason),
+                    None => violation.reason.clone(),
+                })
+                .collect();
+            let mut rejected_record = record.clone();
+            rejected_record.push_field(&line.to_string());
+            rejected_record.push_field(&reasons.join("; "));
+            rejected.write_record(&rejected_record)?;
+            report.rejected_rows += 1;
+            report.violations.extend(violations);
+        }
+
+        valid.flush()?;
+        rejected.flush()?;
+        Ok(report)
+    }
+}
+
+// Run every rule on a record and see if it can be deserialized
+fn check_record<T>(
+    headers: &csv::StringRecord,
+    record: &csv::StringRecord,
+    rules: &[(usize, &Rule)],
+    line: u64,
+) -> Vec<Violation>
+where
+    T: DeserializeOwned,
+{
+    if record.len() != headers.len() {
+        return vec![Violation {
+            line,
+            column: None,
+            value: None,
+            reason: format!("expected {} fields, found {}", headers.len(), record.len()),
+        }];
+    }
+
+    let violation = |index: usize, reason: String| Violation {
+        line,
+        column: Some(headers[index].to_string()),
+        value: Some(record[index].to_string()),
+        reason,
+    };
+    let mut violations = Vec::new();
+    for &(index, rule) in rules {
+        if let Err(reason) = rule.check(&record[index]) {
+            violations.push(violation(index, reason));
+        }
+    }
+
+    // Serde stops at the first field it can't handle, so this
+    // finds at most one problem. If a rule already complained
+    // about the same column, there's nothing new to learn from it
+    match record.deserialize::<T>(Some(headers)) {
+        Ok(_) => {}
+        Err(e) => {
+            let (index, reason) = match *e.kind() {
+                csv::ErrorKind::Deserialize { ref err, .. } => (
+                    err.field().map(|field| field as usize),
+                    err.kind().to_string(),
+                ),
+                ref kind => (None, format!("{:?}", kind)),
+            };
+            let reported = |index| {
+                violations
+                    .iter()
+                    .any(|v| v.column.as_deref() == Some(&headers[index]))
+            };
+            match index {
+                Some(index) if reported(index) => {}
+                Some(index) => violations.push(violation(index, reason)),
+                None => violations.push(Violation {
+                    line,
+                    column: None,
+                    value: None,
+                    reason,
+                }),
+            }
+        }
+    }
+    violations
+}
+
+impl From<csv::Error> for ValidationError {
+    fn from(e: csv::Error) -> Self {
+        ValidationError::Csv(e)
+    }
+}
+
+impl From<io::Error> for ValidationError {
+    fn from(e: io::Error) -> Self {
+        ValidationError::Csv(e.into())
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::Csv(ref e) => write!(f, "CSV error: {}", e),
+            ValidationError::UnknownColumn(ref column) => {
+                write!(f, "There is no column called '{}'", column)
+            }
+        }
+    }
+}
+
+impl error::Error for ValidationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planet_validator() -> Validator {
+        Validator::new()
+            .rule("name", Rule::Required)
+            .rule("name", Rule::matches("^[A-Z][a-z]+$").unwrap())
+            .rule(
+                "radius",
+                Rule::Range {
+                    min: 0.0,
+                    max: 100.0,
+                },
+            )
+            .rule(
+                "gravity",
+                Rule::Range {
+                    min: 0.0,
+                    max: 50.0,
+                },
+            )
+    }
+
+    fn validate(input: &str) -> (Report, String, String) {
+        let mut valid = Vec::new();
+        let mut rejected = Vec::new();
+        let report = planet_validator()
+            .validate::<Planet, _, _, _>(input.as_bytes(), &mut valid, &mut rejected)
+            .unwrap();
+        (
+            report,
+            String::from_utf8(valid).unwrap(),
+            String::from_utf8(rejected).unwrap(),
+        )
+    }
+
+    fn violation(line: u64, column: &str, value: &str, reason: &str) -> Violation {
+        Violation {
+            line,
+            column: Some(column.to_string()),
+            value: Some(value.to_string()),
+            reason: reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn collects_every_violation() {
+        let (report, _, _) = validate(INPUT);
+        assert_eq!(2, report.valid_rows);
+        assert_eq!(4, report.rejected_rows);
+        assert_eq!(
+            vec![
+                violation(3, "radius", "zero point nine", "is not a number"),
+                violation(4, "name", "", "value is required"),
+                Violation {
+                    line: 5,
+                    column: None,
+                    value: None,
+                    reason: "expected 4 fields, found 3".to_string(),
+                },
+                violation(7, "name", "saturn", "does not match ^[A-Z][a-z]+$"),
+                violation(7, "radius", "-9.45", "must be between 0 and 100"),
+                violation(7, "gravity", "many", "is not a number"),
+            ],
+            report.violations
+        );
+    }
+
+    #[test]
+    fn reports_type_errors_without_rules() {
+        let input = "name,radius,distance_from_sun,gravity\nMars,0.53,far,0.38\n";
+        let (report, valid, _) = validate(input);
+        assert_eq!(1, report.violations.len());
+        let violation = &report.violations[0];
+        assert_eq!(Some("distance_from_sun"), violation.column.as_deref());
+        assert_eq!(Some("far"), violation.value.as_deref());
+        assert_eq!(
+            "line 2, column 'distance_from_sun' ('far'): invalid float literal",
+            violation.to_string()
+        );
+        assert_eq!("name,radius,distance_from_sun,gravity\n", valid);
+    }
+
+    #[test]
+    fn splits_valid_and_rejected_rows() {
+        let (_, valid, rejected) = validate(INPUT);
+        let mut reader = csv::Reader::from_reader(valid.as_bytes());
+        let planets: Vec<Planet> = reader.deserialize().map(Result::unwrap).collect();
+        let names: Vec<_> = planets.iter().map(|planet| planet.name.as_str()).collect();
+        assert_eq!(vec!["Mercury", "Jupiter"], names);
+
+        let mut lines = rejected.lines();
+        assert_eq!(
+            Some("name,radius,distance_from_sun,gravity,line,errors"),
+            lines.next()
+        );
+        assert_eq!(
+            Some("Venus,zero point nine,0.73,0.9,3,radius: is not a number"),
+            lines.next()
+        );
+        assert_eq!(Some(",1,1,1,4,name: value is required"), lines.next());
+        assert_eq!(
+            Some("Mars,0.53,1.67,5,expected 4 fields, found 3"),
+            lines.next().map(|line| line.replace('"', "")).as_deref()
+        );
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("saturn,-9.45,10.12,many,7,"));
+        assert_eq!(None, lines.next());
+    }
+
+    #[test]
+    fn rejects_rules_for_unknown_columns() {
+        let validator = Validator::new().rule("mass", Rule::Required);
+        let result =
+            validator.validate::<Planet, _, _, _>(INPUT.as_bytes(), io::sink(), io::sink());
+        match result {
+            Err(ValidationError::UnknownColumn(column)) => assert_eq!("mass", column),
+            other => panic!("Expected an unknown column, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn checks_single_rules() {
+        let range = Rule::Range { min: 1.0, max: 2.0 };
+        assert!(range.check("1").is_ok());
+        assert!(range.check(" 2.0 ").is_ok());
+        assert!(range.check("2.1").is_err());
+        assert!(range.check("").is_ok());
+        assert!(Rule::Required.check("  ").is_err());
+        assert!(Rule::matches("^a+$").unwrap().check("aaa").is_ok());
+        assert!(Rule::matches("^a+$").unwrap().check("aba").is_err());
+    }
+}
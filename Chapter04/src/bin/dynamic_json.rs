@@ -1,33 +1,72 @@
 #[macro_use]
 extern crate serde_json;
 
-use std::io::{self, BufRead};
-use std::collections::HashMap;
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::{env, error, fmt, process};
 
 fn main() {
-    // A HashMap is the same as a JSON without any schema
-    let mut key_value_map = HashMap::new();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (pretty, path) = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => (true, None),
+        ["--compact"] => (false, None),
+        ["--compact", path] => (false, Some(path)),
+        [path] => (true, Some(path)),
+        _ => {
+            eprintln!("Usage: dynamic_json [--compact] [file]");
+            process::exit(2);
+        }
+    };
+
     let stdin = io::stdin();
-    println!("Enter a key and a value");
-    for input in stdin.lock().lines() {
+    let reader: Box<dyn BufRead> = match path {
+        Some(path) => Box::new(BufReader::new(
+            File::open(path).expect("Failed to open input file"),
+        )),
+        None => {
+            // The prompts go to stderr so that stdout only contains the JSON
+            eprintln!("Enter a key and a value");
+            Box::new(stdin.lock())
+        }
+    };
+
+    // A map of JSON values is the same as a JSON without any schema
+    let mut key_value_map = Map::new();
+    let mut malformed_lines = 0;
+    for (index, input) in reader.lines().enumerate() {
         let input = input.expect("Failed to read line");
-        let key_value: Vec<_> = input.split_whitespace().collect();
-        let key = key_value[0].to_string();
-        let value = key_value[1].to_string();
-
-        println!("Saving key-value pair: {} -> {}", key, value);
-        // The json! macro lets us convert a value into its JSON representation
-        key_value_map.insert(key, json!(value));
-        println!(
-            "Enter another pair or stop by pressing '{}'",
-            END_OF_TRANSMISSION
-        );
+        // Malformed lines are reported and skipped, so that a single
+        // typo doesn't throw away everything that was entered so far
+        match insert_line(&mut key_value_map, index + 1, &input) {
+            Ok(Some((key, value))) => eprintln!("Saving key-value pair: {} -> {}", key, value),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                malformed_lines += 1;
+            }
+        }
+        if path.is_none() {
+            eprintln!(
+                "Enter another pair or stop by pressing '{}'",
+                END_OF_TRANSMISSION
+            );
+        }
     }
+
+    let json = Value::Object(key_value_map);
     // to_string_pretty returns a JSON with nicely readable whitespace
-    let json =
-        serde_json::to_string_pretty(&key_value_map).expect("Failed to convert HashMap into JSON");
-    println!("Your input has been made into the following JSON:");
+    let json = if pretty {
+        serde_json::to_string_pretty(&json)
+    } else {
+        serde_json::to_string(&json)
+    }
+    .expect("Failed to convert map into JSON");
     println!("{}", json);
+    if malformed_lines > 0 {
+        eprintln!("Skipped {} malformed line(s)", malformed_lines);
+        process::exit(1);
+    }
 }
 
 #[cfg(target_os = "windows")]
@@ -35,3 +74,207 @@ const END_OF_TRANSMISSION: &str = "Ctrl Z";
 
 #[cfg(not(target_os = "windows"))]
 const END_OF_TRANSMISSION: &str = "Ctrl D";
+
+#[derive(Debug, PartialEq)]
+struct LineError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for LineError {}
+
+// Parses a line and stores its value in the map. Returns what
+// was set, or None if the line was blank or a comment
+fn insert_line(
+    map: &mut Map<String, Value>,
+    line: usize,
+    input: &str,
+) -> Result<Option<(String, Value)>, LineError> {
+    let error = |message: String| LineError { line, message };
+    let (key, value) = match parse_line(input).map_err(error)? {
+        Some(key_value) => key_value,
+        None => return Ok(None),
+    };
+    insert_nested(map, &key, value.clone()).map_err(error)?;
+    Ok(Some((key, value)))
+}
+
+// Accepts "key=value", "key = value" and "key value"
+fn parse_line(input: &str) -> Result<Option<(String, Value)>, String> {
+    let input = input.trim();
+    if input.is_empty() || input.starts_with('#') {
+        return Ok(None);
+    }
+    let key_end = input
+        .find(|c: char| c == '=' || c.is_whitespace())
+        .unwrap_or(input.len());
+    let key = &input[..key_end];
+    if key.is_empty() {
+        return Err("Missing key before '='".to_string());
+    }
+    let rest = input[key_end..].trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest).trim_start();
+    if rest.is_empty() {
+        return Err(format!("Missing value for key '{}'", key));
+    }
+    Ok(Some((key.to_string(), parse_value(rest)?)))
+}
+
+fn parse_value(input: &str) -> Result<Value, String> {
+    match input.chars().next() {
+        // Double quotes support the same escapes as JSON strings
+        Some('"') => match serde_json::from_str(input) {
+            Ok(string @ Value::String(_)) => Ok(string),
+            _ => Err(format!("Invalid quoted value {}", input)),
+        },
+        // Single quotes take everything between them literally
+        Some('\'') => {
+            if input.len() > 1 && input.ends_with('\'') && !input[1..input.len() - 1].contains('\'')
+            {
+                Ok(json!(input[1..input.len() - 1]))
+            } else {
+                Err(format!("Invalid quoted value {}", input))
+            }
+        }
+        _ => Ok(infer_type(input)),
+    }
+}
+
+// Unquoted values become numbers, booleans or null if they look
+// like one, and strings otherwise. Quote a value to keep it a string
+fn infer_type(input: &str) -> Value {
+    match serde_json::from_str(input) {
+        Ok(value @ Value::Null) | Ok(value @ Value::Bool(_)) | Ok(value @ Value::Number(_)) => {
+            value
+        }
+        _ => json!(input),
+    }
+}
+
+// "a.b.c" creates the objects a and b if needed and sets c inside of b
+fn insert_nested(map: &mut Map<String, Value>, key: &str, value: Value) -> Result<(), String> {
+    let parts: Vec<_> = key.split('.').collect();
+    if parts.iter().any(|part| part.is_empty()) {
+        return Err(format!("Key '{}' contains an empty segment", key));
+    }
+    let (last, parents) = parts.split_last().expect("split always returns a part");
+    let mut map = map;
+    for (depth, part) in parents.iter().enumerate() {
+        let child = map
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+        map = match *child {
+            Value::Object(ref mut child) => child,
+            _ => {
+                return Err(format!(
+                    "Cannot set '{}' because '{}' is not an object",
+                    key,
+                    parts[..=depth].join(".")
+                ))
+            }
+        };
+    }
+    if map.get(*last).is_some_and(Value::is_object) && !value.is_object() {
+        return Err(format!(
+            "Cannot set '{}' because it already contains nested keys",
+            key
+        ));
+    }
+    map.insert(last.to_string(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> (Value, Vec<LineError>) {
+        let mut map = Map::new();
+        let errors = input
+            .lines()
+            .enumerate()
+            .filter_map(|(index, line)| insert_line(&mut map, index + 1, line).err())
+            .collect();
+        (Value::Object(map), errors)
+    }
+
+    #[test]
+    fn accepts_different_separators() {
+        let (json, errors) = parse("a=1\nb = 2\nc 3\nd\t=  4\ne=f=g\n");
+        assert!(errors.is_empty());
+        assert_eq!(json!({ "a": 1, "b": 2, "c": 3, "d": 4, "e": "f=g" }), json);
+    }
+
+    #[test]
+    fn infers_types() {
+        let input = "int 42\nnegative -7\nfloat 2.5\nexponent 1e3\nyes true\nno false\n\
+                     nothing null\nword hello\nsentence hello world\nleading_zero 007\n";
+        let (json, errors) = parse(input);
+        assert!(errors.is_empty());
+        assert_eq!(
+            json!({
+                "int": 42,
+                "negative": -7,
+                "float": 2.5,
+                "exponent": 1000.0,
+                "yes": true,
+                "no": false,
+                "nothing": null,
+                "word": "hello",
+                "sentence": "hello world",
+                "leading_zero": "007"
+            }),
+            json
+        );
+    }
+
+    #[test]
+    fn keeps_quoted_values_as_strings() {
+        let input = "a \"42\"\nb = 'true'\nc \"tab\\there\"\nd '  padded  '\ne \"\"\n";
+        let (json, errors) = parse(input);
+        assert!(errors.is_empty());
+        assert_eq!(
+            json!({ "a": "42", "b": "true", "c": "tab\there", "d": "  padded  ", "e": "" }),
+            json
+        );
+    }
+
+    #[test]
+    fn nests_dotted_keys() {
+        let input = "owner.name John\nowner.age 23\nowner.pet.name Waldo\nowner.name Jane\n";
+        let (json, errors) = parse(input);
+        assert!(errors.is_empty());
+        assert_eq!(
+            json!({ "owner": { "name": "Jane", "age": 23, "pet": { "name": "Waldo" } } }),
+            json
+        );
+    }
+
+    #[test]
+    fn reports_malformed_lines() {
+        let input = "\n# a comment\nalone\n=1\nok 1\nquote \"open\nquote 'a'b'\nok.nested 2\n\
+                     a..b 3\npet.name Waldo\npet 5\n";
+        let (json, errors) = parse(input);
+        let lines: Vec<_> = errors.iter().map(|error| error.line).collect();
+        assert_eq!(vec![3, 4, 6, 7, 8, 9, 11], lines);
+        assert_eq!(
+            "Line 3: Missing value for key 'alone'",
+            errors[0].to_string()
+        );
+        assert_eq!(
+            "Line 8: Cannot set 'ok.nested' because 'ok' is not an object",
+            errors[4].to_string()
+        );
+        assert_eq!(
+            "Line 11: Cannot set 'pet' because it already contains nested keys",
+            errors[6].to_string()
+        );
+        assert_eq!(json!({ "ok": 1, "pet": { "name": "Waldo" } }), json);
+    }
+}
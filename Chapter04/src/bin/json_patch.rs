@@ -0,0 +1,641 @@
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::BufReader;
+use std::{env, error, fmt, process};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => {
+            demo();
+            Ok(())
+        }
+        ["diff", from, to] => {
+            let patch = diff(&read_json(from), &read_json(to));
+            print_json(&patch);
+            Ok(())
+        }
+        ["apply", document, patch] => {
+            let mut document = read_json(document);
+            let patch: Vec<Operation> =
+                serde_json::from_value(read_json(patch)).expect("Failed to parse JSON Patch");
+            apply(&mut document, &patch).map(|_| print_json(&document))
+        }
+        ["merge", document, patch] => {
+            let mut document = read_json(document);
+            merge(&mut document, &read_json(patch));
+            print_json(&document);
+            Ok(())
+        }
+        _ => {
+            eprintln!("Usage: json_patch [diff <from> <to> | apply <document> <patch> | merge <document> <merge patch>]");
+            process::exit(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to apply patch: {}", e);
+        process::exit(1);
+    }
+}
+
+fn read_json(path: &str) -> Value {
+    let file = File::open(path).expect("Failed to open JSON file");
+    serde_json::from_reader(BufReader::new(file)).expect("Failed to parse JSON")
+}
+
+fn print_json<T: serde::Serialize>(value: &T) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("Failed to serialize JSON")
+    );
+}
+
+fn demo() {
+    let before = json!({
+        "name": "John",
+        "age": 23,
+        "pets": [
+            { "name": "Waldo", "species": "Dog", "age": 2 },
+            { "name": "Speedy", "species": "Turtle", "age": 47 }
+        ]
+    });
+    let after = json!({
+        "name": "John",
+        "age": 24,
+        "pets": [
+            { "name": "Waldo", "species": "Dog", "age": 3 },
+            { "name": "Speedy", "species": "Turtle", "age": 47 },
+            { "name": "Rex", "species": "Dog", "age": 1 }
+        ],
+        "city": "London"
+    });
+
+    let patch = diff(&before, &after);
+    println!("JSON Patch from before to after:");
+    print_json(&patch);
+    let mut document = before.clone();
+    apply(&mut document, &patch).expect("Failed to apply our own patch");
+    assert_eq!(after, document);
+
+    // A failing test operation leaves the document untouched
+    let guarded = vec![
+        Operation::Replace {
+            path: "/age".to_string(),
+            value: json!(30),
+        },
+        Operation::Test {
+            path: "/name".to_string(),
+            value: json!("Jane"),
+        },
+    ];
+    match apply(&mut document, &guarded) {
+        Ok(()) => println!("Applied the guarded patch"),
+        Err(e) => println!("Rejected the guarded patch: {}", e),
+    }
+    println!("Age is still {}", document["age"]);
+
+    // A merge patch is less precise, but much easier to write by hand
+    let merge_patch = merge_diff(&before, &after);
+    println!("Merge patch from before to after:");
+    print_json(&merge_patch);
+    let mut document = before;
+    merge(&mut document, &merge_patch);
+    assert_eq!(after, document);
+}
+
+// A single operation of an RFC 6902 JSON Patch.
+// Paths are JSON Pointers as described in RFC 6901
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Operation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    // Fails the whole patch if the value at path is different
+    Test { path: String, value: Value },
+}
+
+#[derive(Debug, PartialEq)]
+enum PatchError {
+    // Pointers have to be empty or start with a '/'
+    InvalidPointer(String),
+    NotFound(String),
+    InvalidIndex(String),
+    CannotRemoveRoot,
+    MoveIntoItself { from: String, path: String },
+    TestFailed { path: String, expected: Value },
+}
+
+// The error of a whole patch, remembering which operation failed
+#[derive(Debug, PartialEq)]
+struct ApplyError {
+    index: usize,
+    error: PatchError,
+}
+
+// Computes a patch that turns `from` into `to`. The operations are
+// always generated in the same order, with object keys sorted, so the
+// output can be stored and reviewed like any other diff
+fn diff(from: &Value, to: &Value) -> Vec<Operation> {
+    let mut patch = Vec::new();
+    diff_values(from, to, "", &mut patch);
+    patch
+}
+
+fn diff_values(from: &Value, to: &Value, path: &str, patch: &mut Vec<Operation>) {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => diff_objects(from, to, path, patch),
+        (Value::Array(from), Value::Array(to)) => diff_arrays(from, to, path, patch),
+        _ if from != to => patch.push(Operation::Replace {
+            path: path.to_string(),
+            value: to.clone(),
+        }),
+        _ => {}
+    }
+}
+
+fn diff_objects(
+    from: &Map<String, Value>,
+    to: &Map<String, Value>,
+    path: &str,
+    patch: &mut Vec<Operation>,
+) {
+    for (key, value) in from {
+        let child = child_pointer(path, key);
+        match to.get(key) {
+            Some(new_value) => diff_values(value, new_value, &child, patch),
+            None => patch.push(Operation::Remove { path: child }),
+        }
+    }
+    for (key, value) in to {
+        if !from.contains_key(key) {
+            patch.push(Operation::Add {
+                path: child_pointer(path, key),
+                value: value.clone(),
+            });
+        }
+    }
+}
+
+// Elements that are the same at the start and the end of both arrays
+// are skipped, so that inserting or removing a single element produces
+// a single operation instead of rewriting everything behind it
+fn diff_arrays(from: &[Value], to: &[Value], path: &str, patch: &mut Vec<Operation>) {
+    let prefix = from.iter().zip(to).take_while(|(a, b)| a == b).count();
+    let suffix = from[prefix..]
+        .iter()
+        .rev()
+        .zip(to[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let from = &from[prefix..from.len() - suffix];
+    let to = &to[prefix..to.len() - suffix];
+
+    for (offset, (old, new)) in from.iter().zip(to).enumerate() {
+        diff_values(
+            old,
+            new,
+            &child_pointer(path, &(prefix + offset).to_string()),
+            patch,
+        );
+    }
+    if to.len() > from.len() {
+        for (offset, value) in to.iter().enumerate().skip(from.len()) {
+            patch.push(Operation::Add {
+                path: child_pointer(path, &(prefix + offset).to_string()),
+                value: value.clone(),
+            });
+        }
+    } else {
+        // Every removal shifts the following elements down by one
+        let index = child_pointer(path, &(prefix + to.len()).to_string());
+        for _ in to.len()..from.len() {
+            patch.push(Operation::Remove {
+                path: index.clone(),
+            });
+        }
+    }
+}
+
+fn child_pointer(parent: &str, token: &str) -> String {
+    // '~' has to be escaped first, otherwise "~1" would turn into "~01"
+    format!("{}/{}", parent, token.replace('~', "~0").replace('/', "~1"))
+}
+
+fn parse_pointer(pointer: &str) -> Result<Vec<String>, PatchError> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !pointer.starts_with('/') {
+        return Err(PatchError::InvalidPointer(pointer.to_string()));
+    }
+    Ok(pointer[1..]
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+// Applies every operation or none of them. If an operation fails,
+// the document is left exactly as it was before
+fn apply(document: &mut Value, patch: &[Operation]) -> Result<(), ApplyError> {
+    let mut patched = document.clone();
+    for (index, operation) in patch.iter().enumerate() {
+        apply_operation(&mut patched, operation).map_err(|error| ApplyError { index, error })?;
+    }
+    *document = patched;
+    Ok(())
+}
+
+fn apply_operation(document: &mut Value, operation: &Operation) -> Result<(), PatchError> {
+    match *operation {
+        Operation::Add {
+            ref path,
+            ref value,
+        } => add(document, path, value.clone()),
+        Operation::Remove { ref path } => remove(document, path).map(|_| ()),
+        Operation::Replace {
+            ref path,
+            ref value,
+        } => {
+            parse_pointer(path)?;
+            let target = document.pointer_mut(path).ok_or_else(|| not_found(path))?;
+            *target = value.clone();
+            Ok(())
+        }
+        Operation::Move { ref from, ref path } => {
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(PatchError::MoveIntoItself {
+                    from: from.clone(),
+                    path: path.clone(),
+                });
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        Operation::Copy { ref from, ref path } => {
+            parse_pointer(from)?;
+            let value = document
+                .pointer(from)
+                .ok_or_else(|| not_found(from))?
+                .clone();
+            add(document, path, value)
+        }
+        Operation::Test {
+            ref path,
+            ref value,
+        } => {
+            parse_pointer(path)?;
+            match document.pointer(path) {
+                Some(actual) if actual == value => Ok(()),
+                _ => Err(PatchError::TestFailed {
+                    path: path.clone(),
+                    expected: value.clone(),
+                }),
+            }
+        }
+    }
+}
+
+fn not_found(path: &str) -> PatchError {
+    PatchError::NotFound(path.to_string())
+}
+
+// Returns the container that holds the value at path, and the last token
+fn parent_mut<'a>(
+    document: &'a mut Value,
+    path: &str,
+) -> Result<Option<(&'a mut Value, String)>, PatchError> {
+    let mut tokens = parse_pointer(path)?;
+    let last = match tokens.pop() {
+        Some(last) => last,
+        None => return Ok(None),
+    };
+    let mut parent = document;
+    for token in &tokens {
+        parent = match *parent {
+            Value::Object(ref mut map) => map.get_mut(token),
+            Value::Array(ref mut values) => {
+                let index = parse_index(token, values.len(), path)?;
+                values.get_mut(index)
+            }
+            _ => None,
+        }
+        .ok_or_else(|| not_found(path))?;
+    }
+    Ok(Some((parent, last)))
+}
+
+// Array indices are plain numbers without leading zeros
+fn parse_index(token: &str, len: usize, path: &str) -> Result<usize, PatchError> {
+    let valid =
+        token == "0" || (!token.starts_with('0') && token.bytes().all(|b| b.is_ascii_digit()));
+    match token.parse() {
+        Ok(index) if valid && index < len => Ok(index),
+        Ok(_) if valid => Err(not_found(path)),
+        _ => Err(PatchError::InvalidIndex(path.to_string())),
+    }
+}
+
+fn add(document: &mut Value, path: &str, value: Value) -> Result<(), PatchError> {
+    let (parent, last) = match parent_mut(document, path)? {
+        Some(parent) => parent,
+        None => {
+            *document = value;
+            return Ok(());
+        }
+    };
+    match *parent {
+        Value::Object(ref mut map) => {
+            map.insert(last, value);
+            Ok(())
+        }
+        Value::Array(ref mut values) => {
+            // "-" means the end of the array and adding
+            // right behind the last element is allowed
+            let index = if last == "-" {
+                values.len()
+            } else {
+                parse_index(&last, values.len() + 1, path)?
+            };
+            values.insert(index, value);
+            Ok(())
+        }
+        _ => Err(not_found(path)),
+    }
+}
+
+fn remove(document: &mut Value, path: &str) -> Result<Value, PatchError> {
+    let (parent, last) = parent_mut(document, path)?.ok_or(PatchError::CannotRemoveRoot)?;
+    match *parent {
+        Value::Object(ref mut map) => map.remove(&last).ok_or_else(|| not_found(path)),
+        Value::Array(ref mut values) => {
+            let index = parse_index(&last, values.len(), path)?;
+            Ok(values.remove(index))
+        }
+        _ => Err(not_found(path)),
+    }
+}
+
+// Applies an RFC 7396 JSON Merge Patch: objects are merged
+// recursively, null removes a key and anything else replaces
+fn merge(document: &mut Value, patch: &Value) {
+    let patch = match *patch {
+        Value::Object(ref patch) => patch,
+        ref patch => {
+            *document = patch.clone();
+            return;
+        }
+    };
+    if !document.is_object() {
+        *document = Value::Object(Map::new());
+    }
+    let map = document
+        .as_object_mut()
+        .expect("Document was just made an object");
+    for (key, value) in patch {
+        if value.is_null() {
+            map.remove(key);
+        } else {
+            merge(map.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+// The merge patch that turns `from` into `to`. Because null means
+// "remove", merge patches cannot set a value to null
+fn merge_diff(from: &Value, to: &Value) -> Value {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let mut patch = Map::new();
+            for key in from.keys() {
+                if !to.contains_key(key) {
+                    patch.insert(key.clone(), Value::Null);
+                }
+            }
+            for (key, value) in to {
+                match from.get(key) {
+                    Some(old) if old == value => {}
+                    Some(old) => {
+                        patch.insert(key.clone(), merge_diff(old, value));
+                    }
+                    None => {
+                        patch.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            Value::Object(patch)
+        }
+        _ => to.clone(),
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::InvalidPointer(ref pointer) => {
+                write!(f, "'{}' is not a valid JSON Pointer", pointer)
+            }
+            PatchError::NotFound(ref path) => write!(f, "Nothing found at '{}'", path),
+            PatchError::InvalidIndex(ref path) => {
+                write!(f, "'{}' does not contain a valid array index", path)
+            }
+            PatchError::CannotRemoveRoot => write!(f, "The whole document cannot be removed"),
+            PatchError::MoveIntoItself { ref from, ref path } => {
+                write!(f, "Cannot move '{}' into its own child '{}'", from, path)
+            }
+            PatchError::TestFailed {
+                ref path,
+                ref expected,
+            } => write!(f, "Expected '{}' to be {}", path, expected),
+        }
+    }
+}
+
+impl error::Error for PatchError {}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Operation {} failed: {}", self.index, self.error)
+    }
+}
+
+impl error::Error for ApplyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PET_OWNER: &str = include_str!("../../pet_owner.json");
+
+    fn patch(json: &str) -> Vec<Operation> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn assert_round_trip(from: Value, to: Value) {
+        let patch = diff(&from, &to);
+        let mut document = from.clone();
+        apply(&mut document, &patch).unwrap();
+        assert_eq!(to, document, "Patch was {:?}", patch);
+        assert!(diff(&to, &to).is_empty());
+    }
+
+    #[test]
+    fn diffs_and_applies_back() {
+        let owner: Value = serde_json::from_str(PET_OWNER).unwrap();
+        let mut changed = owner.clone();
+        changed["age"] = json!(24);
+        changed["pets"][1]["colour"] = json!("Blue");
+        changed["pets"].as_array_mut().unwrap().remove(0);
+        changed["address"] = json!({ "city": "London" });
+        assert_round_trip(owner.clone(), changed.clone());
+        assert_round_trip(changed, owner.clone());
+
+        assert_round_trip(json!([1, 2, 3]), json!([0, 1, 2, 3, 4]));
+        assert_round_trip(json!([1, 2, 3, 4, 5]), json!([1, 5]));
+        assert_round_trip(json!([1, 2, 3]), json!([3, 2, 1]));
+        assert_round_trip(json!({ "a/b": { "~c": 1 } }), json!({ "a/b": { "~c": 2 } }));
+        assert_round_trip(json!({ "a": 1 }), json!([1]));
+        assert_round_trip(owner, json!(null));
+    }
+
+    #[test]
+    fn produces_stable_minimal_diffs() {
+        let from = json!({ "b": [1, 2, 3], "a": 1, "c": { "d/e": true } });
+        let to = json!({ "b": [0, 1, 2, 3], "c": { "d/e": false }, "f": null });
+        let expected = patch(
+            r#"[
+                { "op": "remove", "path": "/a" },
+                { "op": "add", "path": "/b/0", "value": 0 },
+                { "op": "replace", "path": "/c/d~1e", "value": false },
+                { "op": "add", "path": "/f", "value": null }
+            ]"#,
+        );
+        assert_eq!(expected, diff(&from, &to));
+        let serialized = serde_json::to_string(&diff(&from, &to)[..1]).unwrap();
+        assert_eq!(r#"[{"op":"remove","path":"/a"}]"#, serialized);
+    }
+
+    #[test]
+    fn applies_every_operation() {
+        let mut document = json!({ "foo": ["bar", "baz"], "qux": { "id": 1 } });
+        let operations = patch(
+            r#"[
+                { "op": "test", "path": "/foo/1", "value": "baz" },
+                { "op": "add", "path": "/foo/1", "value": "new" },
+                { "op": "add", "path": "/foo/-", "value": "last" },
+                { "op": "remove", "path": "/foo/0" },
+                { "op": "replace", "path": "/qux/id", "value": 2 },
+                { "op": "copy", "from": "/qux", "path": "/copy" },
+                { "op": "move", "from": "/foo", "path": "/qux/foo" }
+            ]"#,
+        );
+        apply(&mut document, &operations).unwrap();
+        assert_eq!(
+            json!({
+                "qux": { "id": 2, "foo": ["new", "baz", "last"] },
+                "copy": { "id": 2 }
+            }),
+            document
+        );
+    }
+
+    #[test]
+    fn rolls_back_failed_patches() {
+        let original = json!({ "name": "John", "pets": ["Waldo"] });
+        let failures = vec![
+            (
+                r#"[{ "op": "test", "path": "/name", "value": "Jane" }]"#,
+                PatchError::TestFailed {
+                    path: "/name".to_string(),
+                    expected: json!("Jane"),
+                },
+            ),
+            (
+                r#"[{ "op": "remove", "path": "/age" }]"#,
+                PatchError::NotFound("/age".to_string()),
+            ),
+            (
+                r#"[{ "op": "add", "path": "/pets/3", "value": 1 }]"#,
+                PatchError::NotFound("/pets/3".to_string()),
+            ),
+            (
+                r#"[{ "op": "remove", "path": "/pets/01" }]"#,
+                PatchError::InvalidIndex("/pets/01".to_string()),
+            ),
+            (
+                r#"[{ "op": "replace", "path": "name", "value": 1 }]"#,
+                PatchError::InvalidPointer("name".to_string()),
+            ),
+            (
+                r#"[{ "op": "move", "from": "/pets", "path": "/pets/0" }]"#,
+                PatchError::MoveIntoItself {
+                    from: "/pets".to_string(),
+                    path: "/pets/0".to_string(),
+                },
+            ),
+            (
+                r#"[{ "op": "remove", "path": "" }]"#,
+                PatchError::CannotRemoveRoot,
+            ),
+        ];
+        for (operations, error) in failures {
+            // Every patch changes the document before failing
+            let mut operations = patch(operations);
+            operations.insert(
+                0,
+                Operation::Add {
+                    path: "/pets/-".to_string(),
+                    value: json!("Speedy"),
+                },
+            );
+            let mut document = original.clone();
+            assert_eq!(
+                Err(ApplyError { index: 1, error }),
+                apply(&mut document, &operations)
+            );
+            assert_eq!(original, document);
+        }
+    }
+
+    #[test]
+    fn merges_patches() {
+        // The example from RFC 7396
+        let mut document = json!({
+            "title": "Goodbye!",
+            "author": { "givenName": "John", "familyName": "Doe" },
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged"
+        });
+        let merge_patch = json!({
+            "title": "Hello!",
+            "phoneNumber": "+01-123-456-7890",
+            "author": { "familyName": null },
+            "tags": ["example"]
+        });
+        let expected = json!({
+            "title": "Hello!",
+            "author": { "givenName": "John" },
+            "tags": ["example"],
+            "content": "This will be unchanged",
+            "phoneNumber": "+01-123-456-7890"
+        });
+        let original = document.clone();
+        merge(&mut document, &merge_patch);
+        assert_eq!(expected, document);
+
+        let computed = merge_diff(&original, &expected);
+        assert_eq!(merge_patch, computed);
+
+        let mut document = json!([1, 2]);
+        merge(&mut document, &json!({ "a": { "b": null, "c": 1 } }));
+        assert_eq!(json!({ "a": { "c": 1 } }), document);
+    }
+}
@@ -0,0 +1,828 @@
+#[macro_use]
+extern crate serde_json;
+
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, Read};
+use std::str::FromStr;
+use std::{env, error, fmt, process};
+
+fn main() {
+    // Usage: json_query <query> [file]
+    // Without a file, the JSON is read from stdin
+    let args: Vec<_> = env::args().skip(1).collect();
+    if args.is_empty() {
+        demo();
+        return;
+    }
+    if args.len() > 2 {
+        eprintln!("Usage: json_query <query> [file]");
+        process::exit(2);
+    }
+
+    // Parsing the query first means that mistakes are
+    // reported before waiting for a potentially long input
+    let query: Query = match args[0].parse() {
+        Ok(query) => query,
+        Err(e) => {
+            eprintln!("{}", args[0]);
+            eprintln!("{}^", " ".repeat(e.position()));
+            eprintln!("Invalid query: {}", e);
+            process::exit(2);
+        }
+    };
+    let reader: Box<dyn Read> = match args.get(1) {
+        Some(path) => Box::new(File::open(path).expect("Failed to open JSON file")),
+        None => Box::new(io::stdin()),
+    };
+    let value: Value = serde_json::from_reader(reader).expect("Failed to parse JSON");
+    for result in query.select(&value) {
+        println!("{}", result);
+    }
+}
+
+fn demo() {
+    let pet_owner = json!({
+        "name": "John",
+        "age": 23,
+        "pets": [
+            { "name": "Waldo", "species": "Dog", "age": 2, "colour": null },
+            { "name": "Speedy", "species": "Turtle", "age": 47, "colour": "Green" },
+            { "name": "Meows", "species": "Cat", "age": null, "colour": "Orange" }
+        ]
+    });
+    let queries = [
+        "$.name",
+        "pets[0].species",
+        "pets[-1].name",
+        "pets[1:].name",
+        "pets[*].colour",
+        "$..age",
+        "pets[?(@.age > 2)].name",
+        "pets[?(@.colour == 'Orange' || @.species == 'Dog')].name",
+    ];
+    for query in &queries {
+        // A query can be compiled once and then used on as many values as needed
+        let compiled = Query::compile(query).expect("Failed to compile query");
+        let results: Vec<_> = compiled
+            .select(&pet_owner)
+            .into_iter()
+            .map(Value::to_string)
+            .collect();
+        println!("{} -> [{}]", query, results.join(", "));
+    }
+}
+
+// A compiled query that can be evaluated against any number of values
+#[derive(Debug, Clone)]
+struct Query {
+    segments: Vec<Segment>,
+}
+
+impl Query {
+    fn compile(query: &str) -> Result<Self, QueryError> {
+        let mut parser = Parser::new(query);
+        let segments = parser.parse_query()?;
+        Ok(Query { segments })
+    }
+
+    // Returns every match in document order. The results
+    // borrow from the value instead of copying it
+    fn select<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        select_segments(&self.segments, value, value)
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Query::compile(s)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    // .name, [0], [*] etc.
+    Child(Selector),
+    // ..name, ..[0], ..* etc.
+    Descendant(Selector),
+}
+
+#[derive(Debug, Clone)]
+enum Selector {
+    Name(String),
+    Wildcard,
+    // Negative indices count from the end
+    Index(i64),
+    Slice {
+        start: Option<i64>,
+        end: Option<i64>,
+        step: i64,
+    },
+    Filter(Expr),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    // A path on its own is true if it matches anything
+    Exists(Path),
+    Compare(Operand, Comparison, Operand),
+}
+
+#[derive(Debug, Clone)]
+struct Path {
+    // Paths start either at the root ($) or at the current node (@)
+    from_root: bool,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Path(Path),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, PartialEq)]
+struct QueryError {
+    // Counted in characters, starting at 0
+    position: usize,
+    message: String,
+}
+
+impl QueryError {
+    fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl error::Error for QueryError {}
+
+fn select_segments<'a>(segments: &[Segment], start: &'a Value, root: &'a Value) -> Vec<&'a Value> {
+    let mut nodes = vec![start];
+    for segment in segments {
+        let mut next = Vec::new();
+        for node in nodes {
+            match *segment {
+                Segment::Child(ref selector) => selector.select(node, root, &mut next),
+                Segment::Descendant(ref selector) => {
+                    let mut descendants = Vec::new();
+                    collect_descendants(node, &mut descendants);
+                    for descendant in descendants {
+                        selector.select(descendant, root, &mut next);
+                    }
+                }
+            }
+        }
+        nodes = next;
+    }
+    nodes
+}
+
+// The node itself and everything below it, parents before children
+fn collect_descendants<'a>(node: &'a Value, descendants: &mut Vec<&'a Value>) {
+    descendants.push(node);
+    for child in children(node) {
+        collect_descendants(child, descendants);
+    }
+}
+
+fn children(node: &Value) -> Vec<&Value> {
+    match *node {
+        Value::Array(ref values) => values.iter().collect(),
+        Value::Object(ref map) => map.values().collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl Selector {
+    fn select<'a>(&self, node: &'a Value, root: &'a Value, results: &mut Vec<&'a Value>) {
+        match *self {
+            Selector::Name(ref name) => {
+                results.extend(node.as_object().and_then(|map| map.get(name)))
+            }
+            Selector::Wildcard => results.extend(children(node)),
+            Selector::Index(index) => {
+                if let Some(values) = node.as_array() {
+                    let index = if index < 0 {
+                        index + values.len() as i64
+                    } else {
+                        index
+                    };
+                    if index >= 0 {
+                        results.extend(values.get(index as usize));
+                    }
+                }
+            }
+            Selector::Slice { start, end, step } => {
+                if let Some(values) = node.as_array() {
+                    let indices = slice_indices(values.len(), start, end, step);
+                    results.extend(indices.into_iter().map(|index| &values[index]));
+                }
+            }
+            Selector::Filter(ref expr) => results.extend(
+                children(node)
+                    .into_iter()
+                    .filter(|child| expr.matches(child, root)),
+            ),
+        }
+    }
+}
+
+// Works like slicing in Python: missing bounds mean the whole
+// array, negative bounds count from the end and out of range
+// bounds are clamped. Huge steps end the slice instead of overflowing
+fn slice_indices(len: usize, start: Option<i64>, end: Option<i64>, step: i64) -> Vec<usize> {
+    let len = len as i64;
+    let normalize = |index: i64| if index < 0 { index + len } else { index };
+    let mut indices = Vec::new();
+    if step > 0 {
+        let start = start.map_or(0, normalize).max(0).min(len);
+        let end = end.map_or(len, normalize).max(0).min(len);
+        let mut index = Some(start);
+        while let Some(current) = index.filter(|&index| index < end) {
+            indices.push(current as usize);
+            index = current.checked_add(step);
+        }
+    } else {
+        let start = start.map_or(len - 1, normalize).max(-1).min(len - 1);
+        let end = end.map_or(-1, normalize).max(-1).min(len - 1);
+        let mut index = Some(start);
+        while let Some(current) = index.filter(|&index| index > end) {
+            indices.push(current as usize);
+            index = current.checked_add(step);
+        }
+    }
+    indices
+}
+
+impl Expr {
+    fn matches(&self, node: &Value, root: &Value) -> bool {
+        match *self {
+            Expr::Or(ref left, ref right) => left.matches(node, root) || right.matches(node, root),
+            Expr::And(ref left, ref right) => left.matches(node, root) && right.matches(node, root),
+            Expr::Not(ref expr) => !expr.matches(node, root),
+            Expr::Exists(ref path) => !path.select(node, root).is_empty(),
+            Expr::Compare(ref left, comparison, ref right) => compare(
+                left.evaluate(node, root),
+                comparison,
+                right.evaluate(node, root),
+            ),
+        }
+    }
+}
+
+impl Path {
+    fn select<'a>(&self, node: &'a Value, root: &'a Value) -> Vec<&'a Value> {
+        let start = if self.from_root { root } else { node };
+        select_segments(&self.segments, start, root)
+    }
+}
+
+impl Operand {
+    // Only paths that match exactly one value can be compared
+    fn evaluate<'a>(&'a self, node: &'a Value, root: &'a Value) -> Option<&'a Value> {
+        match *self {
+            Operand::Literal(ref value) => Some(value),
+            Operand::Path(ref path) => {
+                let results = path.select(node, root);
+                if results.len() == 1 {
+                    Some(results[0])
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+fn compare(left: Option<&Value>, comparison: Comparison, right: Option<&Value>) -> bool {
+    let ordering = match (left, right) {
+        (Some(left), Some(right)) => {
+            if comparison == Comparison::Equal {
+                return values_equal(left, right);
+            } else if comparison == Comparison::NotEqual {
+                return !values_equal(left, right);
+            }
+            order(left, right)
+        }
+        // Two missing values are considered equal
+        (None, None) => Some(Ordering::Equal),
+        _ => None,
+    };
+    match (comparison, ordering) {
+        (Comparison::Equal, Some(Ordering::Equal)) => true,
+        (Comparison::NotEqual, ordering) => ordering != Some(Ordering::Equal),
+        (Comparison::Less, Some(Ordering::Less)) => true,
+        (Comparison::LessOrEqual, Some(ordering)) => ordering != Ordering::Greater,
+        (Comparison::Greater, Some(Ordering::Greater)) => true,
+        (Comparison::GreaterOrEqual, Some(ordering)) => ordering != Ordering::Less,
+        _ => false,
+    }
+}
+
+// 2 and 2.0 are the same number, even though serde_json stores them differently
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left.as_f64(), right.as_f64()) {
+        (Some(left), Some(right)) => left == right,
+        _ => left == right,
+    }
+}
+
+// Only numbers and strings have an order, everything else fails to compare
+fn order(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(_), Value::Number(_)) => left.as_f64()?.partial_cmp(&right.as_f64()?),
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        _ => None,
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    // Byte offset into the input
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Parser { input, pos: 0 }
+    }
+
+    fn parse_query(&mut self) -> Result<Vec<Segment>, QueryError> {
+        self.skip_whitespace();
+        let mut segments = Vec::new();
+        // "pets[0]" is accepted as a shorthand for "$.pets[0]"
+        if !self.eat("$") && self.peek().is_some_and(is_name_char) {
+            segments.push(Segment::Child(Selector::Name(self.parse_name())));
+        }
+        segments.extend(self.parse_segments()?);
+        self.skip_whitespace();
+        if self.peek().is_some() {
+            return self.error("Expected '.', '..' or '['");
+        }
+        Ok(segments)
+    }
+
+    fn parse_segments(&mut self) -> Result<Vec<Segment>, QueryError> {
+        let mut segments = Vec::new();
+        loop {
+            if self.eat("..") {
+                let selector = if self.peek() == Some('[') {
+                    self.parse_bracket()?
+                } else {
+                    self.parse_dot_selector()?
+                };
+                segments.push(Segment::Descendant(selector));
+            } else if self.eat(".") {
+                segments.push(Segment::Child(self.parse_dot_selector()?));
+            } else if self.peek() == Some('[') {
+                segments.push(Segment::Child(self.parse_bracket()?));
+            } else {
+                return Ok(segments);
+            }
+        }
+    }
+
+    fn parse_dot_selector(&mut self) -> Result<Selector, QueryError> {
+        if self.eat("*") {
+            Ok(Selector::Wildcard)
+        } else if self.peek().is_some_and(is_name_char) {
+            Ok(Selector::Name(self.parse_name()))
+        } else {
+            self.error("Expected a name or '*'")
+        }
+    }
+
+    fn parse_bracket(&mut self) -> Result<Selector, QueryError> {
+        self.expect("[")?;
+        self.skip_whitespace();
+        let selector = match self.peek() {
+            Some('*') => {
+                self.bump();
+                Selector::Wildcard
+            }
+            Some('?') => {
+                self.bump();
+                Selector::Filter(self.parse_or()?)
+            }
+            Some(quote) if quote == '\'' || quote == '"' => Selector::Name(self.parse_string()?),
+            Some(c) if c == '-' || c == ':' || c.is_ascii_digit() => self.parse_index_or_slice()?,
+            _ => return self.error("Expected an index, a slice, a name, '*' or '?'"),
+        };
+        self.skip_whitespace();
+        self.expect("]")?;
+        Ok(selector)
+    }
+
+    fn parse_index_or_slice(&mut self) -> Result<Selector, QueryError> {
+        let start = self.parse_optional_integer()?;
+        self.skip_whitespace();
+        if !self.eat(":") {
+            return match start {
+                Some(index) => Ok(Selector::Index(index)),
+                None => self.error("Expected an index"),
+            };
+        }
+        self.skip_whitespace();
+        let end = self.parse_optional_integer()?;
+        self.skip_whitespace();
+        let mut step = 1;
+        if self.eat(":") {
+            self.skip_whitespace();
+            let position = self.pos;
+            if let Some(value) = self.parse_optional_integer()? {
+                if value == 0 {
+                    self.pos = position;
+                    return self.error("Slice step cannot be zero");
+                }
+                step = value;
+            }
+        }
+        Ok(Selector::Slice { start, end, step })
+    }
+
+    fn parse_optional_integer(&mut self) -> Result<Option<i64>, QueryError> {
+        let start = self.pos;
+        self.eat("-");
+        self.skip_while(|c| c.is_ascii_digit());
+        let text = &self.input[start..self.pos];
+        if text.is_empty() {
+            return Ok(None);
+        }
+        text.parse().map(Some).or_else(|_| {
+            self.pos = start;
+            self.error("Invalid integer")
+        })
+    }
+
+    fn parse_name(&mut self) -> String {
+        let start = self.pos;
+        self.skip_while(is_name_char);
+        self.input[start..self.pos].to_string()
+    }
+
+    fn parse_string(&mut self) -> Result<String, QueryError> {
+        let start = self.pos;
+        let quote = self.bump().expect("Caller checked for a quote");
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => return Ok(string),
+                Some('\\') => match self.bump() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some(c) if c == '\\' || c == '\'' || c == '"' || c == '/' => string.push(c),
+                    _ => {
+                        self.pos = start;
+                        return self.error("Invalid escape sequence in string");
+                    }
+                },
+                Some(c) => string.push(c),
+                None => {
+                    self.pos = start;
+                    return self.error("Unterminated string");
+                }
+            }
+        }
+    }
+
+    // Filters follow the usual precedence: ! binds
+    // tighter than &&, which binds tighter than ||
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_and()?;
+        while self.eat_token("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_unary()?;
+        while self.eat_token("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        self.skip_whitespace();
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.parse_or()?;
+            self.skip_whitespace();
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, QueryError> {
+        let start = self.pos;
+        let left = self.parse_operand()?;
+        self.skip_whitespace();
+        let comparison = match self.parse_comparison_operator() {
+            Some(comparison) => comparison,
+            None => {
+                return match left {
+                    Operand::Path(path) => Ok(Expr::Exists(path)),
+                    Operand::Literal(_) => {
+                        self.pos = start;
+                        self.error("Expected a path before a literal without a comparison")
+                    }
+                }
+            }
+        };
+        let right = self.parse_operand()?;
+        Ok(Expr::Compare(left, comparison, right))
+    }
+
+    fn parse_comparison_operator(&mut self) -> Option<Comparison> {
+        // Longer operators have to be tried first
+        let operators = [
+            ("==", Comparison::Equal),
+            ("!=", Comparison::NotEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ];
+        operators
+            .iter()
+            .find(|&&(operator, _)| self.eat(operator))
+            .map(|&(_, comparison)| comparison)
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, QueryError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == '@' || c == '$' => {
+                self.bump();
+                let segments = self.parse_segments()?;
+                Ok(Operand::Path(Path {
+                    from_root: c == '$',
+                    segments,
+                }))
+            }
+            Some(quote) if quote == '\'' || quote == '"' => {
+                Ok(Operand::Literal(Value::String(self.parse_string()?)))
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                self.skip_while(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+                match serde_json::from_str(&self.input[start..self.pos]) {
+                    Ok(number @ Value::Number(_)) => Ok(Operand::Literal(number)),
+                    _ => {
+                        self.pos = start;
+                        self.error("Invalid number")
+                    }
+                }
+            }
+            Some(c) if is_name_char(c) => {
+                let start = self.pos;
+                match self.parse_name().as_str() {
+                    "true" => Ok(Operand::Literal(Value::Bool(true))),
+                    "false" => Ok(Operand::Literal(Value::Bool(false))),
+                    "null" => Ok(Operand::Literal(Value::Null)),
+                    _ => {
+                        self.pos = start;
+                        self.error("Expected '@', '$' or a literal")
+                    }
+                }
+            }
+            _ => self.error("Expected '@', '$' or a literal"),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.input[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_token(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        self.eat(token)
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), QueryError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            self.error(&format!("Expected '{}'", token))
+        }
+    }
+
+    fn skip_while<F: Fn(char) -> bool>(&mut self, predicate: F) {
+        while self.peek().is_some_and(&predicate) {
+            self.bump();
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.skip_while(char::is_whitespace);
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, QueryError> {
+        let found = match self.peek() {
+            Some(c) => format!("found '{}'", c),
+            None => "reached the end of the query".to_string(),
+        };
+        Err(QueryError {
+            position: self.input[..self.pos].chars().count(),
+            message: format!("{}, but {}", message, found),
+        })
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    const PET_OWNER: &str = include_str!("../../pet_owner.json");
+
+    fn pet_owner() -> Value {
+        serde_json::from_str(PET_OWNER).unwrap()
+    }
+
+    fn query(query: &str) -> Vec<Value> {
+        let value = pet_owner();
+        let compiled = Query::compile(query).unwrap();
+        compiled.select(&value).into_iter().cloned().collect()
+    }
+
+    fn error_position(query: &str) -> usize {
+        Query::compile(query).unwrap_err().position()
+    }
+
+    #[test]
+    fn selects_names_and_indices() {
+        assert_eq!(vec![json!("John")], query("$.name"));
+        assert_eq!(vec![json!("John")], query("name"));
+        assert_eq!(vec![json!("Waldo")], query("$.pets[0].name"));
+        assert_eq!(vec![json!("Turtle")], query("$['pets'][1][\"species\"]"));
+        assert_eq!(vec![json!("Cat")], query("pets[-1].species"));
+        assert!(query("pets[3]").is_empty());
+        assert!(query("pets[-4]").is_empty());
+        assert!(query("name.first").is_empty());
+        assert_eq!(vec![pet_owner()], query("$"));
+    }
+
+    #[test]
+    fn selects_slices() {
+        assert_eq!(
+            vec![json!("Waldo"), json!("Speedy")],
+            query("pets[0:2].name")
+        );
+        assert_eq!(
+            vec![json!("Speedy"), json!("Meows")],
+            query("pets[1:].name")
+        );
+        assert_eq!(vec![json!("Meows")], query("pets[-1:].name"));
+        assert_eq!(
+            vec![json!("Waldo"), json!("Meows")],
+            query("pets[::2].name")
+        );
+        assert_eq!(
+            vec![json!("Meows"), json!("Speedy"), json!("Waldo")],
+            query("pets[::-1].name")
+        );
+        assert!(query("pets[5:10]").is_empty());
+        assert_eq!(
+            vec![json!("Speedy")],
+            query("pets[1::9223372036854775807].name")
+        );
+        assert_eq!(
+            vec![json!("Meows")],
+            query("pets[::-9223372036854775808].name")
+        );
+    }
+
+    #[test]
+    fn selects_wildcards_and_descendants() {
+        assert_eq!(
+            vec![json!(null), json!("Green"), json!("Orange")],
+            query("pets[*].colour")
+        );
+        assert_eq!(
+            vec![json!(null), json!("Green"), json!("Orange")],
+            query("pets.*.colour")
+        );
+        // Objects are visited in key order
+        assert_eq!(vec![json!(23), json!("John")], query("$.*")[..2].to_vec());
+        assert_eq!(
+            vec![
+                json!("John"),
+                json!("Waldo"),
+                json!("Speedy"),
+                json!("Meows")
+            ],
+            query("$..name")
+        );
+        assert_eq!(
+            vec![json!(23), json!(2), json!(47), json!(null)],
+            query("..age")
+        );
+        assert_eq!(vec![json!("Speedy")], query("$..[1].name"));
+    }
+
+    #[test]
+    fn filters_with_predicates() {
+        assert_eq!(vec![json!("Speedy")], query("pets[?(@.age > 2)].name"));
+        assert_eq!(vec![json!("Speedy")], query("pets[?@.age >= $.age].name"));
+        assert_eq!(
+            vec![json!("Waldo")],
+            query("pets[?(@.colour == null)].name")
+        );
+        assert_eq!(
+            vec![json!("Waldo"), json!("Meows")],
+            query("pets[?(@.species == 'Cat' || @.age < 10)].name")
+        );
+        assert_eq!(
+            vec![json!("Meows")],
+            query("pets[?(@.colour && !(@.age <= 47))].name")
+        );
+        assert_eq!(
+            vec![json!("Speedy"), json!("Meows")],
+            query("pets[?(@.colour != null)].name")
+        );
+        assert_eq!(vec![json!("Waldo")], query("pets[?(@.age == 2.0)].name"));
+        // Every pet has an age, even if it is null
+        assert_eq!(3, query("pets[?(@.age)]").len());
+        assert!(query("pets[?(@.weight)]").is_empty());
+        // Comparing different types is never true
+        assert!(query("pets[?(@.name < 3)]").is_empty());
+    }
+
+    #[test]
+    fn returns_references_into_the_value() {
+        let value = pet_owner();
+        let compiled: Query = "pets[?(@.species == 'Dog')]".parse().unwrap();
+        let results = compiled.select(&value);
+        assert_eq!(1, results.len());
+        assert!(ptr::eq(&value["pets"][0], results[0]));
+
+        // The same compiled query works on other values
+        let other = json!({ "pets": [{ "species": "Cat" }, { "species": "Dog", "name": "Rex" }] });
+        assert_eq!(
+            vec![&json!({ "species": "Dog", "name": "Rex" })],
+            compiled.select(&other)
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_positions() {
+        assert_eq!(5, error_position("pets["));
+        assert_eq!(15, error_position("pets[?(@.age > )]"));
+        assert_eq!(9, error_position("pets[0:1:0]"));
+        assert_eq!(6, error_position("pets.."));
+        assert_eq!(5, error_position("pets[abc]"));
+        assert_eq!(7, error_position("pets[?(2)]"));
+        assert_eq!(5, error_position("pets['Waldo]"));
+        assert_eq!(5, error_position("pets name"));
+        let error = Query::compile("$.pets[0").unwrap_err();
+        assert_eq!(
+            "Expected ']', but reached the end of the query at position 8",
+            error.to_string()
+        );
+    }
+}
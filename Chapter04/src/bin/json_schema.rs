@@ -0,0 +1,846 @@
+#[macro_use]
+extern crate chapter_five_derive;
+extern crate regex;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::{env, error, fmt, process};
+
+// trait definitions have to be in "consumer" crate
+trait JsonSchema {
+    // The schema that every serialized value of this type matches
+    fn json_schema() -> Value;
+    // Whether a struct field of this type has to be present
+    fn is_required() -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct PetOwner {
+    #[schema(min_length = 1)]
+    name: String,
+    #[schema(maximum = 150)]
+    age: u8,
+    pets: Vec<Pet>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct Pet {
+    #[schema(min_length = 1, pattern = "^[A-Z]")]
+    name: String,
+    species: AllowedSpecies,
+    age: Option<u8>,
+    colour: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+enum AllowedSpecies {
+    Dog,
+    Turtle,
+    Cat,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (schema, documents) = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => (PetOwner::json_schema(), vec!["pet_owner.json".to_string()]),
+        ["--print-schema"] => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&PetOwner::json_schema())
+                    .expect("Failed to serialize schema")
+            );
+            return;
+        }
+        [schema, ref documents @ ..] if !documents.is_empty() => (
+            read_json(schema),
+            documents.iter().map(|path| path.to_string()).collect(),
+        ),
+        _ => {
+            eprintln!("Usage: json_schema [--print-schema | <schema> <document>...]");
+            process::exit(2);
+        }
+    };
+
+    let validator = Validator::new(schema).unwrap_or_else(|e| {
+        eprintln!("Invalid schema: {}", e);
+        process::exit(2);
+    });
+    let mut all_valid = true;
+    for path in &documents {
+        match validator.validate(&read_json(path)) {
+            Ok(()) => println!("{} is valid", path),
+            Err(violations) => {
+                all_valid = false;
+                println!("{} has {} problem(s):", path, violations.len());
+                for violation in violations {
+                    println!("    {}", violation);
+                }
+            }
+        }
+    }
+
+    // Only documents that passed validation are handed to serde
+    if args.is_empty() && all_valid {
+        let owner: PetOwner = serde_json::from_value(read_json(&documents[0]))
+            .expect("Failed to deserialize the validated document");
+        println!("{:?}", owner);
+    }
+    if !all_valid {
+        process::exit(1);
+    }
+}
+
+fn read_json(path: &str) -> Value {
+    let file = File::open(path).expect("Failed to open JSON file");
+    serde_json::from_reader(BufReader::new(file)).expect("Failed to parse JSON")
+}
+
+impl JsonSchema for String {
+    fn json_schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl JsonSchema for bool {
+    fn json_schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+macro_rules! impl_integer_schema {
+    ($($ty:ty),*) => {
+        $(
+            impl JsonSchema for $ty {
+                fn json_schema() -> Value {
+                    json!({ "type": "integer", "minimum": <$ty>::MIN, "maximum": <$ty>::MAX })
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_schema!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl JsonSchema for f32 {
+    fn json_schema() -> Value {
+        json!({ "type": "number" })
+    }
+}
+
+impl JsonSchema for f64 {
+    fn json_schema() -> Value {
+        json!({ "type": "number" })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+// serde writes None as null and accepts a missing field as None
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn json_schema() -> Value {
+        let mut schema = T::json_schema();
+        if let Some(schema) = schema.as_object_mut() {
+            if let Some(ty) = schema.get_mut("type") {
+                *ty = json!([ty.clone(), "null"]);
+            }
+            if let Some(values) = schema.get_mut("enum").and_then(Value::as_array_mut) {
+                values.push(Value::Null);
+            }
+        }
+        schema
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+}
+
+// A schema that was checked once and can then validate any number of documents.
+// Supports a subset of draft 7: type, enum, const, minimum, maximum,
+// exclusiveMinimum, exclusiveMaximum, minLength, maxLength, pattern, items,
+// minItems, maxItems, required, properties, additionalProperties and $ref
+// pointing into the same document. Other keywords are ignored
+struct Validator {
+    schema: Value,
+    patterns: HashMap<String, Regex>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Violation {
+    // A JSON Pointer to the offending value in the document
+    pointer: String,
+    message: String,
+}
+
+#[derive(Debug, PartialEq)]
+struct SchemaError {
+    // A JSON Pointer into the schema
+    pointer: String,
+    message: String,
+}
+
+impl Validator {
+    fn new(schema: Value) -> Result<Self, SchemaError> {
+        let mut checked = Checked::default();
+        checked.references.insert(String::new());
+        check_schema(&schema, &schema, "", &mut checked)?;
+        Ok(Validator {
+            schema,
+            patterns: checked.patterns,
+        })
+    }
+
+    // Collects every violation instead of stopping at the first one
+    fn validate(&self, document: &Value) -> Result<(), Vec<Violation>> {
+        let mut violations = Vec::new();
+        self.check(&self.schema, document, "", &mut violations);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    fn check(&self, schema: &Value, value: &Value, pointer: &str, violations: &mut Vec<Violation>) {
+        let mut violation = |message: String| {
+            violations.push(Violation {
+                pointer: pointer.to_string(),
+                message,
+            })
+        };
+        let schema = match *schema {
+            Value::Object(ref schema) => schema,
+            Value::Bool(false) => return violation("No value is allowed here".to_string()),
+            _ => return,
+        };
+        // In draft 7, $ref replaces every other keyword next to it
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            if let Some(target) = resolve(&self.schema, reference) {
+                self.check(target, value, pointer, violations);
+            }
+            return;
+        }
+
+        if let Some(types) = schema.get("type") {
+            let types: Vec<_> = match *types {
+                Value::Array(ref types) => types.iter().filter_map(Value::as_str).collect(),
+                ref ty => ty.as_str().into_iter().collect(),
+            };
+            if !types.iter().any(|ty| has_type(value, ty)) {
+                // Checking anything else would only produce follow-up errors
+                return violation(format!(
+                    "Expected {} but found {}",
+                    types.join(" or "),
+                    type_name(value)
+                ));
+            }
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            if !values.iter().any(|allowed| json_equal(allowed, value)) {
+                let values: Vec<_> = values.iter().map(Value::to_string).collect();
+                violation(format!(
+                    "Expected one of {} but found {}",
+                    values.join(", "),
+                    value
+                ));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if !json_equal(expected, value) {
+                violation(format!("Expected {} but found {}", expected, value));
+            }
+        }
+
+        match *value {
+            Value::Number(_) => check_number(schema, value, violation),
+            Value::String(ref string) => self.check_string(schema, string, violation),
+            Value::Array(ref values) => self.check_array(schema, values, pointer, violations),
+            Value::Object(ref map) => self.check_object(schema, map, pointer, violations),
+            _ => {}
+        }
+    }
+
+    fn check_string<F: FnMut(String)>(
+        &self,
+        schema: &Map<String, Value>,
+        string: &str,
+        mut violation: F,
+    ) {
+        // Lengths are counted in characters, not bytes
+        let len = string.chars().count() as u64;
+        if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+            if len < min {
+                violation(format!(
+                    "Expected at least {} character(s) but found {}",
+                    min, len
+                ));
+            }
+        }
+        if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+            if len > max {
+                violation(format!(
+                    "Expected at most {} character(s) but found {}",
+                    max, len
+                ));
+            }
+        }
+        if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+            if self
+                .patterns
+                .get(pattern)
+                .is_some_and(|regex| !regex.is_match(string))
+            {
+                violation(format!(
+                    "\"{}\" does not match the pattern \"{}\"",
+                    string, pattern
+                ));
+            }
+        }
+    }
+
+    fn check_array(
+        &self,
+        schema: &Map<String, Value>,
+        values: &[Value],
+        pointer: &str,
+        violations: &mut Vec<Violation>,
+    ) {
+        let len = values.len() as u64;
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+            if len < min {
+                violations.push(Violation::new(
+                    pointer,
+                    format!("Expected at least {} item(s) but found {}", min, len),
+                ));
+            }
+        }
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+            if len > max {
+                violations.push(Violation::new(
+                    pointer,
+                    format!("Expected at most {} item(s) but found {}", max, len),
+                ));
+            }
+        }
+        match schema.get("items") {
+            // An array of schemas checks the items by position
+            Some(Value::Array(schemas)) => {
+                for (index, (schema, value)) in schemas.iter().zip(values).enumerate() {
+                    self.check(
+                        schema,
+                        value,
+                        &child_pointer(pointer, &index.to_string()),
+                        violations,
+                    );
+                }
+            }
+            Some(schema) => {
+                for (index, value) in values.iter().enumerate() {
+                    self.check(
+                        schema,
+                        value,
+                        &child_pointer(pointer, &index.to_string()),
+                        violations,
+                    );
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn check_object(
+        &self,
+        schema: &Map<String, Value>,
+        map: &Map<String, Value>,
+        pointer: &str,
+        violations: &mut Vec<Violation>,
+    ) {
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !map.contains_key(key) {
+                    violations.push(Violation::new(
+                        pointer,
+                        format!("Missing required property \"{}\"", key),
+                    ));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, value) in map {
+            let child = child_pointer(pointer, key);
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(schema) => self.check(schema, value, &child, violations),
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => {
+                        violations.push(Violation::new(&child, "Unexpected property".to_string()))
+                    }
+                    Some(schema) => self.check(schema, value, &child, violations),
+                    None => {}
+                },
+            }
+        }
+    }
+}
+
+fn check_number<F: FnMut(String)>(schema: &Map<String, Value>, value: &Value, mut violation: F) {
+    let number = value.as_f64().expect("Value is a number");
+    let bound = |keyword| schema.get(keyword).and_then(Value::as_f64);
+    if let Some(min) = bound("minimum") {
+        if number < min {
+            violation(format!("{} is less than the minimum of {}", value, min));
+        }
+    }
+    if let Some(max) = bound("maximum") {
+        if number > max {
+            violation(format!("{} is greater than the maximum of {}", value, max));
+        }
+    }
+    if let Some(min) = bound("exclusiveMinimum") {
+        if number <= min {
+            violation(format!("{} is not greater than {}", value, min));
+        }
+    }
+    if let Some(max) = bound("exclusiveMaximum") {
+        if number >= max {
+            violation(format!("{} is not less than {}", value, max));
+        }
+    }
+}
+
+// What check_schema has found out so far
+#[derive(Default)]
+struct Checked {
+    patterns: HashMap<String, Regex>,
+    // Pointers of the $ref targets that were already checked
+    references: HashSet<String>,
+}
+
+// Walks through every subschema once, so that validating
+// can't fail because of a mistake in the schema itself
+fn check_schema(
+    root: &Value,
+    schema: &Value,
+    pointer: &str,
+    checked: &mut Checked,
+) -> Result<(), SchemaError> {
+    let error = |pointer: &str, message: String| {
+        Err(SchemaError {
+            pointer: pointer.to_string(),
+            message,
+        })
+    };
+    let map = match *schema {
+        Value::Object(ref map) => map,
+        Value::Bool(_) => return Ok(()),
+        _ => {
+            return error(
+                pointer,
+                "A schema has to be an object or a boolean".to_string(),
+            )
+        }
+    };
+
+    if let Some(reference) = map.get("$ref") {
+        let reference = match reference.as_str() {
+            Some(reference) => reference,
+            None => return error(pointer, "$ref has to be a string".to_string()),
+        };
+        // Follow chains of references to make sure they end somewhere
+        let mut seen = vec![reference];
+        let mut target = resolve(root, reference);
+        while let Some(next) = target
+            .and_then(|target| target.get("$ref"))
+            .and_then(Value::as_str)
+        {
+            if seen.contains(&next) {
+                return error(pointer, format!("$ref \"{}\" refers to itself", reference));
+            }
+            seen.push(next);
+            target = resolve(root, next);
+        }
+        if target.is_none() {
+            let last = seen.last().expect("seen is never empty");
+            return error(
+                pointer,
+                format!("$ref \"{}\" does not exist in this document", last),
+            );
+        }
+        // A reference can point anywhere, not only into definitions,
+        // so check its target like any other subschema
+        for reference in seen {
+            let target_pointer = &reference[1..];
+            if checked.references.insert(target_pointer.to_string()) {
+                let target = resolve(root, reference).expect("References were resolved above");
+                check_schema(root, target, target_pointer, checked)?;
+            }
+        }
+    }
+    if let Some(pattern) = map.get("pattern") {
+        let pattern = match pattern.as_str() {
+            Some(pattern) => pattern,
+            None => return error(pointer, "pattern has to be a string".to_string()),
+        };
+        match Regex::new(pattern) {
+            Ok(regex) => {
+                checked.patterns.insert(pattern.to_string(), regex);
+            }
+            Err(e) => return error(pointer, format!("Invalid pattern: {}", e)),
+        }
+    }
+    for keyword in &["properties", "definitions"] {
+        if let Some(schemas) = map.get(*keyword).and_then(Value::as_object) {
+            let keyword_pointer = child_pointer(pointer, keyword);
+            for (key, schema) in schemas {
+                check_schema(root, schema, &child_pointer(&keyword_pointer, key), checked)?;
+            }
+        }
+    }
+    if let Some(schema) = map.get("additionalProperties") {
+        check_schema(
+            root,
+            schema,
+            &child_pointer(pointer, "additionalProperties"),
+            checked,
+        )?;
+    }
+    match map.get("items") {
+        Some(Value::Array(schemas)) => {
+            for (index, schema) in schemas.iter().enumerate() {
+                let items_pointer = child_pointer(pointer, "items");
+                check_schema(
+                    root,
+                    schema,
+                    &child_pointer(&items_pointer, &index.to_string()),
+                    checked,
+                )?;
+            }
+        }
+        Some(schema) => check_schema(root, schema, &child_pointer(pointer, "items"), checked)?,
+        None => {}
+    }
+    Ok(())
+}
+
+// Only references into the same document, like "#/definitions/Pet", are supported
+fn resolve<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    reference
+        .strip_prefix('#')
+        .and_then(|pointer| root.pointer(pointer))
+}
+
+fn has_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        // 1.0 is an integer as far as JSON Schema is concerned
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|f| f.fract() == 0.0)
+        }
+        ty => type_name(value) == ty || (ty == "number" && value.is_number()),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(ref number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// Like ==, but 1 and 1.0 are equal
+fn json_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(_), Value::Number(_)) => left.as_f64() == right.as_f64(),
+        (Value::Array(left), Value::Array(right)) => {
+            left.len() == right.len() && left.iter().zip(right).all(|(l, r)| json_equal(l, r))
+        }
+        (Value::Object(left), Value::Object(right)) => {
+            left.len() == right.len()
+                && left
+                    .iter()
+                    .all(|(key, l)| right.get(key).is_some_and(|r| json_equal(l, r)))
+        }
+        _ => left == right,
+    }
+}
+
+fn child_pointer(parent: &str, token: &str) -> String {
+    // '~' has to be escaped first, otherwise "~1" would turn into "~01"
+    format!("{}/{}", parent, token.replace('~', "~0").replace('/', "~1"))
+}
+
+impl Violation {
+    fn new(pointer: &str, message: String) -> Self {
+        Violation {
+            pointer: pointer.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.pointer.is_empty() {
+            write!(f, "(root): {}", self.message)
+        } else {
+            write!(f, "{}: {}", self.pointer, self.message)
+        }
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.pointer.is_empty() {
+            write!(f, "(root): {}", self.message)
+        } else {
+            write!(f, "{}: {}", self.pointer, self.message)
+        }
+    }
+}
+
+impl error::Error for SchemaError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PET_OWNER: &str = include_str!("../../pet_owner.json");
+
+    fn violations(schema: Value, document: Value) -> Vec<(String, String)> {
+        let validator = Validator::new(schema).unwrap();
+        match validator.validate(&document) {
+            Ok(()) => Vec::new(),
+            Err(violations) => violations
+                .into_iter()
+                .map(|violation| (violation.pointer, violation.message))
+                .collect(),
+        }
+    }
+
+    fn pointers(schema: Value, document: Value) -> Vec<String> {
+        violations(schema, document)
+            .into_iter()
+            .map(|(pointer, _)| pointer)
+            .collect()
+    }
+
+    fn schema_error(schema: Value) -> SchemaError {
+        match Validator::new(schema) {
+            Err(e) => e,
+            Ok(_) => panic!("Expected the schema to be rejected"),
+        }
+    }
+
+    #[test]
+    fn derives_schemas_from_types() {
+        let u8_schema = json!({ "type": "integer", "minimum": 0, "maximum": 255 });
+        assert_eq!(u8_schema, u8::json_schema());
+        assert_eq!(
+            json!({
+                "title": "AllowedSpecies",
+                "type": "string",
+                "enum": ["Dog", "Turtle", "Cat"]
+            }),
+            AllowedSpecies::json_schema()
+        );
+        assert_eq!(
+            json!({
+                "title": "Pet",
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "minLength": 1, "pattern": "^[A-Z]" },
+                    "species": {
+                        "title": "AllowedSpecies",
+                        "type": "string",
+                        "enum": ["Dog", "Turtle", "Cat"]
+                    },
+                    "age": { "type": ["integer", "null"], "minimum": 0, "maximum": 255 },
+                    "colour": { "type": ["string", "null"] }
+                },
+                "required": ["name", "species"],
+                "additionalProperties": false
+            }),
+            Pet::json_schema()
+        );
+        assert_eq!(
+            json!(150),
+            PetOwner::json_schema()["properties"]["age"]["maximum"]
+        );
+    }
+
+    #[test]
+    fn accepts_valid_documents() {
+        let document: Value = serde_json::from_str(PET_OWNER).unwrap();
+        assert!(violations(PetOwner::json_schema(), document).is_empty());
+        let without_optionals = json!({
+            "name": "Jane",
+            "age": 30,
+            "pets": [{ "name": "Rex", "species": "Dog" }]
+        });
+        assert!(violations(PetOwner::json_schema(), without_optionals).is_empty());
+    }
+
+    #[test]
+    fn reports_every_violation_with_a_pointer() {
+        let document = json!({
+            "name": "",
+            "age": 255,
+            "pets": [
+                { "species": "Dog", "age": -1 },
+                { "name": "speedy", "species": "Fish", "colour": 3, "legs": 4 }
+            ]
+        });
+        assert_eq!(
+            vec![
+                (
+                    "/age".to_string(),
+                    "255 is greater than the maximum of 150".to_string()
+                ),
+                (
+                    "/name".to_string(),
+                    "Expected at least 1 character(s) but found 0".to_string()
+                ),
+                (
+                    "/pets/0".to_string(),
+                    "Missing required property \"name\"".to_string()
+                ),
+                (
+                    "/pets/0/age".to_string(),
+                    "-1 is less than the minimum of 0".to_string()
+                ),
+                (
+                    "/pets/1/colour".to_string(),
+                    "Expected string or null but found integer".to_string()
+                ),
+                (
+                    "/pets/1/legs".to_string(),
+                    "Unexpected property".to_string()
+                ),
+                (
+                    "/pets/1/name".to_string(),
+                    "\"speedy\" does not match the pattern \"^[A-Z]\"".to_string()
+                ),
+                (
+                    "/pets/1/species".to_string(),
+                    "Expected one of \"Dog\", \"Turtle\", \"Cat\" but found \"Fish\"".to_string()
+                ),
+            ],
+            violations(PetOwner::json_schema(), document)
+        );
+    }
+
+    #[test]
+    fn follows_references() {
+        let schema = json!({
+            "definitions": {
+                "node": {
+                    "type": "object",
+                    "properties": {
+                        "value": { "type": "integer", "exclusiveMinimum": 0 },
+                        "children": { "type": "array", "items": { "$ref": "#/definitions/node" } }
+                    },
+                    "required": ["value"]
+                },
+                "alias": { "$ref": "#/definitions/node" }
+            },
+            "$ref": "#/definitions/alias"
+        });
+        let document = json!({
+            "value": 1,
+            "children": [
+                { "value": 2, "children": [{ "value": 0 }] },
+                { "children": [] }
+            ]
+        });
+        assert_eq!(
+            vec!["/children/0/children/0/value", "/children/1"],
+            pointers(schema, document)
+        );
+    }
+
+    #[test]
+    fn follows_references_anywhere_in_the_schema() {
+        let schema = json!({ "$ref": "#/x", "x": { "type": "string", "pattern": "^a" } });
+        assert_eq!(
+            vec!["".to_string()],
+            pointers(schema.clone(), json!("banana"))
+        );
+        assert!(Validator::new(schema)
+            .unwrap()
+            .validate(&json!("apple"))
+            .is_ok());
+    }
+
+    #[test]
+    fn checks_other_keywords() {
+        let schema = json!({
+            "type": "array",
+            "minItems": 3,
+            "items": [
+                { "type": "integer" },
+                { "const": { "a": [1, 2] } },
+                { "type": "string", "maxLength": 3 },
+                false
+            ]
+        });
+        assert!(violations(schema.clone(), json!([2.0, { "a": [1.0, 2] }, "äöü"])).is_empty());
+        assert_eq!(
+            vec!["", "/0", "/1"],
+            pointers(schema.clone(), json!([1.5, { "a": [1] }]))
+        );
+        assert_eq!(
+            vec!["/2", "/3"],
+            pointers(schema, json!([1, { "a": [1, 2] }, "long", null]))
+        );
+        let additional = json!({ "additionalProperties": { "type": "boolean" } });
+        assert_eq!(
+            vec!["/b~1c"],
+            pointers(additional, json!({ "a": true, "b/c": 1 }))
+        );
+    }
+
+    #[test]
+    fn rejects_broken_schemas() {
+        let error = schema_error(json!({ "properties": { "a": { "pattern": "(" } } }));
+        assert_eq!("/properties/a", error.pointer);
+        let error = schema_error(json!({ "items": { "$ref": "#/definitions/missing" } }));
+        assert_eq!(
+            "/items: $ref \"#/definitions/missing\" does not exist in this document",
+            error.to_string()
+        );
+        let error = schema_error(json!({
+            "definitions": { "a": { "$ref": "#/definitions/b" }, "b": { "$ref": "#/definitions/a" } }
+        }));
+        assert_eq!("/definitions/a", error.pointer);
+        assert_eq!(
+            "/items/1",
+            schema_error(json!({ "items": [true, 3] })).pointer
+        );
+        // References to places other than definitions are checked as well
+        let error = schema_error(json!({ "$ref": "#/x", "x": { "pattern": "(" } }));
+        assert_eq!("/x", error.pointer);
+        let error = schema_error(json!({ "$ref": "#/x", "x": { "items": { "$ref": "#/nope" } } }));
+        assert_eq!(
+            "/x/items: $ref \"#/nope\" does not exist in this document",
+            error.to_string()
+        );
+        assert_eq!("/x", schema_error(json!({ "$ref": "#/x", "x": 3 })).pointer);
+    }
+}
@@ -0,0 +1,555 @@
+extern crate flate2;
+extern crate serde;
+extern crate serde_json;
+
+#[macro_use]
+extern crate serde_derive;
+
+use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::de::{self, DeserializeOwned};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use std::{env, error, fmt, fs, process};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Event {
+    timestamp: u64,
+    kind: String,
+    user: Option<String>,
+    duration_ms: Option<u32>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => demo(),
+        ["generate", path, count] => match count.parse() {
+            Ok(count) => generate(Path::new(path), count),
+            Err(_) => {
+                eprintln!("Invalid number of events: {}", count);
+                process::exit(2);
+            }
+        },
+        ["summarize", path] => summarize(Path::new(path)),
+        ["clean", input, output] => clean(Path::new(input), Path::new(output)),
+        _ => {
+            eprintln!("Usage: ndjson [generate <path> <count> | summarize <path> | clean <input> <output>]");
+            eprintln!("Paths ending in .gz are compressed");
+            process::exit(2);
+        }
+    };
+    match result {
+        Ok(0) => {}
+        Ok(malformed) => {
+            eprintln!("Skipped {} malformed line(s)", malformed);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn demo() -> io::Result<usize> {
+    let path = env::temp_dir().join(format!("events-{}.ndjson.gz", process::id()));
+    generate(&path, 5)?;
+    // Damage the log the way a crashed writer or a bad merge would
+    let damaged = env::temp_dir().join(format!("events-{}.damaged.ndjson.gz", process::id()));
+    let mut writer = create(&damaged)?;
+    for (index, line) in open(&path)?.lines().enumerate() {
+        writeln!(writer, "{}", line?)?;
+        if index == 2 {
+            writeln!(writer, "{{\"timestamp\": 3, \"kind\": ")?;
+        }
+    }
+    writer.finish()?;
+    fs::rename(&damaged, &path)?;
+    let malformed = summarize(&path)?;
+    fs::remove_file(&path)?;
+    Ok(malformed)
+}
+
+fn generate(path: &Path, count: u64) -> io::Result<usize> {
+    let kinds = ["login", "search", "purchase", "logout"];
+    // Compressing works better on bigger chunks, so flush less often than by default
+    let mut writer = NdjsonWriter::new(create(path)?)
+        .flush_every(10_000)
+        .flush_interval(Duration::from_secs(5));
+    for timestamp in 0..count {
+        writer.write(&Event {
+            timestamp,
+            kind: kinds[timestamp as usize % kinds.len()].to_string(),
+            user: if timestamp % 3 == 0 {
+                None
+            } else {
+                Some(format!("user{}", timestamp % 7))
+            },
+            duration_ms: Some((timestamp * 37 % 1000) as u32),
+        })?;
+    }
+    writer.into_inner()?.finish()?;
+    Ok(0)
+}
+
+// Only one event is in memory at any time, no matter how big the log is
+fn summarize(path: &Path) -> io::Result<usize> {
+    let mut kinds = BTreeMap::new();
+    let mut malformed = 0;
+    for event in NdjsonReader::<_, Event>::new(open(path)?) {
+        match event {
+            Ok(event) => *kinds.entry(event.kind).or_insert(0) += 1,
+            Err(ReadError::Malformed { line, error }) => {
+                eprintln!("Line {}: {}", line, error);
+                malformed += 1;
+            }
+            Err(ReadError::Io(e)) => return Err(e),
+        }
+    }
+    for (kind, count) in kinds {
+        println!("{:>10}: {}", kind, count);
+    }
+    Ok(malformed)
+}
+
+// Copies all valid events, which can also be used to (de)compress a log
+fn clean(input: &Path, output: &Path) -> io::Result<usize> {
+    let mut writer = NdjsonWriter::new(create(output)?);
+    let mut malformed = 0;
+    for event in NdjsonReader::<_, Event>::new(open(input)?) {
+        match event {
+            Ok(event) => writer.write(&event)?,
+            Err(ReadError::Malformed { line, error }) => {
+                eprintln!("Line {}: {}", line, error);
+                malformed += 1;
+            }
+            Err(ReadError::Io(e)) => return Err(e),
+        }
+    }
+    writer.into_inner()?.finish()?;
+    Ok(malformed)
+}
+
+#[derive(Debug)]
+enum ReadError {
+    // Reading can't continue after an I/O error
+    Io(io::Error),
+    // Malformed lines can be skipped, the next line is read as usual
+    Malformed {
+        line: usize,
+        error: serde_json::Error,
+    },
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReadError::Io(ref e) => write!(f, "Failed to read records: {}", e),
+            ReadError::Malformed { line, ref error } => write!(f, "Line {}: {}", line, error),
+        }
+    }
+}
+
+impl error::Error for ReadError {}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+// Deserializes one line at a time. Blank lines are ignored
+struct NdjsonReader<R, T> {
+    reader: R,
+    // Reused for every line, so reading doesn't allocate per record
+    buffer: Vec<u8>,
+    // Longer lines are reported as malformed without ever being
+    // kept in memory, e.g. a corrupt region without any newlines
+    max_line_len: usize,
+    line: usize,
+    done: bool,
+    record: PhantomData<T>,
+}
+
+impl<R: BufRead, T: DeserializeOwned> NdjsonReader<R, T> {
+    fn new(reader: R) -> Self {
+        NdjsonReader {
+            reader,
+            buffer: Vec::new(),
+            max_line_len: 16 * 1024 * 1024,
+            line: 0,
+            done: false,
+            record: PhantomData,
+        }
+    }
+}
+
+impl<R: BufRead, T: DeserializeOwned> Iterator for NdjsonReader<R, T> {
+    type Item = Result<T, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            self.buffer.clear();
+            let limit = self.max_line_len as u64 + 1;
+            match (&mut self.reader)
+                .take(limit)
+                .read_until(b'\n', &mut self.buffer)
+            {
+                Ok(0) => self.done = true,
+                Ok(read) if read as u64 == limit && !self.buffer.ends_with(b"\n") => {
+                    self.line += 1;
+                    if let Err(e) = skip_line(&mut self.reader) {
+                        self.done = true;
+                        return Some(Err(ReadError::Io(e)));
+                    }
+                    return Some(Err(ReadError::Malformed {
+                        line: self.line,
+                        error: de::Error::custom(format!(
+                            "Line is longer than {} bytes",
+                            self.max_line_len
+                        )),
+                    }));
+                }
+                Ok(_) => {
+                    self.line += 1;
+                    // Invalid UTF-8 is reported by serde_json like any other malformed line
+                    let record = trim_ascii_whitespace(&self.buffer);
+                    if record.is_empty() {
+                        continue;
+                    }
+                    return Some(serde_json::from_slice(record).map_err(|error| {
+                        ReadError::Malformed {
+                            line: self.line,
+                            error,
+                        }
+                    }));
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(ReadError::Io(e)));
+                }
+            }
+        }
+        None
+    }
+}
+
+// Skip the rest of a line without keeping it
+fn skip_line<R: BufRead>(reader: &mut R) -> io::Result<()> {
+    loop {
+        let (len, found) = {
+            let available = match reader.fill_buf() {
+                Ok(available) => available,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            match available.iter().position(|&b| b == b'\n') {
+                Some(index) => (index + 1, true),
+                None => (available.len(), available.is_empty()),
+            }
+        };
+        reader.consume(len);
+        if found {
+            return Ok(());
+        }
+    }
+}
+
+fn trim_ascii_whitespace(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |end| end + 1);
+    &bytes[start..end]
+}
+
+// Writes one record per line and flushes regularly, so that readers
+// following the file see whole records and a crash loses little data
+struct NdjsonWriter<W: Write> {
+    writer: BufWriter<W>,
+    flush_every: usize,
+    flush_interval: Duration,
+    unflushed: usize,
+    last_flush: Instant,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    fn new(writer: W) -> Self {
+        NdjsonWriter {
+            writer: BufWriter::new(writer),
+            flush_every: 1000,
+            flush_interval: Duration::from_secs(1),
+            unflushed: 0,
+            last_flush: Instant::now(),
+        }
+    }
+
+    fn flush_every(mut self, records: usize) -> Self {
+        self.flush_every = records;
+        self
+    }
+
+    fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    fn write<T: Serialize>(&mut self, record: &T) -> io::Result<()> {
+        // serde_json never writes newlines in compact mode,
+        // so every record is guaranteed to stay on its own line
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        self.unflushed += 1;
+        if self.unflushed >= self.flush_every || self.last_flush.elapsed() >= self.flush_interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.unflushed = 0;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    fn into_inner(mut self) -> io::Result<W> {
+        self.flush()?;
+        self.writer
+            .into_inner()
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+}
+
+// Gzip files are recognized by their magic bytes, whatever their name
+fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let is_gzip = reader.fill_buf()?.starts_with(&[0x1F, 0x8B]);
+    Ok(if is_gzip {
+        // Concatenated gzip files, as produced by appending to a log, are read as one
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else {
+        Box::new(reader)
+    })
+}
+
+// Files ending in .gz are compressed
+fn create(path: &Path) -> io::Result<Output> {
+    let file = File::create(path)?;
+    Ok(
+        if path.extension().is_some_and(|extension| extension == "gz") {
+            Output::Gzip(GzEncoder::new(file, Compression::default()))
+        } else {
+            Output::Plain(file)
+        },
+    )
+}
+
+enum Output {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+}
+
+impl Output {
+    // Writes the gzip trailer. Dropping an encoder would do that
+    // as well, but would silently ignore any error
+    fn finish(self) -> io::Result<()> {
+        let file = match self {
+            Output::Plain(file) => file,
+            Output::Gzip(encoder) => encoder.finish()?,
+        };
+        file.sync_all()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Output::Plain(ref mut file) => file.write(buf),
+            Output::Gzip(ref mut encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Output::Plain(ref mut file) => file.flush(),
+            // Flushing a gzip stream ends the current deflate block,
+            // so everything written so far can be decompressed
+            Output::Gzip(ref mut encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::io::{Cursor, Read};
+    use std::rc::Rc;
+
+    // The name comes last, as its extension decides about compression
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        env::temp_dir().join(format!("ndjson-{}-{}", process::id(), name))
+    }
+
+    fn event(timestamp: u64) -> Event {
+        Event {
+            timestamp,
+            kind: "login".to_string(),
+            user: Some("john".to_string()),
+            duration_ms: None,
+        }
+    }
+
+    #[test]
+    fn skips_malformed_lines() {
+        let input: &[u8] = b"{\"timestamp\":1,\"kind\":\"login\",\"user\":\"john\"}\r\n\
+            \n\
+            {\"timestamp\":2,\"kind\":\n\
+            {\"timestamp\":\"3\",\"kind\":\"login\"}\n\
+            \xFF\xFE\n   \n\
+            {\"timestamp\":4,\"kind\":\"login\",\"user\":\"john\",\"duration_ms\":null}";
+        let mut lines = Vec::new();
+        let events: Vec<Event> = NdjsonReader::new(input)
+            .filter_map(|event| match event {
+                Ok(event) => Some(event),
+                Err(ReadError::Malformed { line, .. }) => {
+                    lines.push(line);
+                    None
+                }
+                Err(ReadError::Io(e)) => panic!("{}", e),
+            })
+            .collect();
+        assert_eq!(vec![event(1), event(4)], events);
+        assert_eq!(vec![3, 4, 5], lines);
+    }
+
+    #[test]
+    fn skips_overlong_lines() {
+        let mut input = b"{\"a\": 1}\n".to_vec();
+        input.extend(vec![b'x'; 100_000]);
+        input.extend_from_slice(b"\n{\"a\": 2}\n1234567890\n");
+        let mut reader = NdjsonReader::new(&input[..]);
+        reader.max_line_len = 10;
+        let results: Vec<Result<serde_json::Value, _>> = reader.collect();
+        assert_eq!(4, results.len());
+        assert_eq!(1, results[0].as_ref().unwrap()["a"]);
+        match results[1] {
+            Err(ReadError::Malformed { line: 2, .. }) => {}
+            ref other => panic!("Expected a malformed line, got {:?}", other),
+        }
+        assert_eq!(2, results[2].as_ref().unwrap()["a"]);
+        // Exactly at the limit is fine
+        assert_eq!(1234567890, *results[3].as_ref().unwrap());
+    }
+
+    #[test]
+    fn stops_after_io_errors() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk on fire"))
+            }
+        }
+        let input =
+            Cursor::new(b"{\"timestamp\":1,\"kind\":\"login\",\"user\":\"john\"}\n".to_vec())
+                .chain(Failing);
+        let mut reader = NdjsonReader::<_, Event>::new(BufReader::new(input));
+        assert_eq!(event(1), reader.next().unwrap().unwrap());
+        match reader.next() {
+            Some(Err(ReadError::Io(_))) => {}
+            other => panic!("Expected an I/O error, got {:?}", other),
+        }
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn flushes_periodically() {
+        struct CountingWriter(Rc<Cell<usize>>);
+        impl Write for CountingWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                self.0.set(self.0.get() + 1);
+                Ok(())
+            }
+        }
+        let flushes = Rc::new(Cell::new(0));
+        let mut writer = NdjsonWriter::new(CountingWriter(flushes.clone()))
+            .flush_every(3)
+            .flush_interval(Duration::from_secs(3600));
+        for timestamp in 0..7 {
+            writer.write(&event(timestamp)).unwrap();
+        }
+        assert_eq!(2, flushes.get());
+        writer.into_inner().unwrap();
+        assert_eq!(3, flushes.get());
+
+        let flushes = Rc::new(Cell::new(0));
+        let mut writer = NdjsonWriter::new(CountingWriter(flushes.clone()))
+            .flush_interval(Duration::from_secs(0));
+        writer.write(&event(1)).unwrap();
+        assert_eq!(1, flushes.get());
+    }
+
+    #[test]
+    fn round_trips_compressed_files() {
+        for name in &["events.ndjson", "events.ndjson.gz"] {
+            let path = temp_path(name);
+            let mut writer = NdjsonWriter::new(create(&path).unwrap()).flush_every(2);
+            for timestamp in 0..5 {
+                writer.write(&event(timestamp)).unwrap();
+            }
+            writer.into_inner().unwrap().finish().unwrap();
+
+            let events: Vec<Event> = NdjsonReader::new(open(&path).unwrap())
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!((0..5).map(event).collect::<Vec<_>>(), events);
+        }
+        fs::remove_file(temp_path("events.ndjson")).unwrap();
+        let compressed = fs::read(temp_path("events.ndjson.gz")).unwrap();
+        assert_eq!(&[0x1F, 0x8B], &compressed[..2]);
+
+        // Compressed content is recognized even without the extension
+        let renamed = temp_path("renamed");
+        fs::rename(temp_path("events.ndjson.gz"), &renamed).unwrap();
+        let count = NdjsonReader::<_, Event>::new(open(&renamed).unwrap()).count();
+        assert_eq!(5, count);
+        fs::remove_file(&renamed).unwrap();
+    }
+
+    #[test]
+    fn cleans_logs() {
+        let input = temp_path("broken.ndjson");
+        fs::write(
+            &input,
+            "{\"timestamp\":1,\"kind\":\"login\",\"user\":\"john\"}\nnot json\n",
+        )
+        .unwrap();
+        let output = temp_path("clean.ndjson.gz");
+        assert_eq!(1, clean(&input, &output).unwrap());
+
+        let mut text = String::new();
+        open(&output).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(
+            "{\"timestamp\":1,\"kind\":\"login\",\"user\":\"john\",\"duration_ms\":null}\n",
+            text
+        );
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+}
@@ -0,0 +1,954 @@
+extern crate toml;
+
+use std::fs;
+use std::{env, error, fmt, process};
+use toml::Value;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (path, command) = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => ("preferences.toml", None),
+        [path, ref command @ ..] if !command.is_empty() => (path, Some(command.to_vec())),
+        _ => {
+            eprintln!("Usage: toml_edit [<path> get <key> | set <key> <value> | remove <key>]");
+            process::exit(2);
+        }
+    };
+    let original = fs::read_to_string(path).expect("Failed to read TOML file");
+    let mut document = Document::parse(&original).unwrap_or_else(|e| {
+        eprintln!("Failed to parse {}: {}", path, e);
+        process::exit(1);
+    });
+
+    let result = match command.as_ref().map(|command| &command[..]) {
+        None => {
+            // Without a command, show what a typical edit does to the file
+            document
+                .set("privacy.public_email", Value::Boolean(false))
+                .and_then(|_| document.set("language.spellcheck", Value::Boolean(true)))
+                .map(|_| print!("{}", document))
+        }
+        Some(&["get", key]) => document.get(key).map(|value| match value {
+            Some(value) => println!("{}", format_value(&value)),
+            None => println!("{} is not set", key),
+        }),
+        Some(&["set", key, value]) => document.set(key, parse_value(value)),
+        Some(&["remove", key]) => document.remove(key).map(|removed| {
+            if !removed {
+                println!("{} is not set", key)
+            }
+        }),
+        _ => {
+            eprintln!("Unknown command");
+            process::exit(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to edit {}: {}", path, e);
+        process::exit(1);
+    }
+    let edited = document.to_string();
+    if command.is_some() && edited != original {
+        fs::write(path, edited).expect("Failed to write TOML file");
+    }
+}
+
+// Values on the command line are TOML, but plain words are taken as strings
+fn parse_value(input: &str) -> Value {
+    toml::from_str::<toml::value::Table>(&format!("value = {}", input))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(input.to_string()))
+}
+
+// A TOML file that remembers its exact text. Every item keeps the
+// bytes it was parsed from, so writing an unchanged document
+// reproduces the file byte for byte, including comments and whitespace
+#[derive(Debug, Clone)]
+struct Document {
+    items: Vec<Item>,
+    // Inserted lines use the same line ending as the rest of the file
+    newline: &'static str,
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    // Blank lines and comments
+    Trivia(String),
+    Table {
+        path: Vec<String>,
+        // [[array.of.tables]]
+        array: bool,
+        raw: String,
+    },
+    KeyValue {
+        key: Vec<String>,
+        // Indentation, key and '='
+        before: String,
+        value: String,
+        // Whitespace, comment and line ending
+        after: String,
+    },
+}
+
+#[derive(Debug)]
+enum EditError {
+    Parse(toml::de::Error),
+    InvalidPath(String),
+    // The scanner didn't understand something the toml crate accepted,
+    // given as line and column
+    Scan(usize, usize),
+    // The edit would have turned the document into invalid TOML,
+    // e.g. by defining the same key twice. The document is left unchanged
+    Conflict(String, toml::de::Error),
+}
+
+impl Document {
+    fn parse(text: &str) -> Result<Self, EditError> {
+        // Let the toml crate reject invalid documents, so that
+        // the scanner below only has to deal with valid TOML
+        toml::from_str::<Value>(text).map_err(EditError::Parse)?;
+        let mut scanner = Scanner { text, pos: 0 };
+        let mut items = Vec::new();
+        while !scanner.at_end() {
+            items.push(scanner.item()?);
+        }
+        let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        Ok(Document { items, newline })
+    }
+
+    // The value of a key such as "privacy.public_email"
+    fn get(&self, path: &str) -> Result<Option<Value>, EditError> {
+        let path = parse_path(path)?;
+        let index = match self.find_key(&path) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        match self.items[index] {
+            Item::KeyValue { ref value, .. } => {
+                let mut table: toml::value::Table =
+                    toml::from_str(&format!("value = {}", value)).map_err(EditError::Parse)?;
+                Ok(table.remove("value"))
+            }
+            _ => unreachable!("find_key only returns keys"),
+        }
+    }
+
+    // Changes the value of a key, keeping its comment, or adds it
+    // to its table. Missing tables are added at the end of the document
+    fn set(&mut self, path: &str, value: Value) -> Result<(), EditError> {
+        let full_path = parse_path(path)?;
+        if self.get(path)? == Some(value.clone()) {
+            // Keeps the original formatting of e.g. 1_000 or 'literal' strings
+            return Ok(());
+        }
+        let (key, table) = full_path.split_last().expect("Paths are never empty");
+        let formatted = format_value(&value);
+        self.edit(path, |document| {
+            if let Some(index) = document.find_key(&full_path) {
+                if let Item::KeyValue { ref mut value, .. } = document.items[index] {
+                    *value = formatted;
+                }
+                return;
+            }
+            let newline = document.newline;
+            match document.find_section(table) {
+                Some((start, end)) => {
+                    // Add the key behind the last key of the table, with the same indentation
+                    let last_key = (start..end)
+                        .rev()
+                        .find(|&index| matches!(document.items[index], Item::KeyValue { .. }));
+                    let indent = match last_key.map(|index| &document.items[index]) {
+                        Some(Item::KeyValue { before, .. }) => {
+                            before[..before.len() - before.trim_start().len()].to_string()
+                        }
+                        _ => String::new(),
+                    };
+                    let position = match last_key {
+                        Some(index) => index + 1,
+                        None if table.is_empty() => 0,
+                        None => start + 1,
+                    };
+                    if position > 0 {
+                        document.items[position - 1].end_line(newline);
+                    }
+                    document
+                        .items
+                        .insert(position, key_value(&indent, key, formatted, newline));
+                }
+                None => {
+                    document.append_table(table);
+                    document.items.push(key_value("", key, formatted, newline));
+                }
+            }
+        })
+    }
+
+    // Removes a key, or a table with everything in it
+    fn remove(&mut self, path: &str) -> Result<bool, EditError> {
+        let prefix = parse_path(path)?;
+        let mut removed = false;
+        self.edit(path, |document| {
+            let mut table = Some(Vec::new());
+            // Blank lines and comments belong to the table above them and go away with it
+            let mut in_removed_table = false;
+            let mut matches: Vec<bool> = document
+                .items
+                .iter()
+                .map(|item| match *item {
+                    Item::Table {
+                        path: ref table_path,
+                        array,
+                        ..
+                    } => {
+                        table = if array {
+                            None
+                        } else {
+                            Some(table_path.clone())
+                        };
+                        in_removed_table = table_path.starts_with(&prefix);
+                        in_removed_table
+                    }
+                    Item::KeyValue { ref key, .. } => {
+                        in_removed_table
+                            || table.as_ref().is_some_and(|table| {
+                                let mut full_path = table.clone();
+                                full_path.extend(key.iter().cloned());
+                                full_path.starts_with(&prefix)
+                            })
+                    }
+                    Item::Trivia(_) => in_removed_table,
+                })
+                .collect();
+            // ...except for comments right above a header, which describe the next table
+            let mut header = None;
+            for index in (0..matches.len()).rev() {
+                match document.items[index] {
+                    Item::Table { .. } => header = Some(matches[index]),
+                    Item::Trivia(ref text) if text.trim_start().starts_with('#') => {
+                        if let Some(header) = header {
+                            matches[index] = header;
+                        }
+                    }
+                    _ => header = None,
+                }
+            }
+            removed = matches.contains(&true);
+            let mut matches = matches.into_iter();
+            document.items.retain(|_| !matches.next().unwrap());
+        })?;
+        Ok(removed)
+    }
+
+    // Applies a change and undoes it if the result isn't valid TOML anymore
+    fn edit<F: FnOnce(&mut Document)>(&mut self, path: &str, change: F) -> Result<(), EditError> {
+        let backup = self.items.clone();
+        change(self);
+        if let Err(e) = toml::from_str::<Value>(&self.to_string()) {
+            self.items = backup;
+            return Err(EditError::Conflict(path.to_string(), e));
+        }
+        Ok(())
+    }
+
+    // Walks through the items together with the table they are in.
+    // Keys inside of [[arrays]] of tables have no path
+    fn paths(&self) -> Vec<Option<Vec<String>>> {
+        let mut table = Some(Vec::new());
+        self.items
+            .iter()
+            .map(|item| match *item {
+                Item::Table {
+                    ref path, array, ..
+                } => {
+                    table = if array { None } else { Some(path.clone()) };
+                    None
+                }
+                Item::KeyValue { ref key, .. } => table.as_ref().map(|table| {
+                    let mut full_path = table.clone();
+                    full_path.extend(key.iter().cloned());
+                    full_path
+                }),
+                Item::Trivia(_) => None,
+            })
+            .collect()
+    }
+
+    fn find_key(&self, path: &[String]) -> Option<usize> {
+        self.paths().iter().position(|full_path| {
+            full_path
+                .as_ref()
+                .is_some_and(|full_path| &full_path[..] == path)
+        })
+    }
+
+    // The range of items that belong to a table, starting at its header.
+    // The root table has no header and starts at the beginning of the document
+    fn find_section(&self, path: &[String]) -> Option<(usize, usize)> {
+        let is_header = |item: &Item| matches!(*item, Item::Table { .. });
+        let start = if path.is_empty() {
+            0
+        } else {
+            self.items.iter().position(|item| match *item {
+                Item::Table {
+                    path: ref table_path,
+                    array: false,
+                    ..
+                } => &table_path[..] == path,
+                _ => false,
+            })?
+        };
+        // The root table ends at the first header
+        let first = if path.is_empty() { 0 } else { start + 1 };
+        let end = self.items[first..]
+            .iter()
+            .position(is_header)
+            .map_or(self.items.len(), |offset| first + offset);
+        Some((start, end))
+    }
+
+    fn append_table(&mut self, path: &[String]) {
+        let newline = self.newline;
+        if let Some(last) = self.items.last_mut() {
+            last.end_line(newline);
+            self.items.push(Item::Trivia(newline.to_string()));
+        }
+        let keys: Vec<_> = path.iter().map(|key| format_key(key)).collect();
+        self.items.push(Item::Table {
+            path: path.to_vec(),
+            array: false,
+            raw: format!("[{}]{}", keys.join("."), newline),
+        });
+    }
+}
+
+fn key_value(indent: &str, key: &str, value: String, newline: &str) -> Item {
+    Item::KeyValue {
+        key: vec![key.to_string()],
+        before: format!("{}{} = ", indent, format_key(key)),
+        value,
+        after: newline.to_string(),
+    }
+}
+
+impl Item {
+    // The last line of a file may not have a line ending
+    // yet, which it needs before anything can follow it
+    fn end_line(&mut self, newline: &str) {
+        let text = match *self {
+            Item::Trivia(ref mut text) => text,
+            Item::Table { ref mut raw, .. } => raw,
+            Item::KeyValue { ref mut after, .. } => after,
+        };
+        if !text.ends_with('\n') {
+            text.push_str(newline);
+        }
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for item in &self.items {
+            match *item {
+                Item::Trivia(ref text) => f.write_str(text)?,
+                Item::Table { ref raw, .. } => f.write_str(raw)?,
+                Item::KeyValue {
+                    ref before,
+                    ref value,
+                    ref after,
+                    ..
+                } => {
+                    f.write_str(before)?;
+                    f.write_str(value)?;
+                    f.write_str(after)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_path(path: &str) -> Result<Vec<String>, EditError> {
+    let mut scanner = Scanner { text: path, pos: 0 };
+    match scanner.key() {
+        Ok(key) if scanner.at_end() => Ok(key),
+        _ => Err(EditError::InvalidPath(path.to_string())),
+    }
+}
+
+// Splits the text into items. It only has to find where things begin
+// and end, since the toml crate already made sure the text is valid
+struct Scanner<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn item(&mut self) -> Result<Item, EditError> {
+        let start = self.pos;
+        self.skip_spaces();
+        match self.peek() {
+            None | Some(b'\r') | Some(b'\n') | Some(b'#') => {
+                self.rest_of_line();
+                Ok(Item::Trivia(self.text[start..self.pos].to_string()))
+            }
+            Some(b'[') => {
+                let array = self.eat("[[");
+                if !array {
+                    self.eat("[");
+                }
+                let path = self.key()?;
+                self.skip_spaces();
+                if !self.eat(if array { "]]" } else { "]" }) {
+                    return Err(self.error());
+                }
+                self.rest_of_line();
+                Ok(Item::Table {
+                    path,
+                    array,
+                    raw: self.text[start..self.pos].to_string(),
+                })
+            }
+            Some(_) => {
+                let key = self.key()?;
+                self.skip_spaces();
+                if !self.eat("=") {
+                    return Err(self.error());
+                }
+                self.skip_spaces();
+                let value_start = self.pos;
+                self.value()?;
+                let value_end = self.pos;
+                self.rest_of_line();
+                Ok(Item::KeyValue {
+                    key,
+                    before: self.text[start..value_start].to_string(),
+                    value: self.text[value_start..value_end].to_string(),
+                    after: self.text[value_end..self.pos].to_string(),
+                })
+            }
+        }
+    }
+
+    // A dotted key like `a."b.c".d`
+    fn key(&mut self) -> Result<Vec<String>, EditError> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some(b'"') => self.basic_string()?,
+                Some(b'\'') => {
+                    let start = self.pos + 1;
+                    self.literal_string()?;
+                    self.text[start..self.pos - 1].to_string()
+                }
+                _ => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(is_bare_key_byte) {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        return Err(self.error());
+                    }
+                    self.text[start..self.pos].to_string()
+                }
+            };
+            parts.push(part);
+            self.skip_spaces();
+            if !self.eat(".") {
+                return Ok(parts);
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<(), EditError> {
+        match self.peek() {
+            Some(b'"') if self.text[self.pos..].starts_with("\"\"\"") => {
+                self.pos += 3;
+                self.skip_past("\"\"\"", true)
+            }
+            Some(b'\'') if self.text[self.pos..].starts_with("'''") => {
+                self.pos += 3;
+                self.skip_past("'''", false)
+            }
+            Some(b'"') => self.basic_string().map(|_| ()),
+            Some(b'\'') => self.literal_string(),
+            Some(b'[') => {
+                self.pos += 1;
+                loop {
+                    self.skip_whitespace_and_comments();
+                    if self.eat("]") {
+                        return Ok(());
+                    }
+                    self.value()?;
+                    self.skip_whitespace_and_comments();
+                    self.eat(",");
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                loop {
+                    self.skip_spaces();
+                    if self.eat("}") {
+                        return Ok(());
+                    }
+                    self.key()?;
+                    self.skip_spaces();
+                    if !self.eat("=") {
+                        return Err(self.error());
+                    }
+                    self.skip_spaces();
+                    self.value()?;
+                    self.skip_spaces();
+                    self.eat(",");
+                }
+            }
+            Some(_) => {
+                let start = self.pos;
+                self.skip_scalar();
+                // Dates and times may be separated by a space, as in 1979-05-27 07:32:00
+                let is_date = self.pos - start == 10 && self.text.as_bytes()[start + 4] == b'-';
+                let time_follows = self.text[self.pos..].starts_with(' ')
+                    && self
+                        .text
+                        .as_bytes()
+                        .get(self.pos + 1)
+                        .is_some_and(u8::is_ascii_digit);
+                if is_date && time_follows {
+                    self.pos += 1;
+                    self.skip_scalar();
+                }
+                if start == self.pos {
+                    return Err(self.error());
+                }
+                Ok(())
+            }
+            None => Err(self.error()),
+        }
+    }
+
+    fn skip_scalar(&mut self) {
+        while self.peek().is_some_and(|b| !b" \t\r\n,]}#".contains(&b)) {
+            self.pos += 1;
+        }
+    }
+
+    // Returns the unescaped content of a "basic string"
+    fn basic_string(&mut self) -> Result<String, EditError> {
+        self.pos += 1;
+        let mut string = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += offset + 1;
+                    return Ok(string);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('b') => string.push('\u{8}'),
+                    Some('t') => string.push('\t'),
+                    Some('n') => string.push('\n'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('r') => string.push('\r'),
+                    Some(c @ 'u') | Some(c @ 'U') => {
+                        let len = if c == 'u' { 4 } else { 8 };
+                        let hex: String = chars.by_ref().take(len).map(|(_, c)| c).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(std::char::from_u32)
+                            .ok_or_else(|| self.error())?;
+                        string.push(c);
+                    }
+                    Some(c) => string.push(c),
+                    None => break,
+                },
+                c => string.push(c),
+            }
+        }
+        Err(self.error())
+    }
+
+    fn literal_string(&mut self) -> Result<(), EditError> {
+        self.pos += 1;
+        self.skip_past("'", false)
+    }
+
+    fn skip_past(&mut self, end: &str, escapes: bool) -> Result<(), EditError> {
+        while !self.at_end() {
+            if escapes && self.eat("\\") {
+                self.pos += self.text[self.pos..]
+                    .chars()
+                    .next()
+                    .map_or(0, char::len_utf8);
+            } else if self.eat(end) {
+                // A closing """ may be followed by up to two more quotes that belong to the string
+                let quote = &end[..1];
+                for _ in 0..2 {
+                    if end.len() == 3 {
+                        self.eat(quote);
+                    }
+                }
+                return Ok(());
+            } else {
+                self.pos += self.text[self.pos..]
+                    .chars()
+                    .next()
+                    .map_or(0, char::len_utf8);
+            }
+        }
+        Err(self.error())
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            match self.peek() {
+                Some(b' ') | Some(b'\t') | Some(b'\r') | Some(b'\n') => self.pos += 1,
+                Some(b'#') => {
+                    while self.peek().is_some_and(|b| b != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    // Whitespace, an optional comment and the line ending
+    fn rest_of_line(&mut self) {
+        while self.peek().is_some_and(|b| b != b'\n') {
+            self.pos += 1;
+        }
+        self.eat("\n");
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(b' ') || self.peek() == Some(b'\t') {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.text[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).cloned()
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.text.len()
+    }
+
+    fn error(&self) -> EditError {
+        let before = &self.text[..self.pos];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        EditError::Scan(
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        )
+    }
+}
+
+fn is_bare_key_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'-'
+}
+
+fn format_key(key: &str) -> String {
+    if !key.is_empty() && key.bytes().all(is_bare_key_byte) {
+        key.to_string()
+    } else {
+        format_string(key)
+    }
+}
+
+fn format_string(s: &str) -> String {
+    let mut formatted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => formatted.push_str("\\\""),
+            '\\' => formatted.push_str("\\\\"),
+            '\n' => formatted.push_str("\\n"),
+            '\t' => formatted.push_str("\\t"),
+            '\r' => formatted.push_str("\\r"),
+            c if c.is_control() => formatted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => formatted.push(c),
+        }
+    }
+    formatted.push('"');
+    formatted
+}
+
+// Writes a value the way it would appear on the right side of a '='
+fn format_value(value: &Value) -> String {
+    match *value {
+        Value::String(ref s) => format_string(s),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) if f.is_nan() => "nan".to_string(),
+        Value::Float(f) if f.is_infinite() => if f > 0.0 { "inf" } else { "-inf" }.to_string(),
+        // Debug formatting always adds a decimal point or an exponent, as TOML requires
+        Value::Float(f) => format!("{:?}", f),
+        Value::Boolean(b) => b.to_string(),
+        Value::Datetime(ref datetime) => datetime.to_string(),
+        Value::Array(ref values) => {
+            let values: Vec<_> = values.iter().map(format_value).collect();
+            format!("[{}]", values.join(", "))
+        }
+        Value::Table(ref table) => {
+            let entries: Vec<_> = table
+                .iter()
+                .map(|(key, value)| format!("{} = {}", format_key(key), format_value(value)))
+                .collect();
+            if entries.is_empty() {
+                "{}".to_string()
+            } else {
+                format!("{{ {} }}", entries.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EditError::Parse(ref e) => write!(f, "Invalid TOML: {}", e),
+            EditError::InvalidPath(ref path) => write!(f, "Invalid key path: {}", path),
+            EditError::Scan(line, column) => {
+                write!(f, "Unsupported syntax at line {}, column {}", line, column)
+            }
+            EditError::Conflict(ref path, ref e) => {
+                write!(
+                    f,
+                    "Changing {} would make the document invalid: {}",
+                    path, e
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for EditError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREFERENCES: &str = include_str!("../../preferences.toml");
+
+    // Files that exercise as much of the syntax as possible
+    const CORPUS: &[&str] = &[
+        PREFERENCES,
+        "",
+        "\n\n",
+        "# Only a comment",
+        "key = \"no trailing newline\"",
+        "  indented   =   1   # spaced out\n\t[ table . \"quoted key\" ]  # header comment\n\ta=true\n",
+        "windows = 1\r\n\r\n[line.endings]\r\nkept = \"yes\"\r\n",
+        "multi = [\n  1, # one\n  2,\n  # a comment line\n  3,\n]\nnested = [[1, 2], [\"a\", 'b']]\n",
+        "basic = \"\"\"\nline one\\\n  continued \"quoted\" \\\"\"\"\"\nliteral = '''\n# not a comment\n[not.a.table]\n'''\n",
+        "escapes = \"tab\\t quote\\\" unicode\\u00e9 \\U0001F600\"\nwinpath = 'C:\\Users\\nodejs'\n",
+        "point = { x = 1, y = -2.5e3, label = \"a, b }\" }\nempty = {}\n",
+        "date = 1979-05-27\ndatetime = 1979-05-27 07:32:00Z\nlocal = 1979-05-27T00:32:00.999999\n",
+        "hex = 0xDEAD_BEEF\nint = 1_000\nfloat = +1.0\ninf = -inf\nnan = nan\n",
+        "dotted.keys = 1\n\"quoted.key\".with.dots = 2\n'literal key' = 3\n",
+        "[[products]]\nname = \"Hammer\"\n\n[[products]]  # second\n\n[[products]]\nname = \"Nail\"\n",
+        "unicode = \"Grüße, 世界\" # ✓\n[\"ümlaut\"]\n\"ключ\" = 'значение'\n",
+    ];
+
+    fn edited<F: FnOnce(&mut Document)>(text: &str, edit: F) -> String {
+        let mut document = Document::parse(text).unwrap();
+        edit(&mut document);
+        document.to_string()
+    }
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        for text in CORPUS {
+            let document = Document::parse(text).unwrap();
+            assert_eq!(*text, document.to_string());
+        }
+    }
+
+    #[test]
+    fn reads_values() {
+        let document = Document::parse(CORPUS[7]).unwrap();
+        assert_eq!(
+            Some(Value::Array(vec![
+                Value::Integer(1),
+                Value::Integer(2),
+                Value::Integer(3)
+            ])),
+            document.get("multi").unwrap()
+        );
+        let document = Document::parse(CORPUS[5]).unwrap();
+        assert_eq!(
+            Some(Value::Boolean(true)),
+            document.get("table.\"quoted key\".a").unwrap()
+        );
+        let document = Document::parse(CORPUS[13]).unwrap();
+        assert_eq!(
+            Some(Value::Integer(2)),
+            document.get("\"quoted.key\".with.dots").unwrap()
+        );
+        assert_eq!(None, document.get("dotted").unwrap());
+        assert_eq!(None, document.get("missing.key").unwrap());
+        // Keys in arrays of tables can't be addressed with a path
+        let document = Document::parse(CORPUS[14]).unwrap();
+        assert_eq!(None, document.get("products.name").unwrap());
+    }
+
+    #[test]
+    fn changes_only_the_value() {
+        let text =
+            "# My settings\n[privacy]\npublic_email   = true   # who can see it\nother = 1\n";
+        let result = edited(text, |document| {
+            document
+                .set("privacy.public_email", Value::Boolean(false))
+                .unwrap()
+        });
+        assert_eq!(
+            "# My settings\n[privacy]\npublic_email   = false   # who can see it\nother = 1\n",
+            result
+        );
+
+        let multi_line = edited(CORPUS[7], |document| {
+            document
+                .set("multi", Value::Array(vec![Value::Integer(4)]))
+                .unwrap()
+        });
+        assert_eq!("multi = [4]\nnested = [[1, 2], [\"a\", 'b']]\n", multi_line);
+
+        // Setting a key to the value it already has keeps its formatting
+        let unchanged = edited(CORPUS[12], |document| {
+            document.set("int", Value::Integer(1000)).unwrap();
+            document.set("hex", Value::Integer(0xDEAD_BEEF)).unwrap();
+        });
+        assert_eq!(CORPUS[12], unchanged);
+    }
+
+    #[test]
+    fn inserts_keys_and_tables() {
+        let text =
+            "title = \"x\"\n\n[person]\n  name = \"John\" # indented\n\n# Next table\n[privacy]\n";
+        let result = edited(text, |document| {
+            document.set("person.age", Value::Integer(23)).unwrap();
+            document
+                .set("privacy.public_email", Value::Boolean(true))
+                .unwrap();
+            document.set("version", Value::Integer(2)).unwrap();
+            document
+                .set("language.\"auto correct\"", Value::String("en".to_string()))
+                .unwrap();
+        });
+        assert_eq!(
+            "title = \"x\"\nversion = 2\n\n[person]\n  name = \"John\" # indented\n  age = 23\n\n\
+             # Next table\n[privacy]\npublic_email = true\n\n[language]\n\"auto correct\" = \"en\"\n",
+            result
+        );
+
+        // Line endings are kept, even if the last line has none
+        let windows = edited("[a]\r\nb = 1", |document| {
+            document.set("a.c", Value::Float(2.0)).unwrap();
+            document.set("d.e", Value::Boolean(false)).unwrap();
+        });
+        assert_eq!(
+            "[a]\r\nb = 1\r\nc = 2.0\r\n\r\n[d]\r\ne = false\r\n",
+            windows
+        );
+
+        let empty = edited("", |document| document.set("a", Value::Integer(1)).unwrap());
+        assert_eq!("a = 1\n", empty);
+        let only_tables = edited("[t]\n", |document| {
+            document.set("a", Value::Integer(1)).unwrap()
+        });
+        assert_eq!("a = 1\n[t]\n", only_tables);
+    }
+
+    #[test]
+    fn removes_keys_and_tables() {
+        let text = "a = 1\nb.c = 2\n\n[t]\nx = 1 # gone\ny = 2\n\n[t.sub]\nz = 3\n\n[u]\nw = 4\n";
+        assert_eq!(
+            "a = 1\n\n[t]\nx = 1 # gone\ny = 2\n\n[t.sub]\nz = 3\n\n[u]\nw = 4\n",
+            edited(text, |document| assert!(document.remove("b").unwrap()))
+        );
+        assert_eq!(
+            "a = 1\nb.c = 2\n\n[t]\ny = 2\n\n[t.sub]\nz = 3\n\n[u]\nw = 4\n",
+            edited(text, |document| assert!(document.remove("t.x").unwrap()))
+        );
+        assert_eq!(
+            "a = 1\nb.c = 2\n\n[u]\nw = 4\n",
+            edited(text, |document| assert!(document.remove("t").unwrap()))
+        );
+        assert_eq!(
+            text,
+            edited(text, |document| assert!(!document
+                .remove("missing")
+                .unwrap()))
+        );
+
+        // Comments right above a header go with that table
+        let text = "[a]\nx = 1\n# end of a\n\n# Settings for b\n# more\n[b]\ny = 2\n\n# c\n[c]\n";
+        assert_eq!(
+            "# Settings for b\n# more\n[b]\ny = 2\n\n# c\n[c]\n",
+            edited(text, |document| assert!(document.remove("a").unwrap()))
+        );
+        assert_eq!(
+            "[a]\nx = 1\n# end of a\n\n# c\n[c]\n",
+            edited(text, |document| assert!(document.remove("b").unwrap()))
+        );
+    }
+
+    #[test]
+    fn rejects_edits_that_break_the_document() {
+        let text = "[person]\nname = \"John\"\npoint = { x = 1 }\n";
+        let mut document = Document::parse(text).unwrap();
+        match document.set("person.name.first", Value::String("J".to_string())) {
+            Err(EditError::Conflict(ref path, _)) if path == "person.name.first" => {}
+            other => panic!("Expected a conflict, got {:?}", other),
+        }
+        assert!(document.set("person.point.y", Value::Integer(2)).is_err());
+        assert!(document.set("person", Value::Integer(2)).is_err());
+        assert_eq!(text, document.to_string());
+
+        match document.set("person..name", Value::Integer(1)) {
+            Err(EditError::InvalidPath(_)) => {}
+            other => panic!("Expected an invalid path, got {:?}", other),
+        }
+        match Document::parse("a = \nb = 1") {
+            Err(EditError::Parse(_)) => {}
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+        let mut scanner = Scanner {
+            text: "a = 1\nb = \n",
+            pos: 6,
+        };
+        match scanner.item() {
+            Err(EditError::Scan(2, 5)) => {}
+            other => panic!("Expected a scan error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn formats_new_values() {
+        let mut table = toml::value::Table::new();
+        table.insert("a b".to_string(), Value::Boolean(true));
+        table.insert("c".to_string(), Value::Array(vec![]));
+        let value = Value::Array(vec![
+            Value::String("q\"uote\n".to_string()),
+            Value::Float(1.0),
+            Value::Float(1e20),
+            Value::Float(-0.5),
+            Value::Table(table),
+            Value::Datetime("1979-05-27T07:32:00Z".parse().unwrap()),
+        ]);
+        let formatted = format_value(&value);
+        assert_eq!(
+            "[\"q\\\"uote\\n\", 1.0, 1e20, -0.5, { \"a b\" = true, c = [] }, 1979-05-27T07:32:00Z]",
+            formatted
+        );
+    }
+}
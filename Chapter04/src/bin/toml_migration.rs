@@ -0,0 +1,579 @@
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::{env, error, fmt, process};
+use toml::value::{Table, Value};
+
+// The current layout of the preferences file. Whenever it changes,
+// a migration has to be added to MIGRATIONS so that old files keep working
+#[derive(Debug, Serialize, Deserialize)]
+struct Preferences {
+    schema_version: u32,
+    person: Person,
+    language: Language,
+    privacy: Privacy,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Person {
+    name: String,
+    email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Language {
+    interface: String,
+    autocorrect: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Privacy {
+    share_anonymous_statistics: bool,
+    visibility: Visibility,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Visibility {
+    name: bool,
+    email: bool,
+}
+
+// Upgrades a document from version `from` to `from + 1`.
+// Migrations work on the raw TOML instead of structs, because the
+// structs for older versions don't exist anymore
+struct Migration {
+    from: u32,
+    description: &'static str,
+    apply: fn(&mut Table) -> Result<(), MigrationError>,
+}
+
+// Files without a schema_version are version 0
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "Default language.autocorrect to an empty list",
+        apply: default_autocorrect,
+    },
+    Migration {
+        from: 1,
+        description: "Move privacy.public_* into privacy.visibility",
+        apply: move_visibility,
+    },
+    Migration {
+        from: 2,
+        description: "Rename language.display to language.interface",
+        apply: rename_display_language,
+    },
+];
+
+const CURRENT_VERSION: u32 = 3;
+const VERSION_KEY: &str = "schema_version";
+
+fn default_autocorrect(preferences: &mut Table) -> Result<(), MigrationError> {
+    set_default(
+        preferences,
+        "language.autocorrect",
+        Value::Array(Vec::new()),
+    )
+}
+
+fn move_visibility(preferences: &mut Table) -> Result<(), MigrationError> {
+    rename(
+        preferences,
+        "privacy.public_name",
+        "privacy.visibility.name",
+    )?;
+    rename(
+        preferences,
+        "privacy.public_email",
+        "privacy.visibility.email",
+    )
+}
+
+fn rename_display_language(preferences: &mut Table) -> Result<(), MigrationError> {
+    rename(preferences, "language.display", "language.interface")
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => demo(),
+        ["--dry-run", path] => run(Path::new(path), true),
+        [path] => run(Path::new(path), false),
+        _ => {
+            eprintln!("Usage: toml_migration [--dry-run] <path>");
+            process::exit(2);
+        }
+    }
+}
+
+// Upgrades a copy of the unversioned file written by toml.rs,
+// which the other recipes still expect to find as it is
+fn demo() {
+    let path = env::temp_dir().join(format!("preferences-{}.toml", process::id()));
+    fs::copy("preferences.toml", &path)
+        .expect("Failed to copy preferences.toml, run toml first to create it");
+    run(&path, true);
+    println!();
+    run(&path, false);
+    fs::remove_file(with_suffix(&path, ".v0.bak")).expect("Failed to remove backup");
+    fs::remove_file(&path).expect("Failed to remove upgraded file");
+}
+
+fn run(path: &Path, dry_run: bool) {
+    let upgrade = upgrade_file(path, dry_run).unwrap_or_else(|e| {
+        eprintln!("Failed to load {}: {}", path.display(), e);
+        process::exit(1);
+    });
+    let path = path.display();
+    match upgrade.migrated_from {
+        Some(version) if dry_run => {
+            println!(
+                "Would upgrade {} from version {} to {}:",
+                path, version, CURRENT_VERSION
+            );
+            for migration in &MIGRATIONS[version as usize..] {
+                println!("  {}", migration.description);
+            }
+            print!("{}", upgrade.diff);
+        }
+        Some(version) => {
+            println!(
+                "Upgraded {} from version {} to {}",
+                path, version, CURRENT_VERSION
+            );
+            for migration in &MIGRATIONS[version as usize..] {
+                println!("  {}", migration.description);
+            }
+            if let Some(backup) = upgrade.backup {
+                println!("The original file was saved as {}", backup.display());
+            }
+        }
+        None => println!("{} is up to date", path),
+    }
+    println!("{:#?}", upgrade.preferences);
+}
+
+#[derive(Debug)]
+enum MigrationError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+    InvalidVersion(Value),
+    // The file was written by a newer version of the program
+    UnsupportedVersion(u32),
+    // Upgrading would overwrite an earlier backup
+    BackupExists(PathBuf),
+    // A migration expected a table at this path
+    NotATable(String),
+}
+
+// The result of loading a preferences file
+struct Upgrade {
+    preferences: Preferences,
+    // The version the file had, if it had to be migrated
+    migrated_from: Option<u32>,
+    // The line diff between the old and the new file
+    diff: String,
+    backup: Option<PathBuf>,
+}
+
+// Loads the preferences, migrating the file to the current version
+// if needed. The original is kept as "<path>.v<version>.bak".
+// In a dry run, nothing is written and the diff shows what would change
+fn upgrade_file(path: &Path, dry_run: bool) -> Result<Upgrade, MigrationError> {
+    let original = fs::read_to_string(path)?;
+    let mut document: Value = toml::from_str(&original)?;
+    let version = migrate(&mut document)?;
+    let preferences: Preferences = document.clone().try_into()?;
+    if version == CURRENT_VERSION {
+        return Ok(Upgrade {
+            preferences,
+            migrated_from: None,
+            diff: String::new(),
+            backup: None,
+        });
+    }
+
+    // Writing the migrated document instead of the struct keeps keys the
+    // struct doesn't know about, at the cost of sorting them
+    let migrated = toml::to_string(&document)?;
+    let diff = line_diff(&original, &migrated);
+    let backup = if dry_run {
+        None
+    } else {
+        let backup = with_suffix(path, &format!(".v{}.bak", version));
+        let mut backup_file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup)
+        {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(MigrationError::BackupExists(backup))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        backup_file.write_all(original.as_bytes())?;
+        backup_file.sync_all()?;
+        // Writing to a temporary file first means that a crash can't leave
+        // a half written preferences file behind, as long as the content
+        // is on disk before the rename and the rename is synced as well
+        let temporary = with_suffix(path, ".tmp");
+        let result = File::create(&temporary)
+            .and_then(|mut file| {
+                file.write_all(migrated.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temporary, path));
+        if let Err(e) = result {
+            let _ = fs::remove_file(&temporary);
+            return Err(e.into());
+        }
+        sync_directory(path)?;
+        Some(backup)
+    };
+    Ok(Upgrade {
+        preferences,
+        migrated_from: Some(version),
+        diff,
+        backup,
+    })
+}
+
+// Runs every migration the document needs, one version at a time,
+// and returns the version the document had before
+fn migrate(document: &mut Value) -> Result<u32, MigrationError> {
+    let table = document
+        .as_table_mut()
+        .ok_or_else(|| MigrationError::NotATable(String::new()))?;
+    let version = match table.get(VERSION_KEY) {
+        None => 0,
+        Some(&Value::Integer(version)) if version >= 0 && version <= u32::MAX as i64 => {
+            version as u32
+        }
+        Some(version) => return Err(MigrationError::InvalidVersion(version.clone())),
+    };
+    if version > CURRENT_VERSION {
+        return Err(MigrationError::UnsupportedVersion(version));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        (migration.apply)(table)?;
+        table.insert(
+            VERSION_KEY.to_string(),
+            Value::Integer(i64::from(migration.from + 1)),
+        );
+    }
+    Ok(version)
+}
+
+// Returns the table that contains the last key of a dotted path.
+// Missing tables are created if `create` is set
+fn parent_table<'a>(
+    root: &'a mut Table,
+    path: &str,
+    create: bool,
+) -> Result<Option<(&'a mut Table, String)>, MigrationError> {
+    let parts: Vec<_> = path.split('.').collect();
+    let (key, parents) = parts.split_last().expect("split always returns a part");
+    let mut table = root;
+    for (depth, part) in parents.iter().enumerate() {
+        if !table.contains_key(*part) {
+            if !create {
+                return Ok(None);
+            }
+            table.insert(part.to_string(), Value::Table(Table::new()));
+        }
+        table = match table.get_mut(*part) {
+            Some(Value::Table(table)) => table,
+            _ => return Err(MigrationError::NotATable(parts[..=depth].join("."))),
+        };
+    }
+    Ok(Some((table, key.to_string())))
+}
+
+fn take(root: &mut Table, path: &str) -> Result<Option<Value>, MigrationError> {
+    Ok(parent_table(root, path, false)?.and_then(|(table, key)| table.remove(&key)))
+}
+
+fn put(root: &mut Table, path: &str, value: Value) -> Result<(), MigrationError> {
+    let (table, key) = parent_table(root, path, true)?.expect("Tables are created");
+    table.insert(key, value);
+    Ok(())
+}
+
+// Moves a value to a new path, which may be in a different table
+fn rename(root: &mut Table, from: &str, to: &str) -> Result<(), MigrationError> {
+    match take(root, from)? {
+        Some(value) => put(root, to, value),
+        None => Ok(()),
+    }
+}
+
+fn set_default(root: &mut Table, path: &str, value: Value) -> Result<(), MigrationError> {
+    let exists =
+        parent_table(root, path, false)?.is_some_and(|(table, key)| table.contains_key(&key));
+    if exists {
+        Ok(())
+    } else {
+        put(root, path, value)
+    }
+}
+
+#[cfg(unix)]
+fn sync_directory(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_path: &Path) -> io::Result<()> {
+    // Directories cannot be opened like files on every platform
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// A minimal line based diff: unchanged lines start with a space,
+// removed lines with '-' and added lines with '+'
+fn line_diff(old: &str, new: &str) -> String {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+    // lengths[i][j] is the longest common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff += &format!("  {}\n", old[i]);
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            diff += &format!("- {}\n", old[i]);
+            i += 1;
+        } else {
+            diff += &format!("+ {}\n", new[j]);
+            j += 1;
+        }
+    }
+    diff
+}
+
+impl From<io::Error> for MigrationError {
+    fn from(e: io::Error) -> Self {
+        MigrationError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for MigrationError {
+    fn from(e: toml::de::Error) -> Self {
+        MigrationError::Parse(e)
+    }
+}
+
+impl From<toml::ser::Error> for MigrationError {
+    fn from(e: toml::ser::Error) -> Self {
+        MigrationError::Serialize(e)
+    }
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MigrationError::Io(ref e) => write!(f, "IO error: {}", e),
+            MigrationError::Parse(ref e) => write!(f, "Invalid preferences: {}", e),
+            MigrationError::Serialize(ref e) => write!(f, "Failed to write TOML: {}", e),
+            MigrationError::InvalidVersion(ref version) => {
+                write!(f, "{} is not a valid {}", version, VERSION_KEY)
+            }
+            MigrationError::UnsupportedVersion(version) => write!(
+                f,
+                "Version {} is newer than the supported version {}",
+                version, CURRENT_VERSION
+            ),
+            MigrationError::BackupExists(ref backup) => write!(
+                f,
+                "The backup {} already exists, move it out of the way first",
+                backup.display()
+            ),
+            MigrationError::NotATable(ref path) if path.is_empty() => {
+                write!(f, "Expected the document to be a table")
+            }
+            MigrationError::NotATable(ref path) => write!(f, "Expected {} to be a table", path),
+        }
+    }
+}
+
+impl error::Error for MigrationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    // The file written by toml.rs, which predates versioning
+    const UNVERSIONED: &str = include_str!("../../preferences.toml");
+
+    fn migrated(input: &str) -> Result<(u32, Value), MigrationError> {
+        let mut document: Value = toml::from_str(input)?;
+        let version = migrate(&mut document)?;
+        Ok((version, document))
+    }
+
+    #[test]
+    fn migrations_are_consecutive() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(index as u32, migration.from, "{}", migration.description);
+        }
+        assert_eq!(CURRENT_VERSION as usize, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn upgrades_unversioned_documents() {
+        let (version, document) = migrated(UNVERSIONED).unwrap();
+        assert_eq!(0, version);
+        let preferences: Preferences = document.try_into().unwrap();
+        assert_eq!(CURRENT_VERSION, preferences.schema_version);
+        assert_eq!("en-GB", preferences.language.interface);
+        assert_eq!(3, preferences.language.autocorrect.len());
+        assert!(preferences.privacy.visibility.name);
+        assert!(!preferences.privacy.share_anonymous_statistics);
+    }
+
+    #[test]
+    fn only_runs_missing_migrations() {
+        let version_one = "schema_version = 1\n[language]\ndisplay = \"de-CH\"\nautocorrect = []\n\
+                           [privacy]\npublic_email = false\n";
+        let (version, document) = migrated(version_one).unwrap();
+        assert_eq!(1, version);
+        assert_eq!(Some(&Value::Integer(3)), document.get("schema_version"));
+        assert_eq!(
+            Some(&Value::Boolean(false)),
+            document["privacy"]["visibility"].get("email")
+        );
+        assert_eq!(None, document["privacy"].get("public_email"));
+        assert_eq!(
+            Some(&Value::String("de-CH".to_string())),
+            document["language"].get("interface")
+        );
+        // A missing autocorrect list is not added again after version 0
+        let (_, document) = migrated("schema_version = 2\n[language]\ndisplay = \"en\"\n").unwrap();
+        assert_eq!(None, document["language"].get("autocorrect"));
+
+        let current = "schema_version = 3\n[language]\ndisplay = \"kept\"\n";
+        let (version, document) = migrated(current).unwrap();
+        assert_eq!(3, version);
+        assert!(document["language"].get("display").is_some());
+    }
+
+    #[test]
+    fn rejects_invalid_versions() {
+        match migrated("schema_version = 4") {
+            Err(MigrationError::UnsupportedVersion(4)) => {}
+            other => panic!("Unexpected result: {:?}", other.map(|(version, _)| version)),
+        }
+        match migrated("schema_version = \"1\"") {
+            Err(MigrationError::InvalidVersion(_)) => {}
+            other => panic!("Unexpected result: {:?}", other.map(|(version, _)| version)),
+        }
+        match migrated("language = \"en\"") {
+            Err(MigrationError::NotATable(ref path)) if path == "language" => {}
+            other => panic!("Unexpected result: {:?}", other.map(|(version, _)| version)),
+        }
+    }
+
+    #[test]
+    fn backs_up_and_rewrites_files() {
+        let path = env::temp_dir().join(format!("toml-migration-{}.toml", process::id()));
+        let backup = with_suffix(&path, ".v0.bak");
+        let _ = fs::remove_file(&backup);
+        fs::write(&path, UNVERSIONED).unwrap();
+        let upgrade = upgrade_file(&path, false).unwrap();
+        assert_eq!(Some(0), upgrade.migrated_from);
+        assert_eq!(Some(&backup), upgrade.backup.as_ref());
+        assert_eq!(UNVERSIONED, fs::read_to_string(&backup).unwrap());
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert!(rewritten.starts_with("schema_version = 3\n"));
+        assert!(!with_suffix(&path, ".tmp").exists());
+
+        // Keys the program doesn't know about are kept
+        fs::remove_file(&backup).unwrap();
+        fs::write(
+            &path,
+            format!("{}\n[plugins]\nenabled = true\n", UNVERSIONED),
+        )
+        .unwrap();
+        upgrade_file(&path, false).unwrap();
+        let rewritten = fs::read_to_string(&path).unwrap();
+        let document: Value = toml::from_str(&rewritten).unwrap();
+        assert_eq!(
+            Some(&Value::Boolean(true)),
+            document["plugins"].get("enabled")
+        );
+
+        // The second run has nothing left to do
+        let upgrade = upgrade_file(&path, false).unwrap();
+        assert_eq!(None, upgrade.migrated_from);
+        assert_eq!(rewritten, fs::read_to_string(&path).unwrap());
+
+        // An older backup is never overwritten
+        fs::write(&path, UNVERSIONED).unwrap();
+        match upgrade_file(&path, false) {
+            Err(MigrationError::BackupExists(ref existing)) if *existing == backup => {}
+            other => panic!("Expected an existing backup, got {:?}", other.err()),
+        }
+        assert_eq!(UNVERSIONED, fs::read_to_string(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&backup).unwrap();
+    }
+
+    #[test]
+    fn dry_runs_only_show_the_diff() {
+        let path = env::temp_dir().join(format!("toml-migration-dry-run-{}.toml", process::id()));
+        fs::write(&path, UNVERSIONED).unwrap();
+        let upgrade = upgrade_file(&path, true).unwrap();
+        assert_eq!(Some(0), upgrade.migrated_from);
+        assert_eq!(None, upgrade.backup);
+        assert_eq!(UNVERSIONED, fs::read_to_string(&path).unwrap());
+        let diff: Vec<_> = upgrade.diff.lines().collect();
+        assert!(diff.contains(&"+ schema_version = 3"));
+        assert!(diff.contains(&"- display = \"en-GB\""));
+        assert!(diff.contains(&"+ interface = \"en-GB\""));
+        assert!(diff.contains(&"- public_email = true"));
+        assert!(diff.contains(&"+ [privacy.visibility]"));
+        assert!(diff.contains(&"  [privacy]"));
+        assert!(!with_suffix(&path, ".v0.bak").exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn diffs_lines() {
+        assert_eq!(
+            "  a\n- b\n+ x\n  c\n+ d\n",
+            line_diff("a\nb\nc\n", "a\nx\nc\nd\n")
+        );
+        assert_eq!("", line_diff("", ""));
+    }
+}
@@ -1,35 +1,60 @@
 extern crate proc_macro;
+extern crate proc_macro2;
 #[macro_use]
 extern crate quote;
+#[macro_use]
 extern crate syn;
 
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use syn::{Data, DeriveInput, Error, Fields, Lit, Meta, NestedMeta};
 
 // HelloWorld is the name for the derive
 // hello_world_name is the name of our optional attribute
 #[proc_macro_derive(HelloWorld, attributes(hello_world_name))]
 pub fn hello_world(input: TokenStream) -> TokenStream {
-    // Construct a string representation of the type definition
-    let s = input.to_string();
-    // Parse the string representation into an abstract syntax tree
-    let ast = syn::parse_derive_input(&s).expect("Failed to parse the source into an AST");
-
-    // Build the implementation
-    let gen = impl_hello_world(&ast);
+    // Parse the token stream into an abstract syntax tree
+    let ast = parse_macro_input!(input as DeriveInput);
 
-    // Return the generated implementation
-    gen.parse()
-        .expect("Failed to parse the AST generated from deriving from HelloWorld")
+    // Build the implementation. Instead of panicking, mistakes in the
+    // input are turned into compile_error! invocations, which the
+    // compiler reports at the span of the offending code
+    impl_hello_world(&ast)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
 }
 
-fn impl_hello_world(ast: &syn::DeriveInput) -> quote::Tokens {
+fn impl_hello_world(ast: &DeriveInput) -> syn::Result<Tokens> {
     let identifier = &ast.ident;
+    if let Data::Union(ref data) = ast.data {
+        return Err(Error::new_spanned(
+            data.union_token,
+            "HelloWorld cannot be derived for unions, only for structs and enums",
+        ));
+    }
+    // Lifetimes don't matter for the greeting, but type and const
+    // parameters would need bounds that we can't guess
+    if let Some(param) = ast
+        .generics
+        .params
+        .iter()
+        .find(|param| !matches!(param, syn::GenericParam::Lifetime(_)))
+    {
+        return Err(Error::new_spanned(
+            param,
+            format!(
+                "HelloWorld cannot be derived for generic types, implement it for {} by hand",
+                identifier
+            ),
+        ));
+    }
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
     // Use the name provided by the attribute
     // If there is no attribute, use the identifier
-    let hello_world_name = get_name_attribute(ast).unwrap_or_else(|| identifier.as_ref());
-    quote! {
+    let hello_world_name = get_name_attribute(ast)?.unwrap_or_else(|| identifier.to_string());
+    Ok(quote! {
         // Insert an implementation for our trait
-        impl HelloWorld for #identifier {
+        impl #impl_generics HelloWorld for #identifier #ty_generics #where_clause {
             fn hello_world() {
                 println!(
                     "The struct or enum {} says: \"Hello world from {}!\"",
@@ -38,32 +63,709 @@ fn impl_hello_world(ast: &syn::DeriveInput) -> quote::Tokens {
                 );
             }
         }
-    }
+    })
 }
 
-fn get_name_attribute(ast: &syn::DeriveInput) -> Option<&str> {
+// Accepts both #[hello_world_name = "Some value"] and #[hello_world_name("Some value")]
+fn get_name_attribute(ast: &DeriveInput) -> syn::Result<Option<String>> {
     const ATTR_NAME: &str = "hello_world_name";
+    let usage = format!(
+        "expected an attribute in the form #[{} = \"Some value\"] or #[{}(\"Some value\")]",
+        ATTR_NAME, ATTR_NAME
+    );
 
-    // Go through all attributes and find one with our name
-    if let Some(attr) = ast.attrs.iter().find(|a| a.name() == ATTR_NAME) {
-        // Check if it's in the form of a name-value pair
-        if let syn::MetaItem::NameValue(_, ref value) = attr.value {
-            // Check if the value is a string
-            if let syn::Lit::Str(ref value_as_str, _) = *value {
-                Some(value_as_str)
-            } else {
-                panic!(
-                    "Expected a string as the value of {}, found {:?} instead",
-                    ATTR_NAME, value
+    // Go through all attributes and find the ones with our name
+    let mut attrs = ast.attrs.iter().filter(|a| a.path.is_ident(ATTR_NAME));
+    let attr = match attrs.next() {
+        Some(attr) => attr,
+        None => return Ok(None),
+    };
+    if let Some(duplicate) = attrs.next() {
+        return Err(Error::new_spanned(
+            duplicate,
+            format!("#[{}] can only be used once", ATTR_NAME),
+        ));
+    }
+    let value = match attr
+        .parse_meta()
+        .map_err(|e| Error::new(e.span(), &usage))?
+    {
+        Meta::NameValue(ref pair) => pair.lit.clone(),
+        Meta::List(ref list) => match list.nested.iter().collect::<Vec<_>>()[..] {
+            [NestedMeta::Lit(ref lit)] => lit.clone(),
+            [] => return Err(Error::new_spanned(list, usage)),
+            [_, ref extra, ..] => {
+                return Err(Error::new_spanned(
+                    extra,
+                    format!("#[{}] takes a single name", ATTR_NAME),
+                ))
+            }
+            [ref other] => return Err(Error::new_spanned(other, usage)),
+        },
+        Meta::Path(ref path) => return Err(Error::new_spanned(path, usage)),
+    };
+    // Check if the value is a string
+    match value {
+        Lit::Str(ref value) => Ok(Some(value.value())),
+        ref other => Err(Error::new_spanned(
+            other,
+            format!("expected a string as the value of {}", ATTR_NAME),
+        )),
+    }
+}
+
+// Encode and Decode are the names for the binary serialization derives
+// endian is an optional attribute to override the byte order of a field
+#[proc_macro_derive(Encode, attributes(endian))]
+pub fn encode(input: TokenStream) -> TokenStream {
+    let mut ast = parse_macro_input!(input as DeriveInput);
+    // Every generic parameter has to be encodable itself
+    add_trait_bounds(&mut ast.generics, parse_quote!(Encode));
+
+    impl_encode(&ast)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(Decode, attributes(endian))]
+pub fn decode(input: TokenStream) -> TokenStream {
+    let mut ast = parse_macro_input!(input as DeriveInput);
+    add_trait_bounds(&mut ast.generics, parse_quote!(Decode));
+
+    impl_decode(&ast)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn add_trait_bounds(generics: &mut syn::Generics, bound: syn::TypeParamBound) {
+    for param in generics.type_params_mut() {
+        param.bounds.push(bound.clone());
+    }
+}
+
+fn reject_unions(ast: &DeriveInput, derive: &str) -> syn::Result<()> {
+    match ast.data {
+        Data::Union(ref data) => Err(Error::new_spanned(
+            data.union_token,
+            format!("{} cannot be derived for unions", derive),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn impl_encode(ast: &DeriveInput) -> syn::Result<Tokens> {
+    reject_unions(ast, "Encode")?;
+    let identifier = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let body = match ast.data {
+        Data::Struct(ref data) => {
+            // Bind all fields by reference and encode them in order
+            let pattern = destructure(&quote!(#identifier), &data.fields);
+            let fields = encode_fields(&data.fields)?;
+            quote! {
+                let #pattern = *self;
+                #(#fields)*
+            }
+        }
+        Data::Enum(ref data) => {
+            // Every variant is prefixed by its index as a u32
+            let arms = data
+                .variants
+                .iter()
+                .enumerate()
+                .map(|(index, variant)| {
+                    let variant_ident = &variant.ident;
+                    let tag = index as u32;
+                    let pattern =
+                        destructure(&quote!(#identifier::#variant_ident), &variant.fields);
+                    let fields = encode_fields(&variant.fields)?;
+                    Ok(quote! {
+                        #pattern => {
+                            Encode::encode::<__E, __W>(&#tag, writer)?;
+                            #(#fields)*
+                        }
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! {
+                match *self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => unreachable!("Unions are rejected above"),
+    };
+    Ok(quote! {
+        impl #impl_generics Encode for #identifier #ty_generics #where_clause {
+            fn encode<__E, __W>(&self, writer: &mut __W) -> ::std::io::Result<()>
+            where
+                __E: ::byteorder::ByteOrder,
+                __W: ::std::io::Write,
+            {
+                #body
+                Ok(())
+            }
+        }
+    })
+}
+
+fn impl_decode(ast: &DeriveInput) -> syn::Result<Tokens> {
+    reject_unions(ast, "Decode")?;
+    let identifier = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let body = match ast.data {
+        Data::Struct(ref data) => {
+            let construction = construct(&quote!(#identifier), &data.fields)?;
+            quote! {
+                Ok(#construction)
+            }
+        }
+        Data::Enum(ref data) => {
+            // Read the variant index first and then its fields
+            let arms = data
+                .variants
+                .iter()
+                .enumerate()
+                .map(|(index, variant)| {
+                    let variant_ident = &variant.ident;
+                    let tag = index as u32;
+                    let construction =
+                        construct(&quote!(#identifier::#variant_ident), &variant.fields)?;
+                    Ok(quote! {
+                        #tag => Ok(#construction),
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! {
+                let tag = <u32 as Decode>::decode::<__E>(input)?;
+                match tag {
+                    #(#arms)*
+                    _ => Err(DecodeError::UnknownVariant(stringify!(#identifier), tag)),
+                }
+            }
+        }
+        Data::Union(_) => unreachable!("Unions are rejected above"),
+    };
+    Ok(quote! {
+        impl #impl_generics Decode for #identifier #ty_generics #where_clause {
+            fn decode<__E>(input: &mut Input) -> Result<Self, DecodeError>
+            where
+                __E: ::byteorder::ByteOrder,
+            {
+                #body
+            }
+        }
+    })
+}
+
+// Creates a pattern like `Foo { a: ref field0, b: ref field1 }` or `Foo(ref field0, ref field1)`
+fn destructure(path: &Tokens, fields: &Fields) -> Tokens {
+    let bindings = field_bindings(fields);
+    match *fields {
+        Fields::Named(ref fields) => {
+            let names: Vec<_> = fields.named.iter().map(|field| &field.ident).collect();
+            quote!(#path { #(#names: ref #bindings),* })
+        }
+        Fields::Unnamed(_) => quote!(#path(#(ref #bindings),*)),
+        Fields::Unit => quote!(#path),
+    }
+}
+
+// Creates an expression that decodes every field in order
+fn construct(path: &Tokens, fields: &Fields) -> syn::Result<Tokens> {
+    let values = fields
+        .iter()
+        .map(|field| {
+            let ty = &field.ty;
+            let endian = field_endianness(field)?;
+            Ok(quote!(<#ty as Decode>::decode::<#endian>(input)?))
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    Ok(match *fields {
+        Fields::Named(ref fields) => {
+            let names: Vec<_> = fields.named.iter().map(|field| &field.ident).collect();
+            quote!(#path { #(#names: #values),* })
+        }
+        Fields::Unnamed(_) => quote!(#path(#(#values),*)),
+        Fields::Unit => quote!(#path),
+    })
+}
+
+fn encode_fields(fields: &Fields) -> syn::Result<Vec<Tokens>> {
+    fields
+        .iter()
+        .zip(field_bindings(fields))
+        .map(|(field, binding)| {
+            let endian = field_endianness(field)?;
+            Ok(quote! {
+                Encode::encode::<#endian, __W>(#binding, writer)?;
+            })
+        })
+        .collect()
+}
+
+fn field_bindings(fields: &Fields) -> Vec<syn::Ident> {
+    (0..fields.len())
+        .map(|index| format_ident!("field{}", index))
+        .collect()
+}
+
+// Fields use the byte order of their parent unless
+// they are annotated with #[endian(big)] or #[endian(little)]
+fn field_endianness(field: &syn::Field) -> syn::Result<Tokens> {
+    const ATTR_NAME: &str = "endian";
+
+    let attr = match field.attrs.iter().find(|a| a.path.is_ident(ATTR_NAME)) {
+        Some(attr) => attr,
+        None => return Ok(quote!(__E)),
+    };
+    if let Ok(Meta::List(ref list)) = attr.parse_meta() {
+        if let [NestedMeta::Meta(Meta::Path(ref path))] = list.nested.iter().collect::<Vec<_>>()[..]
+        {
+            if path.is_ident("big") {
+                return Ok(quote!(::byteorder::BigEndian));
+            } else if path.is_ident("little") {
+                return Ok(quote!(::byteorder::LittleEndian));
+            }
+        }
+    }
+    Err(Error::new_spanned(
+        attr,
+        format!(
+            "expected an attribute in the form #[{}(big)] or #[{}(little)]",
+            ATTR_NAME, ATTR_NAME
+        ),
+    ))
+}
+
+// JsonSchema generates a JSON Schema describing the JSON that serde produces for a type
+// schema is an optional field attribute that adds further constraints, e.g.
+// #[schema(minimum = 1, max_length = 20, pattern = "^[A-Z]")]
+#[proc_macro_derive(JsonSchema, attributes(schema))]
+pub fn json_schema(input: TokenStream) -> TokenStream {
+    let mut ast = parse_macro_input!(input as DeriveInput);
+    add_trait_bounds(&mut ast.generics, parse_quote!(JsonSchema));
+
+    impl_json_schema(&ast)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn impl_json_schema(ast: &DeriveInput) -> syn::Result<Tokens> {
+    reject_unions(ast, "JsonSchema")?;
+    let identifier = &ast.ident;
+    let title = identifier.to_string();
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let body = match ast.data {
+        // Structs are objects with one property per field
+        Data::Struct(syn::DataStruct {
+            fields: Fields::Named(ref fields),
+            ..
+        }) => {
+            let properties = fields
+                .named
+                .iter()
+                .map(|field| {
+                    let name = field
+                        .ident
+                        .as_ref()
+                        .expect("Named fields have a name")
+                        .to_string();
+                    let ty = &field.ty;
+                    let constraints = schema_constraints(field)?;
+                    Ok(quote! {
+                        let mut schema = <#ty as JsonSchema>::json_schema();
+                        if let Some(schema) = schema.as_object_mut() {
+                            #(#constraints)*
+                        }
+                        if <#ty as JsonSchema>::is_required() {
+                            required.push(::serde_json::Value::from(#name));
+                        }
+                        properties.insert(#name.to_string(), schema);
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! {
+                let mut properties = ::serde_json::Map::new();
+                let mut required = Vec::new();
+                #({ #properties })*
+                let mut schema = ::serde_json::Map::new();
+                schema.insert("title".to_string(), ::serde_json::Value::from(#title));
+                schema.insert("type".to_string(), ::serde_json::Value::from("object"));
+                schema.insert("properties".to_string(), ::serde_json::Value::Object(properties));
+                schema.insert("required".to_string(), ::serde_json::Value::Array(required));
+                schema.insert("additionalProperties".to_string(), ::serde_json::Value::Bool(false));
+                ::serde_json::Value::Object(schema)
+            }
+        }
+        // serde writes unit variants as their name
+        Data::Enum(ref data) => {
+            let names = data
+                .variants
+                .iter()
+                .map(|variant| match variant.fields {
+                    Fields::Unit => Ok(variant.ident.to_string()),
+                    ref fields => Err(Error::new_spanned(
+                        fields,
+                        format!(
+                            "JsonSchema only supports enums without fields, but {}::{} has fields",
+                            identifier, variant.ident
+                        ),
+                    )),
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! {
+                let mut schema = ::serde_json::Map::new();
+                schema.insert("title".to_string(), ::serde_json::Value::from(#title));
+                schema.insert("type".to_string(), ::serde_json::Value::from("string"));
+                schema.insert(
+                    "enum".to_string(),
+                    ::serde_json::Value::Array(vec![#(::serde_json::Value::from(#names)),*]),
                 );
+                ::serde_json::Value::Object(schema)
             }
-        } else {
-            panic!(
-                "Expected an attribute in the form #[{} = \"Some value\"]",
-                ATTR_NAME
-            );
         }
+        Data::Struct(_) => {
+            return Err(Error::new_spanned(
+                identifier,
+                format!(
+                    "JsonSchema can only be derived for structs with named fields, {} has none",
+                    identifier
+                ),
+            ))
+        }
+        Data::Union(_) => unreachable!("Unions are rejected above"),
+    };
+    Ok(quote! {
+        impl #impl_generics JsonSchema for #identifier #ty_generics #where_clause {
+            fn json_schema() -> ::serde_json::Value {
+                #body
+            }
+        }
+    })
+}
+
+// Turns #[schema(min_length = 1)] into code that inserts "minLength": 1 into the schema
+fn schema_constraints(field: &syn::Field) -> syn::Result<Vec<Tokens>> {
+    const ATTR_NAME: &str = "schema";
+    let usage = format!(
+        "expected constraints in the form #[{}(constraint = value)]",
+        ATTR_NAME
+    );
+
+    let mut constraints = Vec::new();
+    for attr in field.attrs.iter().filter(|a| a.path.is_ident(ATTR_NAME)) {
+        let list = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list,
+            _ => return Err(Error::new_spanned(attr, usage)),
+        };
+        for item in &list.nested {
+            let pair = match *item {
+                NestedMeta::Meta(Meta::NameValue(ref pair)) => pair,
+                ref other => return Err(Error::new_spanned(other, usage)),
+            };
+            let name = pair
+                .path
+                .get_ident()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            let keyword = match name.as_str() {
+                "minimum" => "minimum",
+                "maximum" => "maximum",
+                "min_length" => "minLength",
+                "max_length" => "maxLength",
+                "min_items" => "minItems",
+                "max_items" => "maxItems",
+                "pattern" => "pattern",
+                _ => {
+                    return Err(Error::new_spanned(
+                        &pair.path,
+                        format!("unknown {} constraint", ATTR_NAME),
+                    ))
+                }
+            };
+            let value = match pair.lit {
+                Lit::Int(_) | Lit::Float(_) | Lit::Str(_) => &pair.lit,
+                ref other => {
+                    return Err(Error::new_spanned(
+                        other,
+                        format!("expected a number or a string as the value of {}", name),
+                    ))
+                }
+            };
+            constraints.push(quote! {
+                schema.insert(#keyword.to_string(), ::serde_json::Value::from(#value));
+            });
+        }
+    }
+    Ok(constraints)
+}
+
+// EnumIter, EnumCount, FromStr and Display save the hand-written matches
+// that are usually needed to list, parse and print the variants of an enum.
+// variant is an optional attribute:
+// #[variant(rename = "...")] and #[variant(alias = "...")] on a variant
+// change its name and add other names it can be parsed from, while
+// #[variant(case_insensitive)] on the enum makes parsing ignore case
+#[proc_macro_derive(EnumIter, attributes(variant))]
+pub fn enum_iter(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    impl_enum_iter(&ast)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(EnumCount, attributes(variant))]
+pub fn enum_count(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    impl_enum_count(&ast)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(FromStr, attributes(variant))]
+pub fn from_str(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    impl_from_str(&ast)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(Display, attributes(variant))]
+pub fn display(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    impl_display(&ast)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn impl_enum_iter(ast: &DeriveInput) -> syn::Result<Tokens> {
+    let identifier = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let variants = unit_variants(ast, "EnumIter")?;
+    Ok(quote! {
+        impl #impl_generics EnumIter for #identifier #ty_generics #where_clause {
+            const VARIANTS: &'static [Self] = &[#(#identifier::#variants),*];
+        }
+    })
+}
+
+fn impl_enum_count(ast: &DeriveInput) -> syn::Result<Tokens> {
+    let identifier = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let count = enum_variants(ast, "EnumCount")?.len();
+    Ok(quote! {
+        impl #impl_generics EnumCount for #identifier #ty_generics #where_clause {
+            const COUNT: usize = #count;
+        }
+    })
+}
+
+fn impl_from_str(ast: &DeriveInput) -> syn::Result<Tokens> {
+    let identifier = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let case_insensitive = enum_options(ast)?;
+    // Only variants without fields can be created from a name alone
+    unit_variants(ast, "FromStr")?;
+
+    let mut arms = Vec::new();
+    let mut expected = Vec::new();
+    // Every name may only lead to one variant
+    let mut seen: Vec<String> = Vec::new();
+    for variant in enum_variants(ast, "FromStr")? {
+        let options = variant_options(variant)?;
+        expected.push(options.name.0.clone());
+        let mut patterns = Vec::new();
+        for &(ref name, span) in Some(&options.name).into_iter().chain(&options.aliases) {
+            let pattern = if case_insensitive {
+                name.to_lowercase()
+            } else {
+                name.clone()
+            };
+            if seen.contains(&pattern) {
+                return Err(Error::new(
+                    span,
+                    format!("the name \"{}\" is already used by another variant", name),
+                ));
+            }
+            seen.push(pattern.clone());
+            patterns.push(pattern);
+        }
+        let variant_ident = &variant.ident;
+        arms.push(quote! {
+            #(#patterns)|* => ::std::result::Result::Ok(#identifier::#variant_ident),
+        });
+    }
+    let expected = expected.join(", ");
+    let input = if case_insensitive {
+        quote!(&*s.to_lowercase())
     } else {
-        None
+        quote!(s)
+    };
+    Ok(quote! {
+        impl #impl_generics ::std::str::FromStr for #identifier #ty_generics #where_clause {
+            // The error lists all valid names, so that it can be shown to users as is
+            type Err = String;
+
+            fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+                match #input {
+                    #(#arms)*
+                    _ => ::std::result::Result::Err(format!(
+                        "Unknown {} \"{}\", expected one of: {}",
+                        stringify!(#identifier),
+                        s,
+                        #expected
+                    )),
+                }
+            }
+        }
+    })
+}
+
+fn impl_display(ast: &DeriveInput) -> syn::Result<Tokens> {
+    let identifier = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    enum_options(ast)?;
+    let arms = enum_variants(ast, "Display")?
+        .iter()
+        .map(|variant| {
+            let variant_ident = &variant.ident;
+            let name = variant_options(variant)?.name.0;
+            // Variants with fields are printed by their name as well
+            Ok(quote!(#identifier::#variant_ident { .. } => #name,))
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    Ok(quote! {
+        impl #impl_generics ::std::fmt::Display for #identifier #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                // pad respects formatting options like {:>10}
+                f.pad(match *self {
+                    #(#arms)*
+                })
+            }
+        }
+    })
+}
+
+fn enum_variants<'a>(
+    ast: &'a DeriveInput,
+    derive: &str,
+) -> syn::Result<&'a syn::punctuated::Punctuated<syn::Variant, Token![,]>> {
+    let message = format!("{} can only be derived for enums", derive);
+    match ast.data {
+        Data::Enum(ref data) => Ok(&data.variants),
+        Data::Struct(ref data) => Err(Error::new_spanned(data.struct_token, message)),
+        Data::Union(ref data) => Err(Error::new_spanned(data.union_token, message)),
+    }
+}
+
+fn unit_variants<'a>(ast: &'a DeriveInput, derive: &str) -> syn::Result<Vec<&'a syn::Ident>> {
+    enum_variants(ast, derive)?
+        .iter()
+        .map(|variant| match variant.fields {
+            Fields::Unit => Ok(&variant.ident),
+            ref fields => Err(Error::new_spanned(
+                fields,
+                format!("{} only supports variants without fields", derive),
+            )),
+        })
+        .collect()
+}
+
+struct VariantOptions {
+    // The name used for printing, with the span to report problems at
+    name: (String, proc_macro2::Span),
+    // Further names that are accepted when parsing
+    aliases: Vec<(String, proc_macro2::Span)>,
+}
+
+const VARIANT_ATTR: &str = "variant";
+
+// All items of all #[variant(...)] attributes
+fn variant_attribute_items(attrs: &[syn::Attribute]) -> syn::Result<Vec<NestedMeta>> {
+    let mut items = Vec::new();
+    for attr in attrs.iter().filter(|a| a.path.is_ident(VARIANT_ATTR)) {
+        match attr.parse_meta()? {
+            Meta::List(list) => items.extend(list.nested),
+            _ => {
+                return Err(Error::new_spanned(
+                    attr,
+                    format!(
+                        "expected an attribute in the form #[{}(rename = \"...\")]",
+                        VARIANT_ATTR
+                    ),
+                ))
+            }
+        }
+    }
+    Ok(items)
+}
+
+// Returns whether parsing is case-insensitive
+fn enum_options(ast: &DeriveInput) -> syn::Result<bool> {
+    let mut case_insensitive = false;
+    for item in variant_attribute_items(&ast.attrs)? {
+        match item {
+            NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("case_insensitive") => {
+                case_insensitive = true
+            }
+            ref other => {
+                return Err(Error::new_spanned(
+                    other,
+                    format!(
+                        "only #[{}(case_insensitive)] can be used on the enum itself",
+                        VARIANT_ATTR
+                    ),
+                ))
+            }
+        }
+    }
+    Ok(case_insensitive)
+}
+
+fn variant_options(variant: &syn::Variant) -> syn::Result<VariantOptions> {
+    let mut rename: Option<syn::LitStr> = None;
+    let mut aliases = Vec::new();
+    for item in variant_attribute_items(&variant.attrs)? {
+        let pair = match item {
+            NestedMeta::Meta(Meta::NameValue(pair)) => pair,
+            NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("case_insensitive") => {
+                return Err(Error::new_spanned(
+                    path,
+                    "case_insensitive applies to all variants and has to be used on the enum",
+                ))
+            }
+            ref other => {
+                return Err(Error::new_spanned(
+                    other,
+                    "expected rename = \"...\" or alias = \"...\"",
+                ))
+            }
+        };
+        let value = match pair.lit {
+            Lit::Str(ref value) => value.clone(),
+            ref other => return Err(Error::new_spanned(other, "expected a string")),
+        };
+        if pair.path.is_ident("rename") {
+            if rename.is_some() {
+                return Err(Error::new_spanned(
+                    &pair,
+                    "a variant can only be renamed once, use alias for further names",
+                ));
+            }
+            rename = Some(value);
+        } else if pair.path.is_ident("alias") {
+            aliases.push((value.value(), value.span()));
+        } else {
+            return Err(Error::new_spanned(
+                &pair.path,
+                format!("unknown {} option, expected rename or alias", VARIANT_ATTR),
+            ));
+        }
     }
+    let name = match rename {
+        Some(rename) => (rename.value(), rename.span()),
+        None => (variant.ident.to_string(), variant.ident.span()),
+    };
+    Ok(VariantOptions { name, aliases })
 }
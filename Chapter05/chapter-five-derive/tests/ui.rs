@@ -0,0 +1,11 @@
+extern crate trybuild;
+
+// Every file in tests/ui is compiled on its own. The ones that should
+// fail have a .stderr file next to them with the expected diagnostics.
+// Run with TRYBUILD=overwrite to update them after changing a message
+#[test]
+fn ui() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/hello_world_name_forms.rs");
+    cases.compile_fail("tests/ui/*_error.rs");
+}
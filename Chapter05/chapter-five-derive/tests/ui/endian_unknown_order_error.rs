@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+#[derive(Encode)]
+struct Header {
+    #[endian(middle)]
+    length: u32,
+}
+
+fn main() {}
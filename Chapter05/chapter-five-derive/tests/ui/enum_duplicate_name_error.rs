@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+#[derive(FromStr)]
+#[variant(case_insensitive)]
+enum Species {
+    Dog,
+    #[variant(alias = "DOG")]
+    Wolf,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+#[derive(FromStr)]
+enum Shape {
+    Point,
+    Circle(f64),
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+trait EnumIter: Sized + 'static {
+    const VARIANTS: &'static [Self];
+}
+
+#[derive(EnumIter)]
+struct Species;
+
+fn main() {}
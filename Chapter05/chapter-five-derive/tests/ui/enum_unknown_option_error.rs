@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+#[derive(Display)]
+enum Species {
+    Dog,
+    #[variant(nickname = "Kitty")]
+    Cat,
+}
+
+fn main() {}
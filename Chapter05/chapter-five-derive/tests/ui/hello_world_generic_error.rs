@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+trait HelloWorld {
+    fn hello_world();
+}
+
+#[derive(HelloWorld)]
+struct Wrapper<'a, T> {
+    value: &'a T,
+}
+
+fn main() {}
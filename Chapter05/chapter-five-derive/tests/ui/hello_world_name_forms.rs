@@ -0,0 +1,26 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+trait HelloWorld {
+    fn hello_world();
+}
+
+#[derive(HelloWorld)]
+#[hello_world_name = "the Land Down Under"]
+struct Australia;
+
+#[derive(HelloWorld)]
+#[hello_world_name("the Land of the Rising Sun")]
+enum Japan {}
+
+// Lifetimes are fine, as they don't change the greeting
+#[derive(HelloWorld)]
+struct Borrowed<'a> {
+    _name: &'a str,
+}
+
+fn main() {
+    Australia::hello_world();
+    Japan::hello_world();
+    Borrowed::hello_world();
+}
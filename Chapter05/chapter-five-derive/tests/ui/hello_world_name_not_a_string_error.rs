@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+trait HelloWorld {
+    fn hello_world();
+}
+
+#[derive(HelloWorld)]
+#[hello_world_name = 42]
+struct Answer;
+
+fn main() {}
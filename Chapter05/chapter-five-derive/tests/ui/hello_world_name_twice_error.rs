@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+trait HelloWorld {
+    fn hello_world();
+}
+
+#[derive(HelloWorld)]
+#[hello_world_name = "Britain"]
+#[hello_world_name = "the United Kingdom"]
+struct Britain;
+
+fn main() {}
@@ -0,0 +1,14 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+trait HelloWorld {
+    fn hello_world();
+}
+
+#[derive(HelloWorld)]
+union Bits {
+    signed: i32,
+    unsigned: u32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+#[derive(JsonSchema)]
+enum Shape {
+    Point,
+    Circle { radius: f64 },
+}
+
+fn main() {}
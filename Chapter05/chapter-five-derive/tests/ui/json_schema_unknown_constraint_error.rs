@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+#[derive(JsonSchema)]
+struct Pet {
+    #[schema(min_lenght = 1)]
+    name: String,
+}
+
+fn main() {}
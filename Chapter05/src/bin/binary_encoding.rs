@@ -0,0 +1,716 @@
+extern crate byteorder;
+#[macro_use]
+extern crate chapter_five_derive;
+
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::convert::TryInto;
+use std::io::{self, Write};
+use std::{error, fmt, mem, str};
+
+// trait definitions have to be in "consumer" crate
+trait Encode {
+    // Write self in the byte order E
+    fn encode<E, W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        E: ByteOrder,
+        W: Write;
+}
+
+trait Decode: Sized {
+    // Read an instance of Self in the byte order E
+    fn decode<E>(input: &mut Input) -> Result<Self, DecodeError>
+    where
+        E: ByteOrder;
+}
+
+#[derive(Debug, PartialEq)]
+enum DecodeError {
+    // The input ended before the value was complete
+    UnexpectedEnd { needed: usize, remaining: usize },
+    // The input contained more bytes than the value
+    TrailingBytes(usize),
+    InvalidBool(u8),
+    InvalidPresenceByte(u8),
+    InvalidUtf8(str::Utf8Error),
+    // The name of the enum and the unknown variant index
+    UnknownVariant(&'static str, u32),
+    // A collection of elements that take up no bytes
+    // was longer than MAX_EMPTY_ELEMENTS
+    TooManyEmptyElements(usize),
+}
+
+impl error::Error for DecodeError {}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnexpectedEnd { needed, remaining } => write!(
+                f,
+                "Input ended unexpectedly: needed {} bytes, but only {} remain",
+                needed, remaining
+            ),
+            DecodeError::TrailingBytes(len) => {
+                write!(f, "Found {} unexpected bytes after the value", len)
+            }
+            DecodeError::InvalidBool(byte) => write!(f, "Invalid bool: {}", byte),
+            DecodeError::InvalidPresenceByte(byte) => {
+                write!(f, "Invalid presence byte for an Option: {}", byte)
+            }
+            DecodeError::InvalidUtf8(ref err) => write!(f, "Invalid string: {}", err),
+            DecodeError::UnknownVariant(name, tag) => {
+                write!(f, "Unknown variant index {} for enum {}", tag, name)
+            }
+            DecodeError::TooManyEmptyElements(len) => write!(
+                f,
+                "Collection of {} elements without any content is too long",
+                len
+            ),
+        }
+    }
+}
+
+// A view into the bytes that are left to decode.
+// Every read is checked against the remaining length
+struct Input<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Input { bytes }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    // Split off the next len bytes
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.bytes.len() {
+            return Err(DecodeError::UnexpectedEnd {
+                needed: len,
+                remaining: self.bytes.len(),
+            });
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+}
+
+// Encode a value in little endian, which is our default byte order
+fn encode_to_vec<T: Encode>(value: &T) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    value
+        .encode::<LittleEndian, _>(&mut bytes)
+        .expect("Writing to a Vec cannot fail");
+    bytes
+}
+
+// Decode a value in little endian that has to span the whole input
+fn decode_from_slice<T: Decode>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let mut input = Input::new(bytes);
+    let value = T::decode::<LittleEndian>(&mut input)?;
+    match input.remaining() {
+        0 => Ok(value),
+        len => Err(DecodeError::TrailingBytes(len)),
+    }
+}
+
+// Implement both traits for all numbers that byteorder supports
+macro_rules! impl_for_number {
+    ($($ty:ty, $size:expr, $read:ident, $write:ident;)*) => {
+        $(
+            impl Encode for $ty {
+                fn encode<E, W>(&self, writer: &mut W) -> io::Result<()>
+                where
+                    E: ByteOrder,
+                    W: Write,
+                {
+                    writer.$write::<E>(*self)
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode<E>(input: &mut Input) -> Result<Self, DecodeError>
+                where
+                    E: ByteOrder,
+                {
+                    let mut bytes = input.take($size)?;
+                    Ok(bytes.$read::<E>().expect("The length was checked before"))
+                }
+            }
+        )*
+    };
+}
+
+impl_for_number! {
+    u16, 2, read_u16, write_u16;
+    u32, 4, read_u32, write_u32;
+    u64, 8, read_u64, write_u64;
+    i16, 2, read_i16, write_i16;
+    i32, 4, read_i32, write_i32;
+    i64, 8, read_i64, write_i64;
+    f32, 4, read_f32, write_f32;
+    f64, 8, read_f64, write_f64;
+}
+
+// Single bytes have no byte order
+impl Encode for u8 {
+    fn encode<E, W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        E: ByteOrder,
+        W: Write,
+    {
+        writer.write_u8(*self)
+    }
+}
+
+impl Decode for u8 {
+    fn decode<E>(input: &mut Input) -> Result<Self, DecodeError>
+    where
+        E: ByteOrder,
+    {
+        Ok(input.take(1)?[0])
+    }
+}
+
+impl Encode for i8 {
+    fn encode<E, W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        E: ByteOrder,
+        W: Write,
+    {
+        writer.write_i8(*self)
+    }
+}
+
+impl Decode for i8 {
+    fn decode<E>(input: &mut Input) -> Result<Self, DecodeError>
+    where
+        E: ByteOrder,
+    {
+        Ok(input.take(1)?[0] as i8)
+    }
+}
+
+impl Encode for bool {
+    fn encode<E, W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        E: ByteOrder,
+        W: Write,
+    {
+        writer.write_u8(*self as u8)
+    }
+}
+
+impl Decode for bool {
+    fn decode<E>(input: &mut Input) -> Result<Self, DecodeError>
+    where
+        E: ByteOrder,
+    {
+        match u8::decode::<E>(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(DecodeError::InvalidBool(byte)),
+        }
+    }
+}
+
+// Fixed-size arrays are written without a length
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode<E, W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        E: ByteOrder,
+        W: Write,
+    {
+        for element in self {
+            element.encode::<E, W>(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode<E>(input: &mut Input) -> Result<Self, DecodeError>
+    where
+        E: ByteOrder,
+    {
+        let mut elements = Vec::with_capacity(N);
+        for _ in 0..N {
+            elements.push(T::decode::<E>(input)?);
+        }
+        Ok(elements
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("The Vec has exactly N elements")))
+    }
+}
+
+// Collections are prefixed with their length as a u32
+fn encode_len<E, W>(len: usize, writer: &mut W) -> io::Result<()>
+where
+    E: ByteOrder,
+    W: Write,
+{
+    if len > u32::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Collection is too long to be encoded",
+        ));
+    }
+    (len as u32).encode::<E, W>(writer)
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<E, W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        E: ByteOrder,
+        W: Write,
+    {
+        encode_len::<E, W>(self.len(), writer)?;
+        for element in self {
+            element.encode::<E, W>(writer)?;
+        }
+        Ok(())
+    }
+}
+
+// Elements that take up no bytes, like unit structs, can't be checked
+// against the length of the input, so their number is limited instead
+const MAX_EMPTY_ELEMENTS: usize = 1 << 16;
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode<E>(input: &mut Input) -> Result<Self, DecodeError>
+    where
+        E: ByteOrder,
+    {
+        let len = u32::decode::<E>(input)? as usize;
+        // Don't trust the length prefix when allocating, as it could be
+        // far larger than the actual input. Whatever it says, we never
+        // reserve more memory than the rest of the input takes up
+        let capacity = input.remaining() / mem::size_of::<T>().max(1);
+        let mut elements = Vec::with_capacity(len.min(capacity));
+        for _ in 0..len {
+            let remaining = input.remaining();
+            elements.push(T::decode::<E>(input)?);
+            if input.remaining() == remaining && len > MAX_EMPTY_ELEMENTS {
+                return Err(DecodeError::TooManyEmptyElements(len));
+            }
+        }
+        Ok(elements)
+    }
+}
+
+impl Encode for String {
+    fn encode<E, W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        E: ByteOrder,
+        W: Write,
+    {
+        encode_len::<E, W>(self.len(), writer)?;
+        writer.write_all(self.as_bytes())
+    }
+}
+
+impl Decode for String {
+    fn decode<E>(input: &mut Input) -> Result<Self, DecodeError>
+    where
+        E: ByteOrder,
+    {
+        let len = u32::decode::<E>(input)? as usize;
+        let bytes = input.take(len)?;
+        str::from_utf8(bytes)
+            .map(str::to_string)
+            .map_err(DecodeError::InvalidUtf8)
+    }
+}
+
+// Options are prefixed with a byte that tells if a value follows
+impl<T: Encode> Encode for Option<T> {
+    fn encode<E, W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        E: ByteOrder,
+        W: Write,
+    {
+        match *self {
+            Some(ref value) => {
+                writer.write_u8(1)?;
+                value.encode::<E, W>(writer)
+            }
+            None => writer.write_u8(0),
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode<E>(input: &mut Input) -> Result<Self, DecodeError>
+    where
+        E: ByteOrder,
+    {
+        match u8::decode::<E>(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode::<E>(input)?)),
+            byte => Err(DecodeError::InvalidPresenceByte(byte)),
+        }
+    }
+}
+
+// Thanks to the derives we don't have to call
+// read_u32 or write_f32 for every single field anymore
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct Header {
+    magic: [u8; 4],
+    // Network protocols often use big endian
+    #[endian(big)]
+    version: u16,
+    entries: Vec<Entry>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct Entry {
+    name: String,
+    value: Value,
+    comment: Option<String>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+enum Value {
+    Missing,
+    Number(f32),
+    Range { from: i32, to: i32 },
+}
+
+fn main() {
+    let header = Header {
+        magic: *b"RSLC",
+        version: 2,
+        entries: vec![
+            Entry {
+                name: "temperature".to_string(),
+                value: Value::Number(-33.4),
+                comment: Some("in degrees celsius".to_string()),
+            },
+            Entry {
+                name: "range".to_string(),
+                value: Value::Range { from: -1, to: 1 },
+                comment: None,
+            },
+            Entry {
+                name: "nothing".to_string(),
+                value: Value::Missing,
+                comment: None,
+            },
+        ],
+    };
+
+    let bytes = encode_to_vec(&header);
+    println!("Encoded {} bytes: {:?}", bytes.len(), bytes);
+
+    let decoded: Header = decode_from_slice(&bytes).expect("Failed to decode header");
+    println!("Decoded: {:?}", decoded);
+    assert_eq!(header, decoded);
+
+    // Decoding never reads past the end of the input
+    let truncated = &bytes[..bytes.len() - 1];
+    match decode_from_slice::<Header>(truncated) {
+        Ok(_) => println!("Decoded a truncated header?!"),
+        Err(err) => println!("Failed to decode truncated header: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::BigEndian;
+
+    // A tiny xorshift generator, so we can create
+    // lots of random values without extra dependencies
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, max: u64) -> u64 {
+            self.next() % max
+        }
+    }
+
+    trait Arbitrary {
+        fn arbitrary(rng: &mut Rng) -> Self;
+    }
+
+    macro_rules! arbitrary_number {
+        ($($ty:ty)*) => {
+            $(
+                impl Arbitrary for $ty {
+                    fn arbitrary(rng: &mut Rng) -> Self {
+                        rng.next() as $ty
+                    }
+                }
+            )*
+        };
+    }
+
+    arbitrary_number!(u8 u16 u32 u64 i8 i16 i32 i64);
+
+    // Floats are generated from integers so that they are
+    // never NaN, which would not be equal to itself
+    impl Arbitrary for f32 {
+        fn arbitrary(rng: &mut Rng) -> Self {
+            rng.next() as i32 as f32 / 7.0
+        }
+    }
+
+    impl Arbitrary for f64 {
+        fn arbitrary(rng: &mut Rng) -> Self {
+            rng.next() as i64 as f64 / 13.0
+        }
+    }
+
+    impl Arbitrary for bool {
+        fn arbitrary(rng: &mut Rng) -> Self {
+            rng.below(2) == 1
+        }
+    }
+
+    impl Arbitrary for String {
+        fn arbitrary(rng: &mut Rng) -> Self {
+            let len = rng.below(8);
+            (0..len)
+                .map(|_| ['a', 'Z', '7', ' ', 'ä', '🦀'][rng.below(6) as usize])
+                .collect()
+        }
+    }
+
+    impl<T: Arbitrary> Arbitrary for Vec<T> {
+        fn arbitrary(rng: &mut Rng) -> Self {
+            let len = rng.below(5);
+            (0..len).map(|_| T::arbitrary(rng)).collect()
+        }
+    }
+
+    impl<T: Arbitrary> Arbitrary for Option<T> {
+        fn arbitrary(rng: &mut Rng) -> Self {
+            if bool::arbitrary(rng) {
+                Some(T::arbitrary(rng))
+            } else {
+                None
+            }
+        }
+    }
+
+    impl<T: Arbitrary> Arbitrary for [T; 3] {
+        fn arbitrary(rng: &mut Rng) -> Self {
+            [T::arbitrary(rng), T::arbitrary(rng), T::arbitrary(rng)]
+        }
+    }
+
+    #[derive(Encode, Decode, Debug, PartialEq)]
+    struct AllKinds {
+        byte: u8,
+        signed_byte: i8,
+        short: i16,
+        #[endian(big)]
+        big_int: u32,
+        #[endian(little)]
+        little_long: i64,
+        unsigned_long: u64,
+        float: f32,
+        double: f64,
+        flag: bool,
+        array: [u16; 3],
+        list: Vec<u32>,
+        text: String,
+        maybe_number: Option<i32>,
+        maybe_text: Option<String>,
+        nested: Vec<Option<Shape>>,
+        pair: Pair,
+        unit: Unit,
+        wrapper: Wrapper<Shape>,
+    }
+
+    #[derive(Encode, Decode, Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Rectangle {
+            #[endian(big)]
+            width: u32,
+            height: u32,
+        },
+    }
+
+    #[derive(Encode, Decode, Debug, PartialEq)]
+    struct Pair(u8, #[endian(big)] u64);
+
+    #[derive(Encode, Decode, Debug, PartialEq)]
+    struct Unit;
+
+    #[derive(Encode, Decode, Debug, PartialEq)]
+    struct Wrapper<T> {
+        inner: T,
+    }
+
+    impl Arbitrary for Shape {
+        fn arbitrary(rng: &mut Rng) -> Self {
+            match rng.below(3) {
+                0 => Shape::Empty,
+                1 => Shape::Circle(f64::arbitrary(rng)),
+                _ => Shape::Rectangle {
+                    width: u32::arbitrary(rng),
+                    height: u32::arbitrary(rng),
+                },
+            }
+        }
+    }
+
+    impl Arbitrary for AllKinds {
+        fn arbitrary(rng: &mut Rng) -> Self {
+            AllKinds {
+                byte: Arbitrary::arbitrary(rng),
+                signed_byte: Arbitrary::arbitrary(rng),
+                short: Arbitrary::arbitrary(rng),
+                big_int: Arbitrary::arbitrary(rng),
+                little_long: Arbitrary::arbitrary(rng),
+                unsigned_long: Arbitrary::arbitrary(rng),
+                float: Arbitrary::arbitrary(rng),
+                double: Arbitrary::arbitrary(rng),
+                flag: Arbitrary::arbitrary(rng),
+                array: Arbitrary::arbitrary(rng),
+                list: Arbitrary::arbitrary(rng),
+                text: Arbitrary::arbitrary(rng),
+                maybe_number: Arbitrary::arbitrary(rng),
+                maybe_text: Arbitrary::arbitrary(rng),
+                nested: Arbitrary::arbitrary(rng),
+                pair: Pair(Arbitrary::arbitrary(rng), Arbitrary::arbitrary(rng)),
+                unit: Unit,
+                wrapper: Wrapper {
+                    inner: Arbitrary::arbitrary(rng),
+                },
+            }
+        }
+    }
+
+    fn round_trip<T>(value: &T)
+    where
+        T: Encode + Decode + PartialEq + fmt::Debug,
+    {
+        let little = encode_to_vec(value);
+        assert_eq!(Ok(value), decode_from_slice::<T>(&little).as_ref());
+
+        let mut big = Vec::new();
+        value.encode::<BigEndian, _>(&mut big).unwrap();
+        let mut input = Input::new(&big);
+        assert_eq!(value, &T::decode::<BigEndian>(&mut input).unwrap());
+        assert_eq!(0, input.remaining());
+    }
+
+    #[test]
+    fn round_trips_random_values() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        for _ in 0..500 {
+            round_trip(&AllKinds::arbitrary(&mut rng));
+            round_trip(&Shape::arbitrary(&mut rng));
+            round_trip(&<Vec<Option<String>>>::arbitrary(&mut rng));
+            round_trip(&<[i64; 3]>::arbitrary(&mut rng));
+        }
+    }
+
+    #[test]
+    fn rejects_every_truncation() {
+        let mut rng = Rng(42);
+        for _ in 0..20 {
+            let bytes = encode_to_vec(&AllKinds::arbitrary(&mut rng));
+            for len in 0..bytes.len() {
+                match decode_from_slice::<AllKinds>(&bytes[..len]) {
+                    Err(DecodeError::UnexpectedEnd { .. }) => {}
+                    other => panic!("Decoded {} of {} bytes: {:?}", len, bytes.len(), other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn respects_field_endianness() {
+        let bytes = encode_to_vec(&Pair(1, 2));
+        assert_eq!(vec![1, 0, 0, 0, 0, 0, 0, 0, 2], bytes);
+
+        let shape = Shape::Rectangle {
+            width: 1,
+            height: 2,
+        };
+        assert_eq!(
+            vec![2, 0, 0, 0, 0, 0, 0, 1, 2, 0, 0, 0],
+            encode_to_vec(&shape)
+        );
+    }
+
+    #[test]
+    fn encodes_collections_with_prefixes() {
+        assert_eq!(
+            vec![2, 0, 0, 0, b'h', b'i'],
+            encode_to_vec(&"hi".to_string())
+        );
+        assert_eq!(vec![1, 0, 0, 0, 7], encode_to_vec(&vec![7u8]));
+        assert_eq!(vec![1, 9], encode_to_vec(&Some(9u8)));
+        assert_eq!(vec![0], encode_to_vec(&None::<u8>));
+        assert_eq!(vec![1, 2, 3], encode_to_vec(&[1u8, 2, 3]));
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert_eq!(
+            Err(DecodeError::UnknownVariant("Shape", 3)),
+            decode_from_slice::<Shape>(&[3, 0, 0, 0])
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidBool(2)),
+            decode_from_slice::<bool>(&[2])
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidPresenceByte(5)),
+            decode_from_slice::<Option<u8>>(&[5, 1])
+        );
+        assert_eq!(
+            Err(DecodeError::TrailingBytes(1)),
+            decode_from_slice::<u16>(&[1, 2, 3])
+        );
+        match decode_from_slice::<String>(&[2, 0, 0, 0, 0xC3, 0x28]) {
+            Err(DecodeError::InvalidUtf8(_)) => {}
+            other => panic!("Decoded invalid UTF-8: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_length_prefix() {
+        // A huge length must not make us allocate or read past the end
+        let bytes = [0xFF, 0xFF, 0xFF, 0xFF, 1, 2];
+        assert_eq!(
+            Err(DecodeError::UnexpectedEnd {
+                needed: 8,
+                remaining: 2,
+            }),
+            decode_from_slice::<Vec<u64>>(&bytes)
+        );
+        assert_eq!(
+            Err(DecodeError::UnexpectedEnd {
+                needed: u32::MAX as usize,
+                remaining: 2,
+            }),
+            decode_from_slice::<String>(&bytes)
+        );
+        assert_eq!(
+            Err(DecodeError::TooManyEmptyElements(u32::MAX as usize)),
+            decode_from_slice::<Vec<Unit>>(&bytes[..4])
+        );
+        assert_eq!(
+            Ok(vec![Unit, Unit, Unit]),
+            decode_from_slice::<Vec<Unit>>(&[3, 0, 0, 0])
+        );
+    }
+}
@@ -1,7 +1,18 @@
 #[macro_use]
 extern crate bitflags;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+use std::str::FromStr;
+use std::{error, fmt};
 
 bitflags! {
+    // The default is no spices at all
+    #[derive(Default)]
     struct Spices: u32 {
         const SALT       = 0b0000_0001;
         const PEPPER     = 0b0000_0010;
@@ -22,6 +33,204 @@ impl Spices {
     }
 }
 
+// bitflags can't list the names of its flags, so we keep our own table.
+// ALL isn't part of it, as printing single flags keeps the output
+// stable if a new spice is added later on
+const NAMED_SPICES: &[(&str, Spices)] = &[
+    ("SALT", Spices::SALT),
+    ("PEPPER", Spices::PEPPER),
+    ("CHILI", Spices::CHILI),
+    ("SAFFRON", Spices::SAFFRON),
+];
+
+// How to treat names and bits that don't belong to any flag
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    // Reject them with an error
+    Strict,
+    // Ignore them, like from_bits_truncate does
+    Lenient,
+}
+
+impl Spices {
+    // Accepts names separated by '|' in any case, like "Salt | PEPPER",
+    // as well as ALL, NONE, an empty string and raw bits like 0x3
+    fn parse(s: &str, mode: Mode) -> Result<Spices, ParseSpicesError> {
+        let mut spices = Spices::empty();
+        if s.trim().is_empty() {
+            return Ok(spices);
+        }
+        for name in s.split('|').map(str::trim) {
+            match Spices::from_name(name) {
+                Some(flags) => spices |= flags,
+                None => {
+                    if let Some(bits) = name
+                        .strip_prefix("0x")
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    {
+                        spices |= Spices::from_raw_bits(bits, mode)?;
+                    } else if mode == Mode::Strict {
+                        return Err(ParseSpicesError::UnknownName(name.to_string()));
+                    }
+                }
+            }
+        }
+        Ok(spices)
+    }
+
+    fn from_name(name: &str) -> Option<Spices> {
+        match name.to_uppercase().as_str() {
+            "ALL" => Some(Spices::ALL),
+            "NONE" => Some(Spices::empty()),
+            upper => NAMED_SPICES
+                .iter()
+                .find(|&&(other, _)| other == upper)
+                .map(|&(_, flag)| flag),
+        }
+    }
+
+    fn from_raw_bits(bits: u32, mode: Mode) -> Result<Spices, ParseSpicesError> {
+        match mode {
+            Mode::Strict => Spices::from_bits(bits).ok_or(ParseSpicesError::UnknownBits(bits)),
+            Mode::Lenient => Ok(Spices::from_bits_truncate(bits)),
+        }
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        NAMED_SPICES
+            .iter()
+            .filter(|&&(_, flag)| self.contains(flag))
+            .map(|&(name, _)| name)
+            .collect()
+    }
+}
+
+// Prints "SALT | PEPPER", or NONE if no flag is set
+impl fmt::Display for Spices {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            f.write_str("NONE")
+        } else {
+            f.write_str(&self.names().join(" | "))
+        }
+    }
+}
+
+impl FromStr for Spices {
+    type Err = ParseSpicesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Spices::parse(s, Mode::Strict)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ParseSpicesError {
+    UnknownName(String),
+    UnknownBits(u32),
+}
+
+impl fmt::Display for ParseSpicesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseSpicesError::UnknownName(ref name) => {
+                let names: Vec<_> = NAMED_SPICES.iter().map(|&(name, _)| name).collect();
+                write!(
+                    f,
+                    "Unknown spice \"{}\", expected one of {}, ALL or NONE",
+                    name,
+                    names.join(", ")
+                )
+            }
+            ParseSpicesError::UnknownBits(bits) => write!(
+                f,
+                "The bits {:#x} don't belong to any spice",
+                bits & !Spices::ALL.bits()
+            ),
+        }
+    }
+}
+
+impl error::Error for ParseSpicesError {}
+
+// Spices are written as a list of names, like ["SALT", "PEPPER"]
+impl Serialize for Spices {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let names = self.names();
+        let mut seq = serializer.serialize_seq(Some(names.len()))?;
+        for name in names {
+            seq.serialize_element(name)?;
+        }
+        seq.end()
+    }
+}
+
+// Reading accepts a list of names, a string like "SALT | PEPPER" or raw bits
+impl<'de> Deserialize<'de> for Spices {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(SpicesVisitor(Mode::Strict))
+    }
+}
+
+struct SpicesVisitor(Mode);
+
+impl<'de> Visitor<'de> for SpicesVisitor {
+    type Value = Spices;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of spices, a string like \"SALT | PEPPER\" or bits")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Spices, E> {
+        Spices::parse(s, self.0).map_err(E::custom)
+    }
+
+    fn visit_u64<E: de::Error>(self, bits: u64) -> Result<Spices, E> {
+        if bits > u64::from(u32::MAX) {
+            return Err(E::custom(format!("{} is too big to be spices", bits)));
+        }
+        Spices::from_raw_bits(bits as u32, self.0).map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Spices, A::Error> {
+        let mut spices = Spices::empty();
+        while let Some(name) = seq.next_element::<String>()? {
+            spices |= Spices::parse(&name, self.0).map_err(de::Error::custom)?;
+        }
+        Ok(spices)
+    }
+}
+
+// Use #[serde(with = "spices_as_bits")] to store the raw bits instead of names
+mod spices_as_bits {
+    use super::{Mode, Spices, SpicesVisitor};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(spices: &Spices, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(spices.bits())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Spices, D::Error> {
+        deserializer.deserialize_u32(SpicesVisitor(Mode::Strict))
+    }
+}
+
+// Use #[serde(deserialize_with = "lenient_spices")] to ignore unknown
+// names and bits, e.g. when reading files written by a newer version
+fn lenient_spices<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Spices, D::Error> {
+    deserializer.deserialize_any(SpicesVisitor(Mode::Lenient))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Recipe {
+    name: String,
+    spices: Spices,
+    #[serde(with = "spices_as_bits")]
+    spice_rack: Spices,
+    #[serde(default, deserialize_with = "lenient_spices")]
+    optional_spices: Spices,
+}
+
 fn main() {
     let classic = Spices::SALT | Spices::PEPPER;
     let spicy = Spices::PEPPER | Spices::CHILI;
@@ -65,4 +274,148 @@ fn main() {
 
     custom.clear();
     println!("Custom spice mix after clearing: {:?}", custom);
+
+    println!();
+
+    // Flags can be read from and written to text, e.g. in config files
+    let from_config: Spices = "salt | Chili".parse().expect("Failed to parse spices");
+    println!("Parsed spices: {}", from_config);
+    if let Err(e) = "SALT | CUMIN".parse::<Spices>() {
+        println!("Error: {}", e);
+    }
+    println!(
+        "Lenient: {}",
+        Spices::parse("SALT | CUMIN", Mode::Lenient).unwrap()
+    );
+
+    let recipe: Recipe = serde_json::from_str(
+        r#"{
+            "name": "Paella",
+            "spices": ["SAFFRON", "salt"],
+            "spice_rack": 15,
+            "optional_spices": "CHILI | SUMAC"
+        }"#,
+    )
+    .expect("Failed to read recipe");
+    println!(
+        "{}: {} (optional: {})",
+        recipe.name, recipe.spices, recipe.optional_spices
+    );
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&recipe).expect("Failed to write recipe")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(Ok(Spices::SALT | Spices::PEPPER), "SALT | PEPPER".parse());
+        assert_eq!(Ok(Spices::SALT | Spices::PEPPER), "pepper|Salt".parse());
+        assert_eq!(Ok(Spices::ALL), "all".parse());
+        assert_eq!(Ok(Spices::ALL), "ALL | SALT".parse());
+        assert_eq!(Ok(Spices::empty()), "".parse());
+        assert_eq!(Ok(Spices::empty()), " None ".parse());
+        assert_eq!(Ok(Spices::CHILI | Spices::SALT), "0x5".parse());
+
+        assert_eq!(
+            Err(ParseSpicesError::UnknownName("CUMIN".to_string())),
+            "SALT | CUMIN".parse::<Spices>()
+        );
+        assert_eq!(
+            Err(ParseSpicesError::UnknownName(String::new())),
+            "SALT |".parse::<Spices>()
+        );
+        assert_eq!(
+            Err(ParseSpicesError::UnknownBits(0x13)),
+            "0x13".parse::<Spices>()
+        );
+        assert_eq!(
+            "Unknown spice \"CUMIN\", expected one of SALT, PEPPER, CHILI, SAFFRON, ALL or NONE",
+            "CUMIN".parse::<Spices>().unwrap_err().to_string()
+        );
+        assert_eq!(
+            "The bits 0x10 don't belong to any spice",
+            "0x13".parse::<Spices>().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn parses_leniently() {
+        assert_eq!(
+            Ok(Spices::SALT),
+            Spices::parse("SALT | CUMIN | ", Mode::Lenient)
+        );
+        assert_eq!(
+            Ok(Spices::SALT | Spices::PEPPER),
+            Spices::parse("0x13", Mode::Lenient)
+        );
+    }
+
+    #[test]
+    fn names_every_spice_once() {
+        // A spice missing from the table would be printed as NONE
+        let union = NAMED_SPICES
+            .iter()
+            .fold(Spices::empty(), |union, &(_, spice)| union | spice);
+        assert_eq!(Spices::ALL, union);
+        for (index, &(_, spice)) in NAMED_SPICES.iter().enumerate() {
+            assert_eq!(1, spice.bits().count_ones());
+            assert!(NAMED_SPICES[..index]
+                .iter()
+                .all(|&(_, other)| !other.intersects(spice)));
+        }
+    }
+
+    #[test]
+    fn prints_stable_names() {
+        assert_eq!("SALT | CHILI", (Spices::CHILI | Spices::SALT).to_string());
+        assert_eq!("SALT | PEPPER | CHILI | SAFFRON", Spices::ALL.to_string());
+        assert_eq!("NONE", Spices::empty().to_string());
+        for bits in 0..16 {
+            let spices = Spices::from_bits(bits).unwrap();
+            assert_eq!(Ok(spices), spices.to_string().parse());
+        }
+    }
+
+    #[test]
+    fn serializes_names_or_bits() {
+        let recipe = Recipe {
+            name: "Chili".to_string(),
+            spices: Spices::SALT | Spices::CHILI,
+            spice_rack: Spices::ALL,
+            optional_spices: Spices::empty(),
+        };
+        let json = serde_json::to_string(&recipe).unwrap();
+        assert_eq!(
+            r#"{"name":"Chili","spices":["SALT","CHILI"],"spice_rack":15,"optional_spices":[]}"#,
+            json
+        );
+        let read: Recipe = serde_json::from_str(&json).unwrap();
+        assert_eq!(recipe.spices, read.spices);
+        assert_eq!(recipe.spice_rack, read.spice_rack);
+
+        let read: Recipe = serde_json::from_str(
+            r#"{"name":"Soup","spices":"salt | pepper","spice_rack":3,"optional_spices":["SUMAC", "CHILI"]}"#,
+        )
+        .unwrap();
+        assert_eq!(Spices::SALT | Spices::PEPPER, read.spices);
+        assert_eq!(Spices::SALT | Spices::PEPPER, read.spice_rack);
+        assert_eq!(Spices::CHILI, read.optional_spices);
+    }
+
+    #[test]
+    fn rejects_unknown_spices() {
+        let error =
+            serde_json::from_str::<Recipe>(r#"{"name":"Soup","spices":["SUMAC"],"spice_rack":0}"#)
+                .unwrap_err();
+        assert!(error.to_string().starts_with("Unknown spice \"SUMAC\""));
+        let error =
+            serde_json::from_str::<Recipe>(r#"{"name":"Soup","spices":[],"spice_rack":16}"#)
+                .unwrap_err();
+        assert!(error.to_string().starts_with("The bits 0x10 don't belong"));
+    }
 }
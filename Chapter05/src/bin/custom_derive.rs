@@ -21,8 +21,14 @@ struct Britain;
 #[hello_world_name = "the Land Down Under"]
 struct Australia;
 
+#[derive(HelloWorld)]
+// The attribute can also be written like a function call
+#[hello_world_name("the Land of the Rising Sun")]
+struct Japan;
+
 fn main() {
     Switzerland::hello_world();
     Britain::hello_world();
     Australia::hello_world();
+    Japan::hello_world();
 }
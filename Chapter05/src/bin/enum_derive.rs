@@ -0,0 +1,122 @@
+#[macro_use]
+extern crate chapter_five_derive;
+
+use std::slice;
+
+// Like HelloWorld, these traits have to be defined in the "consumer" crate.
+// FromStr and Display are the ones from the standard library
+trait EnumIter: Sized + 'static {
+    // All variants in the order they are declared in
+    const VARIANTS: &'static [Self];
+
+    fn iter() -> slice::Iter<'static, Self> {
+        Self::VARIANTS.iter()
+    }
+}
+
+trait EnumCount {
+    const COUNT: usize;
+}
+
+// Without the derives, printing and parsing
+// would both need a hand-written match
+#[derive(Debug, PartialEq, EnumIter, EnumCount, FromStr, Display)]
+#[variant(case_insensitive)]
+enum AllowedSpecies {
+    Dog,
+    #[variant(alias = "tortoise")]
+    Turtle,
+    #[variant(alias = "kitten", alias = "kitty")]
+    Cat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumIter, EnumCount, FromStr, Display)]
+enum CrustType {
+    #[variant(rename = "Nice and thin")]
+    Thin,
+    #[variant(rename = "Extra thick and extra filling", alias = "thick")]
+    Thick,
+}
+
+fn main() {
+    println!("We accept {} species:", AllowedSpecies::COUNT);
+    for species in AllowedSpecies::iter() {
+        println!("- {}", species);
+    }
+
+    for input in &["cat", "TORTOISE", "Kitty", "Bird"] {
+        match input.parse::<AllowedSpecies>() {
+            Ok(species) => println!("{} is a {:?}", input, species),
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    // Renamed variants are printed with their new name
+    let crust: CrustType = "thick".parse().expect("Failed to parse crust type");
+    println!("crust_type: {}", crust);
+    println!("Choose between: {:?}", CrustType::VARIANTS);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_and_counts_variants() {
+        let species: Vec<_> = AllowedSpecies::iter().collect();
+        assert_eq!(
+            vec![
+                &AllowedSpecies::Dog,
+                &AllowedSpecies::Turtle,
+                &AllowedSpecies::Cat
+            ],
+            species
+        );
+        assert_eq!(3, AllowedSpecies::COUNT);
+        assert_eq!(&[CrustType::Thin, CrustType::Thick], CrustType::VARIANTS);
+        assert_eq!(2, CrustType::COUNT);
+    }
+
+    #[test]
+    fn prints_names() {
+        assert_eq!("Turtle", AllowedSpecies::Turtle.to_string());
+        assert_eq!("Nice and thin", CrustType::Thin.to_string());
+        // Formatting options like padding are respected
+        assert_eq!("[Dog   ]", format!("[{:<6}]", AllowedSpecies::Dog));
+    }
+
+    #[test]
+    fn parses_names_and_aliases() {
+        assert_eq!(Ok(AllowedSpecies::Dog), "Dog".parse());
+        assert_eq!(Ok(AllowedSpecies::Dog), "dOG".parse());
+        assert_eq!(Ok(AllowedSpecies::Turtle), "Tortoise".parse());
+        assert_eq!(Ok(AllowedSpecies::Cat), "KITTEN".parse());
+        assert_eq!(
+            Ok(CrustType::Thick),
+            "Extra thick and extra filling".parse()
+        );
+        assert_eq!(Ok(CrustType::Thick), "thick".parse());
+
+        // Every variant can be parsed from what it prints
+        for crust in CrustType::iter() {
+            assert_eq!(Ok(*crust), crust.to_string().parse());
+        }
+    }
+
+    #[test]
+    fn lists_valid_names_on_errors() {
+        assert_eq!(
+            Err("Unknown AllowedSpecies \"Bird\", expected one of: Dog, Turtle, Cat".to_string()),
+            "Bird".parse::<AllowedSpecies>()
+        );
+        // Without case_insensitive, the case has to match
+        assert_eq!(
+            Err(
+                "Unknown CrustType \"THICK\", expected one of: Nice and thin, \
+                 Extra thick and extra filling"
+                    .to_string()
+            ),
+            "THICK".parse::<CrustType>()
+        );
+    }
+}